@@ -0,0 +1,40 @@
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::Path,
+    process,
+};
+
+/// Atomically replace the file at `path` with the given `contents`.
+///
+/// The contents are written to a temporary file in the same directory, which is then renamed
+/// over the original file. So, the file is either completely written or not touched at all,
+/// even if diener is killed in the middle of writing. The permissions of an existing file are
+/// preserved.
+pub(crate) fn write(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Path has no file name"))?;
+    let tmp = path.with_file_name(format!(
+        ".{}.diener-{}.tmp",
+        file_name.to_string_lossy(),
+        process::id()
+    ));
+
+    let result = (|| {
+        let mut file = File::create(&tmp)?;
+        file.write_all(contents.as_ref())?;
+        file.sync_all()?;
+
+        if let Ok(metadata) = fs::metadata(path) {
+            fs::set_permissions(&tmp, metadata.permissions())?;
+        }
+
+        fs::rename(&tmp, path)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
@@ -0,0 +1,107 @@
+use crate::report::Change;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::{
+    env,
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A file that was modified by a run.
+#[derive(Debug, Serialize)]
+pub(crate) struct File {
+    pub path: PathBuf,
+    pub changes: Vec<Change>,
+}
+
+/// The arguments whose values are secrets and must not end up in the audit log.
+const SECRET_ARGS: &[&str] = &["--github-token"];
+
+/// The record of a single run that is appended to the audit log.
+#[derive(Debug, Serialize)]
+struct Record<'a> {
+    /// Seconds since the unix epoch.
+    timestamp: u64,
+    args: Vec<String>,
+    files: &'a [File],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Append a record of the current run to the audit log at `path`.
+///
+/// Every record is a single JSON object on its own line.
+pub(crate) fn append(path: &Path, files: &[File], error: Option<&anyhow::Error>) -> Result<()> {
+    let record = Record {
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default(),
+        args: redact(env::args()),
+        files,
+        error: error.map(|e| format!("{:#}", e)),
+    };
+
+    let mut line = serde_json::to_string(&record)?;
+    line.push('\n');
+
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut f| f.write_all(line.as_bytes()))
+        .with_context(|| format!("Failed to append to audit log '{}'", path.display()))
+}
+
+/// Replace the values of the [`SECRET_ARGS`] in `args`.
+///
+/// Supports both `--arg value` and `--arg=value`.
+fn redact(args: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut redact_next = false;
+    args.into_iter()
+        .map(|arg| {
+            if std::mem::take(&mut redact_next) {
+                return "<redacted>".into();
+            }
+
+            match arg.split_once('=') {
+                Some((name, _)) if SECRET_ARGS.contains(&name) => format!("{}=<redacted>", name),
+                _ => {
+                    redact_next = SECRET_ARGS.contains(&arg.as_str());
+                    arg
+                }
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redact_tokens() {
+        let args = [
+            "diener",
+            "update",
+            "--github-token",
+            "secret1",
+            "--github-token=secret2",
+            "--substrate",
+        ];
+
+        assert_eq!(
+            redact(args.map(String::from)),
+            [
+                "diener",
+                "update",
+                "--github-token",
+                "<redacted>",
+                "--github-token=<redacted>",
+                "--substrate",
+            ]
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use anyhow::Result;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::{Duration, SystemTime},
+};
+
+/// The settings of the on-disk cache for network responses.
+#[derive(Debug, Clone)]
+pub(crate) struct Settings {
+    /// The cache directory, `None` disables the cache.
+    pub dir: Option<PathBuf>,
+    /// How long a cached response stays valid.
+    pub ttl: Duration,
+    /// Ignore the cached responses, but store the new ones.
+    pub refresh: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            ttl: Duration::from_secs(3600),
+            refresh: false,
+        }
+    }
+}
+
+/// The settings used for all cache lookups.
+static SETTINGS: OnceLock<Settings> = OnceLock::new();
+
+/// Set the settings that should be used for the cache.
+pub(crate) fn configure(settings: Settings) {
+    let _ = SETTINGS.set(settings);
+}
+
+/// Returns the default cache directory, `$XDG_CACHE_HOME/diener` or `~/.cache/diener`.
+pub(crate) fn default_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CACHE_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".cache")))
+        .map(|d| d.join("diener"))
+}
+
+/// Returns the path of the cache entry for the given `url`.
+///
+/// The file name is the 64 bit FNV-1a hash of the whole url, so urls that only differ in
+/// characters that are not valid in file names get different entries.
+fn entry_path(dir: &Path, url: &str) -> PathBuf {
+    let hash = url.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    dir.join(format!("{:016x}", hash))
+}
+
+/// Returns the cached response for `url` or calls `fetch` and caches its response.
+///
+/// Responses that are `None` (e.g. `404 Not Found`) are not cached.
+pub(crate) fn get_or_fetch(
+    url: &str,
+    fetch: impl FnOnce() -> Result<Option<String>>,
+) -> Result<Option<String>> {
+    let settings = SETTINGS.get_or_init(Default::default);
+    let path = match &settings.dir {
+        Some(dir) => entry_path(dir, url),
+        None => return fetch(),
+    };
+
+    let is_fresh = fs::metadata(&path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|m| SystemTime::now().duration_since(m).ok())
+        .is_some_and(|age| age < settings.ttl);
+    if is_fresh && !settings.refresh {
+        if let Ok(content) = fs::read_to_string(&path) {
+            log::debug!("Using cached response for {}", url);
+            return Ok(Some(content));
+        }
+    }
+
+    let content = fetch()?;
+    if let Some(content) = &content {
+        let written = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&path, content));
+        if let Err(e) = written {
+            log::debug!("Failed to cache the response for {}: {}", url, e);
+        }
+    }
+
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entry_path_is_unique_per_url() {
+        let dir = Path::new("/cache");
+        let urls = [
+            "https://crates.io/api/v1/crates/sp-io",
+            "https://crates.io/api/v1/crates/sp_io",
+            "https://crates.io/api/v1/crates_sp-io",
+            "http://crates.io/api/v1/crates/sp-io",
+        ];
+
+        let paths = urls
+            .iter()
+            .map(|u| entry_path(dir, u))
+            .collect::<std::collections::BTreeSet<_>>();
+        assert_eq!(paths.len(), urls.len());
+        assert_eq!(entry_path(dir, urls[0]), entry_path(dir, urls[0]));
+    }
+}
@@ -0,0 +1,277 @@
+use crate::{
+    lockfile::LockFile,
+    logging::{self, output},
+    report,
+    repos::{split_reference, MatchOptions, Matcher, Reference, Repo},
+    update::manifests,
+};
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use git_url_parse::GitUrl;
+use globset::GlobSet;
+use std::{
+    env::current_dir,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use structopt::StructOpt;
+use toml_edit::Document;
+
+/// `check-dependent` subcommand options.
+#[derive(Debug, StructOpt)]
+pub struct CheckDependent {
+    /// The path where Diener should search for `Cargo.toml` files.
+    ///
+    /// Uses the working directory if none is supplied.
+    #[structopt(long)]
+    path: Option<PathBuf>,
+
+    /// The repository the project should depend on.
+    #[structopt(long, possible_values = &[ "substrate", "polkadot", "cumulus", "beefy" ])]
+    dependency: String,
+
+    /// The `branch` the dependencies should use.
+    #[structopt(
+        long,
+        conflicts_with_all = &[ "rev", "tag" ],
+        required_unless_one = &[ "rev", "tag" ]
+    )]
+    branch: Option<String>,
+
+    /// The `rev` the dependencies should use.
+    ///
+    /// Abbreviated commit hashes are accepted.
+    #[structopt(long, conflicts_with_all = &[ "branch", "tag" ])]
+    rev: Option<String>,
+
+    /// The `tag` the dependencies should use.
+    #[structopt(long, conflicts_with_all = &[ "branch", "rev" ])]
+    tag: Option<String>,
+
+    #[structopt(flatten)]
+    match_options: MatchOptions,
+}
+
+/// A dependency that doesn't use the expected version.
+struct Mismatch {
+    /// The manifest or `Cargo.lock` that declares the dependency.
+    file: PathBuf,
+    /// The line of the dependency in `file`, starting at 1.
+    line: Option<usize>,
+    message: String,
+}
+
+impl Mismatch {
+    /// Print the mismatch, relative to `path`.
+    ///
+    /// In GitHub Actions, an annotation pointing at the dependency is printed as well.
+    fn print(&self, path: &Path) {
+        let relative = self.file.strip_prefix(path).unwrap_or(&self.file);
+        if logging::is_json() {
+            log::error!(
+                manifest:% = self.file.display(),
+                line = self.line;
+                "{}: {}", relative.display(), self.message
+            );
+        } else {
+            println!("  {}: {}", relative.display(), self.message);
+        }
+
+        if report::in_github_actions() {
+            report::github_error(&self.file, self.line, &self.message);
+        }
+    }
+}
+
+impl CheckDependent {
+    /// Run this subcommand.
+    pub fn run(self) -> Result<()> {
+        let path = self
+            .path
+            .map(Ok)
+            .unwrap_or_else(|| current_dir().with_context(|| "Working directory is invalid."))?;
+        ensure!(
+            path.is_dir(),
+            "Path '{}' is not a directory.",
+            path.display()
+        );
+
+        let repo = Repo::from_config_name(&self.dependency)
+            .ok_or_else(|| anyhow!("Unknown repository `{}`.", self.dependency))?;
+        let expected: Reference = match (self.branch, self.rev, self.tag) {
+            (Some(branch), _, _) => ("branch", branch),
+            (_, Some(rev), _) => ("rev", rev),
+            (_, _, Some(tag)) => ("tag", tag),
+            _ => bail!("You need to pass `--branch`, `--rev` or `--tag`."),
+        };
+        let matcher = Matcher::new(self.match_options)?;
+
+        let mut checked = 0;
+        let mut mismatches = Vec::new();
+        for manifest in manifests(&path, &GlobSet::empty()) {
+            let content = fs::read_to_string(&manifest)?;
+            let doc = Document::from_str(&content)
+                .with_context(|| anyhow!("Failed to parse {}", manifest.display()))?;
+
+            for (name, dep) in doc
+                .iter()
+                .filter(|(k, _)| k.contains("dependencies"))
+                .filter_map(|(_, v)| v.as_table())
+                .flat_map(|t| t.iter())
+                .filter_map(|(name, dep)| dep.as_table_like().map(|d| (name, d)))
+            {
+                let (url, embedded) = match dep.get("git").and_then(|g| g.as_str()) {
+                    Some(git) => split_reference(git),
+                    None => continue,
+                };
+                if GitUrl::parse(url).ok().and_then(|g| matcher.classify(&g)) != Some(repo) {
+                    continue;
+                }
+
+                let reference = ["branch", "tag", "rev"]
+                    .into_iter()
+                    .find_map(|k| dep.get(k).and_then(|v| v.as_str()).map(|v| (k, v.into())))
+                    .or(embedded);
+                checked += 1;
+
+                let matches = reference.as_ref().is_some_and(|(key, value)| {
+                    *key == expected.0 && same_reference(key, value, &expected.1)
+                });
+                if !matches {
+                    let actual = reference.as_ref().map(|(k, v)| (*k, v.as_str()));
+                    mismatches.push(Mismatch {
+                        line: dependency_line(&content, name),
+                        message: format!("`{}` uses {}", name, report::git_source(url, actual)),
+                        file: manifest.clone(),
+                    });
+                }
+            }
+        }
+
+        match LockFile::find(&path) {
+            Some(lock_file) => {
+                let content = fs::read_to_string(&lock_file)?;
+                for package in LockFile::read(&lock_file)?.packages {
+                    let source = match package.git_source() {
+                        Some(source) => source,
+                        None => continue,
+                    };
+                    if GitUrl::parse(&source.url)
+                        .ok()
+                        .and_then(|g| matcher.classify(&g))
+                        != Some(repo)
+                    {
+                        continue;
+                    }
+                    checked += 1;
+
+                    let matches = if expected.0 == "rev" {
+                        same_reference("rev", &source.commit, &expected.1)
+                    } else {
+                        source
+                            .reference
+                            .as_ref()
+                            .is_some_and(|(k, v)| k == expected.0 && *v == expected.1)
+                    };
+                    if !matches {
+                        let name_line = format!("name = \"{}\"", package.name);
+                        mismatches.push(Mismatch {
+                            file: lock_file.clone(),
+                            line: content.lines().position(|l| l == name_line).map(|l| l + 1),
+                            message: format!(
+                                "`{}` is locked to {}#{}",
+                                package.name,
+                                report::git_source(
+                                    &source.url,
+                                    source
+                                        .reference
+                                        .as_ref()
+                                        .map(|(k, v)| (k.as_str(), v.as_str()))
+                                ),
+                                source.commit
+                            ),
+                        });
+                    }
+                }
+            }
+            None => log::warn!("No `Cargo.lock` found, only checking the manifests."),
+        }
+
+        let expected = format!("{}={}", expected.0, expected.1);
+        ensure!(
+            checked > 0,
+            "The project doesn't depend on {}.",
+            repo.name()
+        );
+
+        if !mismatches.is_empty() {
+            output!(
+                "Expected all dependencies on {} to use {}:",
+                repo.name(),
+                expected
+            );
+            mismatches.iter().for_each(|m| m.print(&path));
+            bail!(
+                "{} of {} dependencies on {} don't use {}.",
+                mismatches.len(),
+                checked,
+                repo.name(),
+                expected
+            );
+        }
+
+        output!(
+            "All {} dependencies on {} use {}.",
+            checked,
+            repo.name(),
+            expected
+        );
+        Ok(())
+    }
+}
+
+/// Checks if the `actual` value of the reference `key` is the `expected` one.
+///
+/// Revisions also match if one is an abbreviation of the other.
+fn same_reference(key: &str, actual: &str, expected: &str) -> bool {
+    if key == "rev" && !actual.is_empty() && !expected.is_empty() {
+        actual.starts_with(expected) || expected.starts_with(actual)
+    } else {
+        actual == expected
+    }
+}
+
+/// Returns the line (starting at 1) that declares the dependency `name` in the manifest
+/// `content`.
+///
+/// `toml_edit` doesn't expose the position of the parsed items, so the line is searched in the
+/// text: either a `name = ...` key in a dependencies table or a `[dependencies.name]` header.
+fn dependency_line(content: &str, name: &str) -> Option<usize> {
+    let mut in_dependencies = false;
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if let Some(header) = line.strip_prefix('[') {
+            let header = header.trim_end_matches(']');
+            if header.contains("dependencies.") {
+                if unquote(header.rsplit('.').next().unwrap_or_default()) == name {
+                    return Some(number + 1);
+                }
+                in_dependencies = false;
+            } else {
+                in_dependencies = header.contains("dependencies");
+            }
+        } else if in_dependencies {
+            let key = line.split(['=', '.']).next().unwrap_or_default();
+            if unquote(key) == name {
+                return Some(number + 1);
+            }
+        }
+    }
+
+    None
+}
+
+/// Removes the whitespace and quotes around a key.
+fn unquote(key: &str) -> &str {
+    key.trim().trim_matches(|c| c == '"' || c == '\'')
+}
@@ -0,0 +1,131 @@
+use crate::{
+    lockfile::LockFile,
+    repos::{same_repository, Repo},
+    update::{Rewrite, Version},
+};
+use anyhow::{anyhow, Result};
+use git_url_parse::GitUrl;
+
+/// A repository selected for the update together with the version it is switched to.
+struct Selected {
+    repo: Repo,
+    git: GitUrl,
+    reference: (&'static str, String),
+}
+
+/// Checks that the selected versions of the repositories fit together.
+///
+/// For every selected repository the `Cargo.lock` at the selected version is fetched and a
+/// warning is printed for every other selected repository that it pins to a different
+/// branch/tag/rev. Returns the number of mismatches.
+pub(crate) fn check(targets: &[(Rewrite, Version)]) -> Result<usize> {
+    let selected = targets
+        .iter()
+        .flat_map(|(rewrite, version)| {
+            rewrite
+                .repositories()
+                .into_iter()
+                .map(move |(repo, git)| (repo, git, version))
+        })
+        .filter_map(|(repo, git, version)| {
+            let reference = version.reference()?;
+            let git = git.unwrap_or_else(|| repo.url());
+            Some(
+                GitUrl::parse(&git)
+                    .map(|git| Selected {
+                        repo,
+                        git,
+                        reference: (reference.0, reference.1.into()),
+                    })
+                    .map_err(|e| anyhow!("Invalid git url `{}`: {}", git, e)),
+            )
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    if selected.len() < 2 {
+        log::info!("`--check-compat` requires at least two repositories with a version.");
+        return Ok(0);
+    }
+
+    let mut mismatches = 0;
+    for lock_owner in &selected {
+        let lock_file = match fetch_lock_file(lock_owner) {
+            Ok(lock_file) => lock_file,
+            Err(e) => {
+                log::warn!(
+                    "Skipping compatibility check for {}: {:#}",
+                    lock_owner.repo.name(),
+                    e
+                );
+                continue;
+            }
+        };
+
+        for other in selected.iter().filter(|s| s.repo != lock_owner.repo) {
+            mismatches += check_pins(lock_owner, other, &lock_file);
+        }
+    }
+
+    Ok(mismatches)
+}
+
+/// Warn about every package of `other` that `lock_file` pins to a different version.
+///
+/// Every distinct pin is reported only once. Returns the number of reported pins.
+fn check_pins(lock_owner: &Selected, other: &Selected, lock_file: &LockFile) -> usize {
+    let (key, value) = (other.reference.0, other.reference.1.as_str());
+    let mut pins = lock_file
+        .packages
+        .iter()
+        .filter_map(|p| p.git_source())
+        .filter(|s| GitUrl::parse(&s.url).is_ok_and(|url| same_repository(&url, &other.git)))
+        .filter(|s| {
+            let same_reference = s
+                .reference
+                .as_ref()
+                .is_some_and(|(k, v)| k == key && v == value);
+            let same_commit = key == "rev" && s.commit.starts_with(value);
+            !same_reference && !same_commit
+        })
+        .map(|s| {
+            let reference = s.reference.map_or_else(
+                || "default branch".into(),
+                |(k, v)| format!("{} `{}`", k, v),
+            );
+            format!("{} (rev {})", reference, s.commit)
+        })
+        .collect::<Vec<_>>();
+    pins.sort();
+    pins.dedup();
+
+    for pin in &pins {
+        log::warn!(
+            "{} at {} `{}` pins {} to {}, but {} `{}` was selected.",
+            lock_owner.repo.name(),
+            lock_owner.reference.0,
+            lock_owner.reference.1,
+            other.repo.name(),
+            pin,
+            key,
+            value,
+        );
+    }
+
+    pins.len()
+}
+
+/// Fetch the `Cargo.lock` of the given repository at the selected version.
+///
+/// Only repositories hosted on GitHub are supported.
+fn fetch_lock_file(selected: &Selected) -> Result<LockFile> {
+    let git = &selected.git;
+    let path = git.path.trim_matches('/').trim_end_matches(".git");
+    if git.host.as_deref() != Some("github.com") {
+        return Err(anyhow!("Only repositories on GitHub are supported."));
+    }
+
+    LockFile::load(&format!(
+        "https://raw.githubusercontent.com/{}/{}/Cargo.lock",
+        path, selected.reference.1
+    ))
+}
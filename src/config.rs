@@ -0,0 +1,403 @@
+use crate::{
+    formatting::Formatting,
+    repos::{ClassifyRule, Repo},
+    update::Version,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use toml_edit::{Document, Item, TableLike};
+
+/// The name of the config file that is searched in the root of the `--path`.
+pub(crate) const CONFIG_FILE_NAME: &str = "diener.toml";
+
+/// The settings for one repository inside a profile.
+#[derive(Debug, Clone)]
+pub(crate) struct RepoSettings {
+    pub git: Option<String>,
+    pub version: Version,
+}
+
+/// A named set of repository settings, `[profile.NAME]` in the config file.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Profile {
+    pub repos: Vec<(Repo, RepoSettings)>,
+}
+
+/// The diener config file.
+///
+/// ```toml
+/// [profile.release-v1]
+/// substrate = { branch = "polkadot-v0.9.43" }
+/// polkadot = { branch = "release-v0.9.43", git = "https://github.com/myorg/polkadot" }
+///
+/// [rename]
+/// beefy-primitives = "sp-consensus-beefy"
+///
+/// [override]
+/// sp-io = { rev = "abc123" }
+///
+/// [match]
+/// substrate = ["https://git.mycorp.io/chain/substrate", "https://git.mycorp.io/mirrors/sdk"]
+///
+/// [[classify]]
+/// pattern = "^github\\.com/myorg/(polkadot-)?sdk$"
+/// repo = "polkadot"
+///
+/// [workspace-package]
+/// rust-version = "1.75"
+///
+/// [format]
+/// compact-inline-tables = true
+/// ```
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Config {
+    pub profiles: BTreeMap<String, Profile>,
+    /// Crates that were renamed upstream, old name -> new name.
+    pub renames: BTreeMap<String, String>,
+    /// Crates that should use a different source than the other dependencies.
+    pub overrides: Overrides,
+    /// Git url prefixes that belong to a repository, independent of the repository name.
+    pub url_rules: Vec<(Repo, String)>,
+    /// Regex rules that classify the git urls, checked in order before the url prefixes.
+    pub classify_rules: Vec<ClassifyRule>,
+    /// The fields of `[workspace.package]` that are set, field -> value.
+    pub workspace_package: BTreeMap<String, String>,
+    /// How the entries inserted into the dependencies are formatted.
+    pub formatting: Formatting,
+}
+
+/// The sources of specific crates, crate name -> source.
+pub(crate) type Overrides = BTreeMap<String, RepoSettings>;
+
+impl FromStr for Config {
+    type Err = anyhow::Error;
+
+    fn from_str(content: &str) -> Result<Self> {
+        let doc = Document::from_str(content)?;
+        let mut config = Config::default();
+
+        if let Some(profiles) = doc.get("profile") {
+            let profiles = profiles
+                .as_table_like()
+                .ok_or_else(|| anyhow!("`profile` is not a table"))?;
+
+            for (name, profile) in profiles.iter() {
+                let profile = profile
+                    .as_table_like()
+                    .ok_or_else(|| anyhow!("`profile.{}` is not a table", name))?;
+                let profile = parse_profile(profile)
+                    .with_context(|| anyhow!("Invalid profile `{}`", name))?;
+                config.profiles.insert(name.into(), profile);
+            }
+        }
+
+        if let Some(renames) = doc.get("rename") {
+            let renames = renames
+                .as_table_like()
+                .ok_or_else(|| anyhow!("`rename` is not a table"))?;
+
+            for (old, new) in renames.iter() {
+                let new = new
+                    .as_str()
+                    .ok_or_else(|| anyhow!("`rename.{}` is not a string", old))?;
+                config.renames.insert(old.into(), new.into());
+            }
+        }
+
+        if let Some(overrides) = doc.get("override") {
+            let overrides = overrides
+                .as_table_like()
+                .ok_or_else(|| anyhow!("`override` is not a table"))?;
+            config.overrides = parse_overrides(overrides)?;
+        }
+
+        if let Some(rules) = doc.get("match") {
+            let rules = rules
+                .as_table_like()
+                .ok_or_else(|| anyhow!("`match` is not a table"))?;
+
+            for (name, prefixes) in rules.iter() {
+                let repo = Repo::from_config_name(name)
+                    .ok_or_else(|| anyhow!("Unknown repository `{}`", name))?;
+                let prefixes = match prefixes.as_array() {
+                    Some(prefixes) => prefixes.iter().map(|p| p.as_str()).collect(),
+                    None => vec![prefixes.as_str()],
+                };
+
+                for prefix in prefixes {
+                    let prefix = prefix.ok_or_else(|| {
+                        anyhow!("`match.{}` is not a string or an array of strings", name)
+                    })?;
+                    config.url_rules.push((repo, prefix.into()));
+                }
+            }
+        }
+
+        if let Some(rules) = doc.get("classify") {
+            let rules = rules
+                .as_array_of_tables()
+                .ok_or_else(|| anyhow!("`classify` is not an array of tables"))?;
+
+            for (index, rule) in rules.iter().enumerate() {
+                config.classify_rules.push(
+                    parse_classify_rule(rule)
+                        .with_context(|| anyhow!("Invalid `classify` rule {}", index + 1))?,
+                );
+            }
+        }
+
+        if let Some(fields) = doc.get("workspace-package") {
+            let fields = fields
+                .as_table_like()
+                .ok_or_else(|| anyhow!("`workspace-package` is not a table"))?;
+
+            for (field, value) in fields.iter() {
+                let value = value
+                    .as_str()
+                    .ok_or_else(|| anyhow!("`workspace-package.{}` is not a string", field))?;
+                check_workspace_package_field(field, value)?;
+                config.workspace_package.insert(field.into(), value.into());
+            }
+        }
+
+        if let Some(formatting) = doc.get("format") {
+            let formatting = formatting
+                .as_table_like()
+                .ok_or_else(|| anyhow!("`format` is not a table"))?;
+            config.formatting = Formatting::parse(formatting)?;
+        }
+
+        Ok(config)
+    }
+}
+
+impl Config {
+    /// Read the config file at the given `path`.
+    pub(crate) fn read(path: &Path) -> Result<Self> {
+        fs::read_to_string(path)
+            .with_context(|| anyhow!("Failed to read config file {}", path.display()))?
+            .parse()
+            .with_context(|| anyhow!("Failed to parse config file {}", path.display()))
+    }
+
+    /// Load the config from `config`, or from [`CONFIG_FILE_NAME`] in `dir` if it exists.
+    pub(crate) fn load(config: Option<&Path>, dir: &Path) -> Result<Self> {
+        match config {
+            Some(config) => Self::read(config),
+            None => {
+                let default: PathBuf = dir.join(CONFIG_FILE_NAME);
+                if default.is_file() {
+                    Self::read(&default)
+                } else {
+                    Ok(Self::default())
+                }
+            }
+        }
+    }
+
+    /// Returns the profile with the given `name`.
+    pub(crate) fn profile(&self, name: &str) -> Result<&Profile> {
+        self.profiles.get(name).ok_or_else(|| {
+            anyhow!(
+                "Unknown profile `{}`, available profiles: {:?}",
+                name,
+                self.profiles.keys().collect::<Vec<_>>()
+            )
+        })
+    }
+}
+
+/// Read the overrides file at the given `path`.
+///
+/// The file maps crate names to their source, e.g. `sp-io = { rev = "abc123" }`.
+pub(crate) fn read_overrides(path: &Path) -> Result<Overrides> {
+    let content = fs::read_to_string(path)
+        .with_context(|| anyhow!("Failed to read overrides file {}", path.display()))?;
+    Document::from_str(&content)
+        .map_err(Into::into)
+        .and_then(|doc| parse_overrides(doc.as_table()))
+        .with_context(|| anyhow!("Failed to parse overrides file {}", path.display()))
+}
+
+fn parse_overrides(overrides: &dyn TableLike) -> Result<Overrides> {
+    overrides
+        .iter()
+        .map(|(name, settings)| Ok((name.to_string(), parse_settings(name, settings)?)))
+        .collect()
+}
+
+fn parse_profile(profile: &dyn TableLike) -> Result<Profile> {
+    let mut repos = Vec::new();
+
+    for (name, settings) in profile.iter() {
+        let repo =
+            Repo::from_config_name(name).ok_or_else(|| anyhow!("Unknown repository `{}`", name))?;
+        repos.push((repo, parse_settings(name, settings)?));
+    }
+
+    Ok(Profile { repos })
+}
+
+/// Parse a `[[classify]]` rule, `repo = "none"` excludes the matching urls.
+fn parse_classify_rule(rule: &dyn TableLike) -> Result<ClassifyRule> {
+    let get = |key| {
+        rule.get(key)
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("`{}` is missing or not a string", key))
+    };
+
+    let repo = match get("repo")? {
+        "none" => None,
+        name => Some(
+            Repo::from_config_name(name).ok_or_else(|| anyhow!("Unknown repository `{}`", name))?,
+        ),
+    };
+    ClassifyRule::new(get("pattern")?, repo)
+}
+
+/// Check the `value` of the `[workspace.package]` `field`.
+///
+/// Only the fields that are versions are checked, the others are written as given.
+pub(crate) fn check_workspace_package_field(field: &str, value: &str) -> Result<()> {
+    match field {
+        "version" => {
+            semver::Version::parse(value)
+                .with_context(|| anyhow!("Invalid `version` `{}`", value))?;
+        }
+        "rust-version" => {
+            let parts = value.split('.').collect::<Vec<_>>();
+            if !(2..=3).contains(&parts.len()) || parts.iter().any(|p| p.parse::<u64>().is_err()) {
+                bail!(
+                    "Invalid `rust-version` `{}`, expected e.g. `1.75` or `1.75.0`",
+                    value
+                );
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Parse the `branch`/`tag`/`rev` and `git` of the entry `name`.
+fn parse_settings(name: &str, settings: &Item) -> Result<RepoSettings> {
+    let settings = settings
+        .as_table_like()
+        .ok_or_else(|| anyhow!("`{}` is not a table", name))?;
+    let get = |key| settings.get(key).and_then(|v| v.as_str()).map(String::from);
+
+    let version = match (get("branch"), get("tag"), get("rev")) {
+        (Some(branch), None, None) => Version::Branch(branch),
+        (None, Some(tag), None) => Version::Tag(tag),
+        (None, None, Some(rev)) => Version::Rev(rev),
+        _ => bail!(
+            "`{}` requires exactly one of `branch`, `tag` or `rev`",
+            name
+        ),
+    };
+
+    Ok(RepoSettings {
+        git: get("git"),
+        version,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_config() {
+        let config = Config::from_str(
+            r#"
+            [profile.release-v1]
+            substrate = { branch = "polkadot-v0.9.43" }
+            polkadot = { tag = "v0.9.43", git = "https://github.com/myorg/polkadot" }
+
+            [rename]
+            beefy-primitives = "sp-consensus-beefy"
+
+            [override]
+            sp-io = { rev = "abc123" }
+
+            [match]
+            substrate = ["https://git.mycorp.io/chain/substrate", "https://git.mycorp.io/mirrors/sdk"]
+            cumulus = "https://git.mycorp.io/chain/cumulus"
+
+            [[classify]]
+            pattern = "^github\\.com/myorg/(polkadot-)?sdk$"
+            repo = "polkadot"
+
+            [[classify]]
+            pattern = "/substrate-archive$"
+            repo = "none"
+
+            [workspace-package]
+            rust-version = "1.75"
+            version = "1.2.0"
+
+            [format]
+            compact-entries = true
+            "#,
+        )
+        .unwrap();
+
+        let profile = config.profile("release-v1").unwrap();
+        assert_eq!(profile.repos.len(), 2);
+        assert!(matches!(
+            &profile.repos[0],
+            (Repo::Substrate, RepoSettings { git: None, version: Version::Branch(b) })
+                if b == "polkadot-v0.9.43"
+        ));
+        assert!(matches!(
+            &profile.repos[1],
+            (Repo::Polkadot, RepoSettings { git: Some(g), version: Version::Tag(t) })
+                if g == "https://github.com/myorg/polkadot" && t == "v0.9.43"
+        ));
+        assert!(config.profile("unknown").is_err());
+
+        assert_eq!(config.renames["beefy-primitives"], "sp-consensus-beefy");
+        assert!(matches!(&config.overrides["sp-io"].version, Version::Rev(r) if r == "abc123"));
+        assert_eq!(
+            config.url_rules,
+            vec![
+                (
+                    Repo::Substrate,
+                    "https://git.mycorp.io/chain/substrate".into()
+                ),
+                (Repo::Substrate, "https://git.mycorp.io/mirrors/sdk".into()),
+                (Repo::Cumulus, "https://git.mycorp.io/chain/cumulus".into()),
+            ]
+        );
+        assert_eq!(config.classify_rules.len(), 2);
+        assert_eq!(config.workspace_package["rust-version"], "1.75");
+        assert_eq!(config.workspace_package["version"], "1.2.0");
+        assert_eq!(config.formatting.compact_entries, Some(true));
+        assert_eq!(config.formatting.compact_inline_tables, None);
+    }
+
+    #[test]
+    fn reject_invalid_config() {
+        for content in [
+            "profile = 1",
+            "[profile.a]\nunknown = { branch = \"master\" }",
+            "[profile.a]\nsubstrate = { branch = \"master\", tag = \"v1\" }",
+            "[profile.a]\nsubstrate = { git = \"https://github.com/myorg/substrate\" }",
+            "[rename]\nfoo = 1",
+            "[match]\nsubstrate = [1]",
+            "[[classify]]\npattern = \"(\"\nrepo = \"substrate\"",
+            "[[classify]]\npattern = \"sdk\"\nrepo = \"unknown\"",
+            "[[classify]]\nrepo = \"substrate\"",
+            "[workspace-package]\nrust-version = \"1.x\"",
+            "[workspace-package]\nversion = \"1.2\"",
+            "[format]\ncompact-entries = \"yes\"",
+        ] {
+            assert!(Config::from_str(content).is_err(), "{}", content);
+        }
+    }
+}
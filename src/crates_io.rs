@@ -0,0 +1,83 @@
+use crate::http;
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Information about a crate published on `crates.io`.
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct Crate {
+    /// The highest published version, including pre-releases.
+    pub max_version: semver::Version,
+    /// The highest published version that isn't a pre-release.
+    pub max_stable_version: Option<semver::Version>,
+    /// The repository url given in the crate manifest.
+    pub repository: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CrateResponse {
+    #[serde(rename = "crate")]
+    krate: Crate,
+}
+
+/// Fetch the information about the crate with the given `name` from `crates.io`.
+///
+/// Returns `None` if the crate isn't published.
+pub(crate) fn get_crate(name: &str) -> Result<Option<Crate>> {
+    http::get_json_cached::<CrateResponse>(&format!("https://crates.io/api/v1/crates/{}", name))
+        .with_context(|| anyhow!("Failed to get `{}` from crates.io", name))
+        .map(|r| r.map(|r| r.krate))
+}
+
+#[derive(Deserialize)]
+struct VersionsResponse {
+    versions: Vec<CrateVersion>,
+}
+
+#[derive(Deserialize)]
+struct CrateVersion {
+    num: semver::Version,
+    yanked: bool,
+}
+
+/// Fetch all versions of the crate with the given `name` that are not yanked.
+///
+/// Returns `None` if the crate isn't published.
+pub(crate) fn get_versions(name: &str) -> Result<Option<Vec<semver::Version>>> {
+    http::get_json_cached::<VersionsResponse>(&format!(
+        "https://crates.io/api/v1/crates/{}/versions",
+        name
+    ))
+    .with_context(|| anyhow!("Failed to get the versions of `{}` from crates.io", name))
+    .map(|r| {
+        r.map(|r| {
+            r.versions
+                .into_iter()
+                .filter(|v| !v.yanked)
+                .map(|v| v.num)
+                .collect()
+        })
+    })
+}
+
+#[derive(Deserialize)]
+struct VersionResponse {
+    version: VersionFeatures,
+}
+
+#[derive(Deserialize)]
+struct VersionFeatures {
+    features: BTreeMap<String, Vec<String>>,
+}
+
+/// Fetch the names of the features of the given `version` of the crate `name`.
+///
+/// Returns `None` if the version isn't published.
+pub(crate) fn get_features(name: &str, version: &str) -> Result<Option<BTreeSet<String>>> {
+    http::get_json_cached::<VersionResponse>(&format!(
+        "https://crates.io/api/v1/crates/{}/{}",
+        name, version
+    ))
+    .with_context(|| anyhow!("Failed to get the features of `{}` {}", name, version))
+    .map(|r| r.map(|r| r.version.features.into_keys().collect()))
+}
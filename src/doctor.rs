@@ -0,0 +1,146 @@
+use crate::{crates_io, http, logging::output, update::manifests};
+use anyhow::{anyhow, bail, Context, Result};
+use globset::GlobSet;
+use serde::Deserialize;
+use std::{
+    env::current_dir,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+};
+use structopt::StructOpt;
+use toml_edit::Document;
+
+/// A check returning some information about the checked part on success.
+type Check<'a> = Box<dyn Fn() -> Result<String> + 'a>;
+
+/// `doctor` subcommand options.
+#[derive(Debug, StructOpt)]
+pub struct Doctor {
+    /// The path where Diener should search for `Cargo.toml` files.
+    #[structopt(long)]
+    path: Option<PathBuf>,
+}
+
+impl Doctor {
+    /// Run this subcommand.
+    pub fn run(self) -> Result<()> {
+        let path = self
+            .path
+            .map(Ok)
+            .unwrap_or_else(|| current_dir().with_context(|| "Working directory is invalid."))?;
+
+        let checks: [(&str, Check); 5] = [
+            ("git", Box::new(check_git)),
+            ("crates.io", Box::new(check_crates_io)),
+            ("GitHub", Box::new(check_github)),
+            ("workspace", Box::new(|| check_workspace(&path))),
+            ("manifests", Box::new(|| check_manifests(&path))),
+        ];
+
+        let mut failed = 0;
+        for (name, check) in &checks {
+            match check() {
+                Ok(info) => output!("[ok]    {}: {}", name, info),
+                Err(e) => {
+                    failed += 1;
+                    output!("[error] {}: {:#}", name, e);
+                }
+            }
+        }
+
+        if failed > 0 {
+            bail!("{} of {} checks failed.", failed, checks.len());
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks that `git` is installed.
+fn check_git() -> Result<String> {
+    let output = Command::new("git")
+        .arg("--version")
+        .output()
+        .with_context(|| "Failed to run `git`, is it installed?")?;
+    Ok(String::from_utf8_lossy(&output.stdout).trim().into())
+}
+
+/// Checks that `crates.io` is reachable.
+fn check_crates_io() -> Result<String> {
+    crates_io::get_crate(env!("CARGO_PKG_NAME"))?;
+    Ok("reachable".into())
+}
+
+/// Checks that GitHub is reachable and the token, if one was given, is valid.
+fn check_github() -> Result<String> {
+    #[derive(Deserialize)]
+    struct RateLimit {
+        rate: Rate,
+    }
+
+    #[derive(Deserialize)]
+    struct Rate {
+        limit: u64,
+        remaining: u64,
+    }
+
+    let rate = http::github_api::<RateLimit>("rate_limit")
+        .context("GitHub is not reachable or the token is invalid")?
+        .ok_or_else(|| anyhow!("Failed to get the GitHub rate limit"))?
+        .rate;
+    let token = if http::has_github_token() {
+        "token is valid"
+    } else {
+        "no token"
+    };
+
+    Ok(format!(
+        "reachable, {}, {}/{} requests remaining",
+        token, rate.remaining, rate.limit
+    ))
+}
+
+/// Checks that `path` belongs to a cargo workspace.
+fn check_workspace(path: &Path) -> Result<String> {
+    let metadata = cargo_metadata::MetadataCommand::new()
+        .current_dir(path)
+        .no_deps()
+        .exec()
+        .with_context(|| anyhow!("{} is not part of a cargo workspace", path.display()))?;
+
+    Ok(format!(
+        "{} with {} member(s)",
+        metadata.workspace_root,
+        metadata.workspace_members.len()
+    ))
+}
+
+/// Checks that all manifests at `path` can be parsed.
+fn check_manifests(path: &Path) -> Result<String> {
+    let mut count = 0;
+    let mut errors = Vec::new();
+
+    for manifest in manifests(path, &GlobSet::empty()) {
+        count += 1;
+        if let Err(e) = fs::read_to_string(&manifest)
+            .map_err(anyhow::Error::from)
+            .and_then(|c| Document::from_str(&c).map_err(Into::into))
+        {
+            let e = e.to_string().trim().replace('\n', "\n    ");
+            errors.push(format!("{}: {}", manifest.display(), e));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(format!("{} manifest(s) parsed", count))
+    } else {
+        bail!(
+            "{} of {} manifest(s) failed to parse:\n  {}",
+            errors.len(),
+            count,
+            errors.join("\n  ")
+        )
+    }
+}
@@ -0,0 +1,71 @@
+use std::fmt;
+
+/// The run was successful.
+pub(crate) const SUCCESS: i32 = 0;
+/// The run failed.
+pub(crate) const FAILURE: i32 = 1;
+/// No dependency matched the requested rewrite.
+pub(crate) const NOTHING_MATCHED: i32 = 2;
+/// The run failed after some manifests were already modified.
+pub(crate) const PARTIAL_FAILURE: i32 = 3;
+/// The run failed because of a network error.
+pub(crate) const NETWORK_ERROR: i32 = 4;
+/// The run was interrupted by a signal or `--timeout`.
+pub(crate) const INTERRUPTED: i32 = 130;
+
+/// Error returned when no dependency matched the requested rewrite.
+#[derive(Debug)]
+pub(crate) struct NothingMatched;
+
+impl fmt::Display for NothingMatched {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "No dependency matched the requested rewrite.")
+    }
+}
+
+impl std::error::Error for NothingMatched {}
+
+/// Context added to errors that happened after some manifests were already modified.
+#[derive(Debug)]
+pub(crate) struct PartialFailure;
+
+impl fmt::Display for PartialFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Failed after some manifests were already modified.")
+    }
+}
+
+/// Error returned when the run was stopped before it was finished.
+#[derive(Debug)]
+pub(crate) enum Interrupted {
+    /// By `SIGINT` (Ctrl-C) or `SIGTERM`.
+    Signal,
+    /// By `--timeout`.
+    Timeout,
+}
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Signal => write!(f, "Interrupted."),
+            Self::Timeout => write!(f, "Timed out."),
+        }
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+/// Returns the exit code for the given `error`.
+pub(crate) fn from_error(error: &anyhow::Error) -> i32 {
+    if error.is::<Interrupted>() {
+        INTERRUPTED
+    } else if error.is::<NothingMatched>() {
+        NOTHING_MATCHED
+    } else if error.is::<PartialFailure>() {
+        PARTIAL_FAILURE
+    } else if error.chain().any(|e| e.is::<reqwest::Error>()) {
+        NETWORK_ERROR
+    } else {
+        FAILURE
+    }
+}
@@ -0,0 +1,297 @@
+use crate::{
+    update::dependency_tables,
+    workspaces::{self, Workspace},
+};
+use anyhow::{anyhow, Context, Result};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt, fs,
+    path::PathBuf,
+    str::FromStr,
+};
+use toml_edit::{Array, Document, InlineTable, Item, Value};
+
+/// The features a dependency is declared with.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct FeatureSet {
+    pub default_features: bool,
+    pub features: BTreeSet<String>,
+}
+
+impl FeatureSet {
+    /// Returns the features of the dependency `dep`.
+    ///
+    /// Returns `None` for dependencies that are inherited from the workspace, as their features
+    /// are added to the ones of the workspace.
+    pub(crate) fn of(dep: &Item) -> Option<Self> {
+        if dep.is_str() {
+            return Some(Self {
+                default_features: true,
+                features: BTreeSet::new(),
+            });
+        }
+
+        let dep = dep.as_table_like()?;
+        if dep.contains_key("workspace") {
+            return None;
+        }
+
+        let default_features = dep
+            .get("default-features")
+            .or_else(|| dep.get("default_features"))
+            .and_then(|d| d.as_bool())
+            .unwrap_or(true);
+        let features = dep
+            .get("features")
+            .and_then(|f| f.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|f| f.as_str().map(String::from))
+            .collect();
+
+        Some(Self {
+            default_features,
+            features,
+        })
+    }
+
+    /// Unify the given `sets`: the default features stay enabled if all of them enable them
+    /// and all features of all sets are enabled.
+    ///
+    /// Disabling the default features wins, as they usually enable `std`, which breaks the
+    /// `no_std` builds of the runtime.
+    fn unify<'a>(sets: impl Iterator<Item = &'a Self>) -> Self {
+        sets.fold(
+            Self {
+                default_features: true,
+                features: BTreeSet::new(),
+            },
+            |mut unified, set| {
+                unified.default_features &= set.default_features;
+                unified.features.extend(set.features.iter().cloned());
+                unified
+            },
+        )
+    }
+
+    /// Declare the dependency `dep` with these features.
+    ///
+    /// Returns `true` if the dependency was modified.
+    pub(crate) fn apply(&self, dep: &mut Item) -> bool {
+        if Self::of(dep).as_ref() == Some(self) {
+            return false;
+        }
+
+        if let Some(version) = dep.as_str() {
+            let mut table = InlineTable::new();
+            table.insert("version", version.into());
+            *dep = Item::Value(Value::InlineTable(table).decorated(" ", ""));
+        }
+        let table = match dep.as_table_like_mut() {
+            Some(table) => table,
+            None => return false,
+        };
+
+        table.remove("default_features");
+        if self.default_features {
+            table.remove("default-features");
+        } else {
+            table.insert(
+                "default-features",
+                Item::Value(Value::from(false).decorated(" ", "")),
+            );
+        }
+
+        if self.features.is_empty() {
+            table.remove("features");
+        } else {
+            let features = self.features.iter().map(String::as_str).collect::<Array>();
+            table.insert(
+                "features",
+                Item::Value(Value::Array(features).decorated(" ", "")),
+            );
+        }
+
+        // Only the last value of an inline table is followed by a space: `{ a = 1, b = 2 }`.
+        if let Some(table) = dep.as_inline_table_mut() {
+            let last = table.len().saturating_sub(1);
+            for (i, (_, value)) in table.iter_mut().enumerate() {
+                let suffix = if i == last { " " } else { "" };
+                let decor = value.decor_mut();
+                if decor
+                    .suffix()
+                    .and_then(|s| s.as_str())
+                    .unwrap_or_default()
+                    .trim()
+                    .is_empty()
+                {
+                    decor.set_suffix(suffix);
+                }
+            }
+        }
+
+        true
+    }
+}
+
+impl fmt::Display for FeatureSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let features = self.features.iter().cloned().collect::<Vec<_>>();
+        match (self.default_features, features.is_empty()) {
+            (true, true) => write!(f, "default features"),
+            (true, false) => write!(f, "features = [{}]", features.join(", ")),
+            (false, true) => write!(f, "default-features = false"),
+            (false, false) => write!(
+                f,
+                "default-features = false, features = [{}]",
+                features.join(", ")
+            ),
+        }
+    }
+}
+
+/// A crate that is declared with different features in the manifests of a workspace.
+#[derive(Debug)]
+pub(crate) struct Drift {
+    /// The workspace, `None` for manifests outside of any workspace.
+    pub workspace: Option<PathBuf>,
+    pub package: String,
+    /// The different feature sets and the manifests that use them.
+    pub declarations: BTreeMap<FeatureSet, BTreeSet<PathBuf>>,
+}
+
+impl Drift {
+    /// Returns the features all declarations are unified to with `--normalize-features`.
+    pub(crate) fn unified(&self) -> FeatureSet {
+        FeatureSet::unify(self.declarations.keys())
+    }
+
+    /// Print the different declarations, the manifests relative to the workspace.
+    pub(crate) fn report(&self) {
+        let declarations = self
+            .declarations
+            .iter()
+            .map(|(set, manifests)| {
+                let manifests = manifests
+                    .iter()
+                    .map(|m| {
+                        self.workspace
+                            .as_deref()
+                            .and_then(|w| m.strip_prefix(w).ok())
+                            .unwrap_or(m)
+                            .display()
+                            .to_string()
+                    })
+                    .collect::<Vec<_>>();
+                format!("{}: {}", set, manifests.join(", "))
+            })
+            .collect::<Vec<_>>();
+
+        log::warn!(
+            dependency = self.package.as_str();
+            "`{}` is declared with different features{}:\n  {}",
+            self.package,
+            self.workspace
+                .as_ref()
+                .map(|w| format!(" in the workspace {}", w.display()))
+                .unwrap_or_default(),
+            declarations.join("\n  ")
+        );
+    }
+}
+
+/// Find the crates that are declared with different features in the `manifests` of one of the
+/// `workspaces`.
+///
+/// Only the dependency tables selected by `kind` are taken into account.
+pub(crate) fn find_drift(
+    manifests: &[PathBuf],
+    workspaces: &[Workspace],
+    kind: impl Fn(&str) -> bool,
+) -> Result<Vec<Drift>> {
+    // (workspace, package) -> features -> manifests.
+    let mut declared =
+        BTreeMap::<(Option<PathBuf>, String), BTreeMap<FeatureSet, BTreeSet<PathBuf>>>::new();
+    for manifest in manifests {
+        let content = fs::read_to_string(manifest)?;
+        if !content.contains("dependencies") {
+            continue;
+        }
+        let doc = Document::from_str(&content)
+            .with_context(|| anyhow!("Failed to parse {}", manifest.display()))?;
+        let workspace = workspaces::containing(workspaces, manifest).map(|w| w.path.clone());
+
+        for deps in dependency_tables(&doc, &kind) {
+            for (name, dep) in deps.iter() {
+                let features = match FeatureSet::of(dep) {
+                    Some(features) => features,
+                    None => continue,
+                };
+                let package = dep.get("package").and_then(|p| p.as_str()).unwrap_or(name);
+
+                declared
+                    .entry((workspace.clone(), package.into()))
+                    .or_default()
+                    .entry(features)
+                    .or_default()
+                    .insert(manifest.clone());
+            }
+        }
+    }
+
+    Ok(declared
+        .into_iter()
+        .filter(|(_, declarations)| declarations.len() > 1)
+        .map(|((workspace, package), declarations)| Drift {
+            workspace,
+            package,
+            declarations,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dependency(declaration: &str) -> (Document, FeatureSet) {
+        let doc = Document::from_str(&format!("dep = {}", declaration)).unwrap();
+        let features = FeatureSet::of(&doc["dep"]).unwrap();
+        (doc, features)
+    }
+
+    #[test]
+    fn unify_disables_default_features() {
+        let (_, a) = dependency(r#""1.0""#);
+        let (_, b) = dependency(r#"{ version = "1.0", default-features = false }"#);
+        let (_, c) = dependency(r#"{ version = "1.0", features = ["serde"] }"#);
+
+        let unified = FeatureSet::unify([&a, &b, &c].into_iter());
+        assert!(!unified.default_features);
+        assert_eq!(unified.features, BTreeSet::from(["serde".to_string()]));
+        assert_eq!(
+            unified.to_string(),
+            "default-features = false, features = [serde]"
+        );
+    }
+
+    #[test]
+    fn apply_rewrites_the_declaration() {
+        let (_, unified) = dependency(r#"{ default-features = false, features = ["serde"] }"#);
+
+        let (mut doc, _) = dependency(r#""1.0""#);
+        assert!(unified.apply(&mut doc["dep"]));
+        assert_eq!(
+            doc.to_string(),
+            "dep = { version = \"1.0\", default-features = false, features = [\"serde\"] }\n"
+        );
+        assert!(!unified.apply(&mut doc["dep"]));
+
+        let (mut doc, _) = dependency(r#"{ version = "1.0", default_features = false }"#);
+        assert!(FeatureSet::unify(std::iter::empty()).apply(&mut doc["dep"]));
+        assert_eq!(doc.to_string(), "dep = { version = \"1.0\" }\n");
+
+        let doc = Document::from_str("dep = { workspace = true }").unwrap();
+        assert!(FeatureSet::of(&doc["dep"]).is_none());
+    }
+}
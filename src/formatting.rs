@@ -0,0 +1,191 @@
+use anyhow::{anyhow, Result};
+use toml_edit::{Item, TableLike, Value};
+
+/// How the entries diener inserts into a dependency are formatted, `[format]` in the config file.
+///
+/// The options are named like the ones of `taplo`. Options that aren't set are detected from the
+/// entries that are already in the table, so the manifests keep the style of their formatter.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Formatting {
+    /// `{a = 1}` instead of `{ a = 1 }`.
+    pub compact_inline_tables: Option<bool>,
+    /// `a=1` instead of `a = 1`.
+    pub compact_entries: Option<bool>,
+}
+
+impl Formatting {
+    /// Parse the `[format]` table of the config file.
+    pub(crate) fn parse(table: &dyn TableLike) -> Result<Self> {
+        let mut formatting = Self::default();
+        for (key, value) in table.iter() {
+            let option = match key {
+                "compact-inline-tables" => &mut formatting.compact_inline_tables,
+                "compact-entries" => &mut formatting.compact_entries,
+                _ => return Err(anyhow!("Unknown option `format.{}`", key)),
+            };
+            *option = Some(
+                value
+                    .as_bool()
+                    .ok_or_else(|| anyhow!("`format.{}` is not a boolean", key))?,
+            );
+        }
+        Ok(formatting)
+    }
+
+    /// Returns the style of the dependency `dep`, the options that aren't set are detected from
+    /// its entries.
+    ///
+    /// Must be called before the dependency is modified, e.g. removing the last entry of an inline
+    /// table also removes the space in front of the closing brace.
+    pub(crate) fn style(&self, dep: &Item) -> Style {
+        let table = dep.as_table_like();
+        Style {
+            inline: dep.is_inline_table(),
+            compact_entries: self
+                .compact_entries
+                .or_else(|| table.and_then(detect_compact_entries))
+                .unwrap_or(false),
+            compact_inline_tables: self
+                .compact_inline_tables
+                .or_else(|| table.and_then(detect_compact_inline_table))
+                .unwrap_or(false),
+        }
+    }
+}
+
+/// The formatting of one dependency, see [`Formatting::style`].
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Style {
+    inline: bool,
+    compact_entries: bool,
+    compact_inline_tables: bool,
+}
+
+impl Style {
+    /// Insert `value` as `key` into the dependency `table`, formatted like its other entries.
+    ///
+    /// An existing entry keeps its position and the comments in front of it.
+    pub(crate) fn insert(&self, table: &mut dyn TableLike, key: &str, value: impl Into<Value>) {
+        let prefix = table
+            .key_decor(key)
+            .and_then(|d| d.prefix())
+            .and_then(|p| p.as_str())
+            .map(String::from);
+
+        let spacing = if self.compact_entries { "" } else { " " };
+        table.insert(key, Item::Value(value.into().decorated(spacing, "")));
+        if let Some(decor) = table.key_decor_mut(key) {
+            decor.set_prefix(prefix.unwrap_or_else(|| if self.inline { " " } else { "" }.into()));
+            decor.set_suffix(spacing);
+        }
+
+        if self.inline {
+            pad_inline_table(table, if self.compact_inline_tables { "" } else { " " });
+        }
+    }
+}
+
+/// Returns `true` if the entries of `table` omit the spaces around `=`.
+fn detect_compact_entries(table: &dyn TableLike) -> Option<bool> {
+    table
+        .iter()
+        .find_map(|(_, item)| item.as_value()?.decor().prefix()?.as_str())
+        .map(|prefix| prefix.is_empty())
+}
+
+/// Returns `true` if the inline `table` has no spaces inside of its braces.
+fn detect_compact_inline_table(table: &dyn TableLike) -> Option<bool> {
+    table
+        .iter()
+        .last()
+        .and_then(|(_, item)| item.as_value()?.decor().suffix()?.as_str())
+        .filter(|suffix| suffix.trim().is_empty())
+        .map(|suffix| suffix.is_empty())
+}
+
+/// Put `padding` after the opening and before the closing brace of an inline `table` and a
+/// single space after the commas.
+///
+/// Only whitespace is replaced, e.g. after entries were added or removed, comments are kept.
+fn pad_inline_table(table: &mut dyn TableLike, padding: &str) {
+    let keys = table.iter().map(|(k, _)| k.to_string()).collect::<Vec<_>>();
+    let last = keys.len().saturating_sub(1);
+    for (i, key) in keys.iter().enumerate() {
+        if let Some(decor) = table.key_decor_mut(key) {
+            if is_whitespace(decor.prefix().and_then(|p| p.as_str())) {
+                decor.set_prefix(if i == 0 { padding } else { " " });
+            }
+        }
+        if let Some(value) = table.get_mut(key).and_then(Item::as_value_mut) {
+            let decor = value.decor_mut();
+            if is_whitespace(decor.suffix().and_then(|s| s.as_str())) {
+                decor.set_suffix(if i == last { padding } else { "" });
+            }
+        }
+    }
+}
+
+fn is_whitespace(decor: Option<&str>) -> bool {
+    decor.unwrap_or_default().trim().is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use toml_edit::Document;
+
+    fn insert(formatting: Formatting, dep: &str) -> String {
+        let mut doc = Document::from_str(dep).unwrap();
+        let style = formatting.style(&doc["dep"]);
+        let table = doc["dep"].as_table_like_mut().unwrap();
+        table.remove("branch");
+        style.insert(table, "git", "https://github.com/paritytech/substrate");
+        style.insert(table, "rev", "abc");
+        doc.to_string()
+    }
+
+    #[test]
+    fn insert_keeps_the_style_of_the_table() {
+        assert_eq!(
+            insert(
+                Formatting::default(),
+                "dep = { git = \"https://github.com/org/substrate\", branch = \"master\" }\n"
+            ),
+            "dep = { git = \"https://github.com/paritytech/substrate\", rev = \"abc\" }\n"
+        );
+        assert_eq!(
+            insert(
+                Formatting::default(),
+                "dep = {branch=\"master\", git=\"https://github.com/org/substrate\"}\n"
+            ),
+            "dep = {git=\"https://github.com/paritytech/substrate\", rev=\"abc\"}\n"
+        );
+        assert_eq!(
+            insert(
+                Formatting::default(),
+                "[dep]\n# Fork.\ngit = \"https://github.com/org/substrate\"\nbranch = \"master\"\n"
+            ),
+            "[dep]\n# Fork.\ngit = \"https://github.com/paritytech/substrate\"\nrev = \"abc\"\n"
+        );
+    }
+
+    #[test]
+    fn configured_style_wins() {
+        let formatting = Formatting::parse(
+            Document::from_str("compact-inline-tables = true\ncompact-entries = false")
+                .unwrap()
+                .as_table(),
+        )
+        .unwrap();
+        assert_eq!(
+            insert(
+                formatting,
+                "dep = { git = \"https://github.com/org/substrate\", branch = \"master\" }\n"
+            ),
+            "dep = {git = \"https://github.com/paritytech/substrate\", rev = \"abc\"}\n"
+        );
+
+        assert!(Formatting::parse(Document::from_str("indent = 4").unwrap().as_table()).is_err());
+    }
+}
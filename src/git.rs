@@ -0,0 +1,339 @@
+use crate::{http, jobs};
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use git_url_parse::GitUrl;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Run `git` with the given `args` and return the output.
+fn git(args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .output()
+        .with_context(|| "Failed to run `git`, is it installed?")?;
+    ensure!(
+        output.status.success(),
+        "`git {}` failed: {}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+
+    String::from_utf8(output.stdout).map_err(|e| anyhow!("`git` returned invalid utf8: {}", e))
+}
+
+/// Like [`git`], but for commands that access a remote.
+///
+/// The command is killed if it doesn't finish within the network timeout, so an unreachable
+/// remote can't block the run.
+fn remote_git(args: &[&str]) -> Result<String> {
+    http::ensure_online()?;
+    let _permit = jobs::network_permit();
+    let timeout = http::timeout();
+    let mut child = Command::new("git")
+        .args(http::git_options())
+        .args(args)
+        // Never wait for credentials on the terminal.
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| "Failed to run `git`, is it installed?")?;
+
+    // Read the output while waiting, a full pipe would block `git` forever.
+    let read = |mut pipe: Box<dyn Read + Send>| {
+        thread::spawn(move || {
+            let mut output = Vec::new();
+            let _ = pipe.read_to_end(&mut output);
+            output
+        })
+    };
+    let stdout = read(Box::new(child.stdout.take().expect("stdout is piped")));
+    let stderr = read(Box::new(child.stderr.take().expect("stderr is piped")));
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            bail!(
+                "`git {}` didn't finish within {}s.",
+                args.join(" "),
+                timeout.as_secs()
+            );
+        }
+        thread::sleep(Duration::from_millis(50));
+    };
+
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+    ensure!(
+        status.success(),
+        "`git {}` failed: {}",
+        args.join(" "),
+        String::from_utf8_lossy(&stderr).trim()
+    );
+
+    String::from_utf8(stdout).map_err(|e| anyhow!("`git` returned invalid utf8: {}", e))
+}
+
+/// Returns all `Cargo.toml` files below `path` with uncommitted changes.
+///
+/// Returns an empty list if `path` isn't inside a git worktree.
+pub(crate) fn dirty_manifests(path: &Path) -> Result<Vec<PathBuf>> {
+    let path = path.to_string_lossy();
+    let in_worktree =
+        git(&["-C", &path, "rev-parse", "--is-inside-work-tree"]).is_ok_and(|o| o.trim() == "true");
+    if !in_worktree {
+        return Ok(Vec::new());
+    }
+
+    // The porcelain output is relative to the root of the worktree, not to `path`.
+    let toplevel = PathBuf::from(git(&["-C", &path, "rev-parse", "--show-toplevel"])?.trim());
+    Ok(git(&[
+        "-C",
+        &path,
+        "status",
+        "--porcelain",
+        "--",
+        ":(glob)**/Cargo.toml",
+    ])?
+    .lines()
+    .filter_map(|l| l.get(3..))
+    // Renames are reported as `old -> new`.
+    .map(|p| p.rsplit(" -> ").next().unwrap_or(p))
+    .map(|p| toplevel.join(p))
+    .collect())
+}
+
+/// Returns the checkouts of the git submodules of the worktree at `path`, including the
+/// submodules of submodules.
+///
+/// Submodules that aren't initialized are skipped. Returns an empty list if `path` isn't inside
+/// a git worktree.
+pub(crate) fn submodules(path: &Path) -> Result<Vec<PathBuf>> {
+    let in_worktree =
+        git_in(path, &["rev-parse", "--is-inside-work-tree"]).is_ok_and(|o| o.trim() == "true");
+    if !in_worktree {
+        return Ok(Vec::new());
+    }
+
+    // The paths are relative to `path`, as git runs in it.
+    Ok(git_in(path, &["submodule", "status", "--recursive"])?
+        .lines()
+        .filter_map(submodule_path)
+        .map(|p| path.join(p))
+        .collect())
+}
+
+/// Returns the path of an initialized submodule from a line of `git submodule status`, e.g.
+/// ` 0123abcd polkadot-sdk (heads/master)`.
+fn submodule_path(line: &str) -> Option<&str> {
+    // `-` marks submodules that aren't initialized.
+    let line = line.strip_prefix([' ', '+', 'U'])?;
+    let (_, path) = line.split_once(' ')?;
+    // The description is missing if the commit can't be described.
+    Some(
+        path.strip_suffix(')')
+            .and_then(|p| p.rsplit_once(" ("))
+            .map_or(path, |(path, _)| path),
+    )
+}
+
+/// Returns all tags of the remote repository at `url`.
+pub(crate) fn remote_tags(url: &str) -> Result<Vec<String>> {
+    Ok(remote_git(&["ls-remote", "--tags", "--refs", url])?
+        .lines()
+        .filter_map(|l| l.split_whitespace().nth(1))
+        .filter_map(|r| r.strip_prefix("refs/tags/"))
+        .map(Into::into)
+        .collect())
+}
+
+/// A commit returned by the GitHub API.
+#[derive(Deserialize)]
+struct Commit {
+    sha: String,
+}
+
+/// Returns `true` if `rev` is an abbreviated commit hash.
+pub(crate) fn is_short_rev(rev: &str) -> bool {
+    (4..40).contains(&rev.len()) && rev.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Resolve the abbreviated commit hash `rev` to the full hash in the remote repository at
+/// `url`.
+///
+/// GitHub repositories are asked for the commit. Everywhere else, only commits a branch or tag
+/// points to can be resolved, as `git` can't look up other commits without fetching the
+/// repository.
+pub(crate) fn resolve_rev(url: &str, rev: &str) -> Result<String> {
+    let github = GitUrl::parse(url)
+        .ok()
+        .filter(|g| g.host.as_deref() == Some("github.com"))
+        .and_then(|g| Some(format!("{}/{}", g.owner?, g.name)));
+    if let Some(repository) = github {
+        return match http::github_api::<Commit>(&format!("repos/{}/commits/{}", repository, rev)) {
+            Ok(Some(commit)) => Ok(commit.sha),
+            Ok(None) => bail!("{} doesn't exist or isn't accessible.", url),
+            // GitHub answers ambiguous and unknown hashes with `422 Unprocessable Entity`.
+            Err(e) if http::status(&e) == Some(StatusCode::UNPROCESSABLE_ENTITY) => {
+                bail!("`{}` is ambiguous or doesn't exist in {}.", rev, url)
+            }
+            Err(e) => Err(e),
+        };
+    }
+
+    let mut matches = remote_git(&["ls-remote", url])?
+        .lines()
+        .filter_map(|l| l.split_whitespace().next())
+        .filter(|h| h.starts_with(&rev.to_ascii_lowercase()))
+        .map(String::from)
+        .collect::<Vec<_>>();
+    matches.sort();
+    matches.dedup();
+    match matches.len() {
+        0 => bail!(
+            "No branch or tag of {} points to `{}`, pass the full commit hash.",
+            url,
+            rev
+        ),
+        1 => Ok(matches.remove(0)),
+        _ => bail!(
+            "`{}` is ambiguous in {}, it matches:\n  {}",
+            rev,
+            url,
+            matches.join("\n  ")
+        ),
+    }
+}
+
+/// The branches of a remote repository.
+#[derive(Debug, Clone)]
+pub(crate) struct RemoteBranches {
+    /// The branch `HEAD` points to, if the remote reports it.
+    pub default: Option<String>,
+    pub branches: Vec<String>,
+}
+
+/// Returns the branches of the remote repository at `url`.
+pub(crate) fn remote_branches(url: &str) -> Result<RemoteBranches> {
+    let output = remote_git(&["ls-remote", "--symref", url, "HEAD", "refs/heads/*"])?;
+
+    let mut remote = RemoteBranches {
+        default: None,
+        branches: Vec::new(),
+    };
+    for line in output.lines() {
+        if let Some(symref) = line.strip_prefix("ref: ") {
+            remote.default = symref
+                .split_whitespace()
+                .next()
+                .and_then(|r| r.strip_prefix("refs/heads/"))
+                .map(Into::into);
+        } else if let Some(branch) = line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|r| r.strip_prefix("refs/heads/"))
+        {
+            remote.branches.push(branch.into());
+        }
+    }
+
+    Ok(remote)
+}
+
+/// Returns the tag with the highest version, ignoring any non numeric prefix.
+///
+/// `polkadot-v0.9.43` is for example treated as version `0.9.43`.
+pub(crate) fn latest_version_tag(tags: &[String]) -> Option<&String> {
+    tags.iter()
+        .filter_map(|t| tag_version(t).map(|v| (v, t)))
+        .filter(|(v, _)| v.pre.is_empty())
+        .max_by(|a, b| a.0.cmp(&b.0))
+        .map(|(_, t)| t)
+}
+
+/// Parses the version of a tag like `v0.9.43` or `polkadot-v1.0.0`.
+fn tag_version(tag: &str) -> Option<semver::Version> {
+    let start = tag.find(|c: char| c.is_ascii_digit())?;
+    semver::Version::parse(&tag[start..]).ok()
+}
+
+/// Run `git` in the directory `dir` with the given `args` and return the output.
+fn git_in(dir: &Path, args: &[&str]) -> Result<String> {
+    let dir = dir.to_string_lossy();
+    git(&[&["-C", dir.as_ref()], args].concat())
+}
+
+/// Returns the branch that is checked out in the repository at `dir`.
+pub(crate) fn current_branch(dir: &Path) -> Result<String> {
+    Ok(git_in(dir, &["rev-parse", "--abbrev-ref", "HEAD"])?
+        .trim()
+        .into())
+}
+
+/// Returns the url of the `remote` of the repository at `dir`.
+pub(crate) fn remote_url(dir: &Path, remote: &str) -> Result<String> {
+    Ok(git_in(dir, &["remote", "get-url", remote])?.trim().into())
+}
+
+/// Commit the given `files` to the new `branch` of the repository at `dir`.
+pub(crate) fn commit_to_new_branch(
+    dir: &Path,
+    branch: &str,
+    files: &[PathBuf],
+    message: &str,
+) -> Result<()> {
+    git_in(dir, &["checkout", "-b", branch])?;
+
+    let files = files
+        .iter()
+        .map(|f| f.to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    let mut add = vec!["add", "--"];
+    add.extend(files.iter().map(String::as_str));
+    git_in(dir, &add)?;
+
+    git_in(dir, &["commit", "-m", message])?;
+    Ok(())
+}
+
+/// Push the `branch` of the repository at `dir` to `remote`.
+pub(crate) fn push(dir: &Path, remote: &str, branch: &str) -> Result<()> {
+    let dir = dir.to_string_lossy();
+    remote_git(&["-C", dir.as_ref(), "push", "--set-upstream", remote, branch])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn submodule_paths_are_parsed() {
+        let status = [
+            " 0123abcd polkadot-sdk (heads/master)",
+            "+4567cdef deps/my chain (v1.0-2-g4567cde)",
+            "-89abcdef not-initialized",
+            "U0123abcd conflicted",
+            " 4567cdef undescribed",
+        ];
+        assert_eq!(
+            status
+                .into_iter()
+                .filter_map(submodule_path)
+                .collect::<Vec<_>>(),
+            ["polkadot-sdk", "deps/my chain", "conflicted", "undescribed"]
+        );
+    }
+}
@@ -0,0 +1,36 @@
+use anyhow::{anyhow, ensure, Context, Result};
+use std::{path::PathBuf, process::Command};
+
+/// A command that is executed for the modified files.
+#[derive(Debug, Clone)]
+pub(crate) struct Hook {
+    /// The shell command, the paths of the modified files are appended as arguments.
+    pub command: String,
+    /// Run the command once for all modified files, instead of once per file.
+    pub once: bool,
+}
+
+impl Hook {
+    /// Run the hook with the given modified `files`.
+    ///
+    /// The command is run by `sh`, so it can contain arguments, pipes, etc.
+    pub(crate) fn run(&self, files: &[PathBuf]) -> Result<()> {
+        log::info!("Running hook `{}`", self.command);
+
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(format!("{} \"$@\"", self.command))
+            .arg("diener-hook")
+            .args(files)
+            .status()
+            .with_context(|| anyhow!("Failed to run hook `{}`", self.command))?;
+        ensure!(
+            status.success(),
+            "Hook `{}` failed with {}",
+            self.command,
+            status
+        );
+
+        Ok(())
+    }
+}
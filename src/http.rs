@@ -0,0 +1,279 @@
+use crate::{cache, jobs};
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use reqwest::{
+    blocking::{Client, RequestBuilder},
+    Certificate, Proxy, StatusCode,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{fs, path::PathBuf, sync::OnceLock, time::Duration};
+
+/// The user agent that is used if no other was configured.
+///
+/// crates.io rejects requests without a user agent.
+pub(crate) const DEFAULT_USER_AGENT: &str =
+    concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// The settings of the HTTP client.
+#[derive(Debug, Clone)]
+pub(crate) struct Settings {
+    /// The timeout of a whole request, from connecting until the body was read.
+    pub timeout: Duration,
+    pub user_agent: String,
+    /// Fail all network requests, like cargo with `CARGO_NET_OFFLINE=true`.
+    pub offline: bool,
+    /// The proxy used for all requests, cargo's `http.proxy`.
+    pub proxy: Option<String>,
+    /// A file with additional CA certificates, cargo's `http.cainfo`.
+    pub cainfo: Option<PathBuf>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+            user_agent: DEFAULT_USER_AGENT.into(),
+            offline: false,
+            proxy: None,
+            cainfo: None,
+        }
+    }
+}
+
+/// The settings used to create the HTTP client.
+static SETTINGS: OnceLock<Settings> = OnceLock::new();
+
+/// Set the settings that should be used for all network requests.
+///
+/// Must be called before the first request, later calls are ignored.
+pub(crate) fn configure(settings: Settings) {
+    let _ = SETTINGS.set(settings);
+}
+
+/// Returns the timeout of network requests, also used for `git` commands that access a remote.
+pub(crate) fn timeout() -> Duration {
+    SETTINGS.get_or_init(Default::default).timeout
+}
+
+/// Fails if network access is disabled with cargo's offline mode.
+pub(crate) fn ensure_online() -> Result<()> {
+    if SETTINGS.get_or_init(Default::default).offline {
+        bail!("Network access is disabled by `CARGO_NET_OFFLINE` or `net.offline` of the cargo config.");
+    }
+    Ok(())
+}
+
+/// Returns the `-c` options that make `git` use the same proxy and CA certificates as the HTTP
+/// client.
+pub(crate) fn git_options() -> Vec<String> {
+    let settings = SETTINGS.get_or_init(Default::default);
+    let mut options = Vec::new();
+    if let Some(proxy) = &settings.proxy {
+        options.extend(["-c".into(), format!("http.proxy={}", proxy)]);
+    }
+    if let Some(cainfo) = &settings.cainfo {
+        options.extend(["-c".into(), format!("http.sslCAInfo={}", cainfo.display())]);
+    }
+    options
+}
+
+/// Returns the HTTP client that is shared by all network requests.
+///
+/// The client keeps the connections open, so the requests to the same host reuse them.
+fn client() -> Result<&'static Client> {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+
+    ensure_online()?;
+    if let Some(client) = CLIENT.get() {
+        return Ok(client);
+    }
+
+    let settings = SETTINGS.get_or_init(Default::default);
+    let mut builder = Client::builder()
+        .user_agent(&settings.user_agent)
+        .timeout(settings.timeout)
+        .pool_max_idle_per_host(jobs::jobs());
+    if let Some(proxy) = &settings.proxy {
+        builder = builder
+            .proxy(Proxy::all(proxy).with_context(|| anyhow!("Invalid `http.proxy` `{}`", proxy))?);
+    }
+    if let Some(cainfo) = &settings.cainfo {
+        let certificates = fs::read(cainfo)
+            .map_err(anyhow::Error::from)
+            .and_then(|pem| Ok(Certificate::from_pem_bundle(&pem)?))
+            .with_context(|| anyhow!("Invalid `http.cainfo` {}", cainfo.display()))?;
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+
+    let client = builder.build().context("Failed to create HTTP client")?;
+    Ok(CLIENT.get_or_init(|| client))
+}
+
+/// The token used for all requests to GitHub.
+static GITHUB_TOKEN: OnceLock<String> = OnceLock::new();
+
+/// Set the token that should be used for all requests to GitHub.
+pub(crate) fn set_github_token(token: Option<String>) {
+    if let Some(token) = token.filter(|t| !t.is_empty()) {
+        let _ = GITHUB_TOKEN.set(token);
+    }
+}
+
+/// Returns `true` if a GitHub token was given.
+pub(crate) fn has_github_token() -> bool {
+    GITHUB_TOKEN.get().is_some()
+}
+
+/// Create a `GET` request for the given `url`.
+///
+/// Requests to GitHub are authenticated with the GitHub token, if one was given.
+fn get(url: &str) -> Result<RequestBuilder> {
+    let mut request = client()?.get(url);
+
+    let is_github = reqwest::Url::parse(url).is_ok_and(|u| {
+        matches!(
+            u.host_str(),
+            Some("github.com" | "api.github.com" | "raw.githubusercontent.com")
+        )
+    });
+    if let Some(token) = GITHUB_TOKEN.get().filter(|_| is_github) {
+        request = request.bearer_auth(token);
+    }
+
+    Ok(request)
+}
+
+/// Returns the HTTP status of the failed request that caused `error`, if there is one.
+pub(crate) fn status(error: &anyhow::Error) -> Option<StatusCode> {
+    error
+        .chain()
+        .find_map(|e| e.downcast_ref::<reqwest::Error>())
+        .and_then(|e| e.status())
+}
+
+/// Returns `true` if the given `source` is an url that should be fetched over the network.
+pub(crate) fn is_url(source: &str) -> bool {
+    source.starts_with("https://") || source.starts_with("http://")
+}
+
+/// Fetch the given `url` and return the body as text.
+pub(crate) fn get_text(url: &str) -> Result<String> {
+    let _permit = jobs::network_permit();
+    log::debug!("Fetching {}", url);
+
+    get(url)?
+        .send()
+        .and_then(|r| r.error_for_status())
+        .and_then(|r| r.text())
+        .with_context(|| anyhow!("Failed to fetch {}", url))
+}
+
+/// Fetch the given `url` and return the body as bytes.
+pub(crate) fn get_bytes(url: &str) -> Result<Vec<u8>> {
+    let _permit = jobs::network_permit();
+    log::debug!("Fetching {}", url);
+
+    get(url)?
+        .send()
+        .and_then(|r| r.error_for_status())
+        .and_then(|r| r.bytes())
+        .map(|b| b.to_vec())
+        .with_context(|| anyhow!("Failed to fetch {}", url))
+}
+
+/// Fetch the given `url` and return the body as text, using the on-disk cache.
+pub(crate) fn get_text_cached(url: &str) -> Result<String> {
+    cache::get_or_fetch(url, || get_text(url).map(Some))?
+        .ok_or_else(|| anyhow!("Failed to fetch {}", url))
+}
+
+/// Fetch the given `url` and return the JSON body.
+///
+/// Returns `None` if the server responds with `404 Not Found`.
+fn get_json_body(url: &str) -> Result<Option<String>> {
+    let _permit = jobs::network_permit();
+    log::debug!("Fetching {}", url);
+
+    let response = get(url)?
+        .header("Accept", "application/json")
+        .send()
+        .with_context(|| anyhow!("Failed to fetch {}", url))?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+
+    response
+        .error_for_status()
+        .and_then(|r| r.text())
+        .map(Some)
+        .with_context(|| anyhow!("Failed to fetch {}", url))
+}
+
+/// Deserialize the JSON `body` of the response of `url`.
+fn parse_json<T: DeserializeOwned>(url: &str, body: Option<String>) -> Result<Option<T>> {
+    body.map(|body| {
+        serde_json::from_str(&body).with_context(|| anyhow!("Failed to parse response of {}", url))
+    })
+    .transpose()
+}
+
+/// Fetch the given `url` and deserialize the JSON body.
+///
+/// Returns `None` if the server responds with `404 Not Found`.
+pub(crate) fn get_json<T: DeserializeOwned>(url: &str) -> Result<Option<T>> {
+    parse_json(url, get_json_body(url)?)
+}
+
+/// Fetch the given `url` and deserialize the JSON body, using the on-disk cache.
+///
+/// Returns `None` if the server responds with `404 Not Found`.
+pub(crate) fn get_json_cached<T: DeserializeOwned>(url: &str) -> Result<Option<T>> {
+    parse_json(url, cache::get_or_fetch(url, || get_json_body(url))?)
+}
+
+/// Send a `GET` request to the given `path` of the GitHub API and deserialize the JSON body.
+///
+/// Returns `None` if the server responds with `404 Not Found`.
+pub(crate) fn github_api<T: DeserializeOwned>(path: &str) -> Result<Option<T>> {
+    get_json(&format!(
+        "https://api.github.com/{}",
+        path.trim_start_matches('/')
+    ))
+}
+
+/// Send a `POST` request with the JSON `body` to the given `path` of the GitHub API and
+/// deserialize the JSON response.
+///
+/// Requires the GitHub token.
+pub(crate) fn github_api_post<T: DeserializeOwned>(path: &str, body: &impl Serialize) -> Result<T> {
+    let url = format!("https://api.github.com/{}", path.trim_start_matches('/'));
+    let token = GITHUB_TOKEN
+        .get()
+        .ok_or_else(|| anyhow!("A GitHub token is required for {}", url))?;
+    let _permit = jobs::network_permit();
+    log::debug!("Posting to {}", url);
+
+    let response = client()?
+        .post(&url)
+        .bearer_auth(token)
+        .header("Accept", "application/vnd.github+json")
+        .header("Content-Type", "application/json")
+        .body(serde_json::to_string(body)?)
+        .send()
+        .with_context(|| anyhow!("Failed to post to {}", url))?;
+
+    let status = response.status();
+    let text = response
+        .text()
+        .with_context(|| anyhow!("Failed to read the response of {}", url))?;
+    ensure!(
+        status.is_success(),
+        "Posting to {} failed with {}: {}",
+        url,
+        status,
+        text
+    );
+
+    serde_json::from_str(&text).with_context(|| anyhow!("Failed to parse response of {}", url))
+}
@@ -0,0 +1,331 @@
+use crate::{
+    lockfile::LockFile,
+    logging::output,
+    repos::{split_reference, with_scheme, MatchOptions, Matcher, UrlScheme},
+    update::{dependency_tables, manifests},
+};
+use anyhow::{anyhow, ensure, Context, Result};
+use git_url_parse::GitUrl;
+use globset::GlobSet;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env::current_dir,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use structopt::StructOpt;
+use toml_edit::Document;
+
+/// `info` subcommand options.
+#[derive(Debug, StructOpt)]
+pub struct Info {
+    /// The path where Diener should search for `Cargo.toml` files.
+    #[structopt(long)]
+    path: Option<PathBuf>,
+
+    /// Print every dependency, instead of only the number of dependencies per reference.
+    #[structopt(long, short = "v")]
+    verbose: bool,
+
+    /// Also print the ecosystem crates in the `Cargo.lock` that are not declared in any of the
+    /// manifests, but only pulled in by other dependencies, with the git source they resolve to.
+    #[structopt(long)]
+    transitive: bool,
+
+    #[structopt(flatten)]
+    match_options: MatchOptions,
+}
+
+/// A package of the `Cargo.lock` that is only pulled in by other dependencies.
+#[derive(Debug, PartialEq)]
+struct TransitiveDependency {
+    name: String,
+    version: String,
+}
+
+/// A git dependency found in a manifest.
+struct GitDependency {
+    name: String,
+    manifest: PathBuf,
+}
+
+impl Info {
+    /// Run this subcommand.
+    pub fn run(self) -> Result<()> {
+        let path = self
+            .path
+            .map(Ok)
+            .unwrap_or_else(|| current_dir().with_context(|| "Working directory is invalid."))?;
+        ensure!(
+            path.is_dir(),
+            "Path '{}' is not a directory.",
+            path.display()
+        );
+
+        // repository -> reference -> dependencies
+        let mut repos = BTreeMap::<String, BTreeMap<String, Vec<GitDependency>>>::new();
+        let mut declared = BTreeSet::new();
+        for manifest in manifests(&path, &GlobSet::empty()) {
+            collect_git_dependencies(&manifest, &mut repos, &mut declared)?;
+        }
+
+        let mut inconsistent = 0;
+        for (repo, references) in &repos {
+            output!("{}", repo);
+            for (reference, deps) in references {
+                output!(
+                    "  {}: {} dependenc{}",
+                    reference,
+                    deps.len(),
+                    if deps.len() == 1 { "y" } else { "ies" }
+                );
+                if self.verbose {
+                    deps.iter().for_each(|d| {
+                        let manifest = d.manifest.strip_prefix(&path).unwrap_or(&d.manifest);
+                        output!("    {} ({})", d.name, manifest.display())
+                    });
+                }
+            }
+
+            if references.len() > 1 {
+                inconsistent += 1;
+                output!(
+                    "  WARNING: dependencies point to {} different references!",
+                    references.len()
+                );
+            }
+        }
+
+        if inconsistent > 0 {
+            output!(
+                "\n{} of {} repositories are used with inconsistent references.",
+                inconsistent,
+                repos.len()
+            );
+        }
+
+        if self.transitive {
+            let lock = LockFile::find(&path)
+                .ok_or_else(|| anyhow!("No `Cargo.lock` found for {}.", path.display()))?;
+            let lock_file = LockFile::read(&lock)?;
+            let matcher = Matcher::new(self.match_options)?;
+            print_transitive(&transitive_dependencies(&lock_file, &declared, &matcher));
+        }
+
+        Ok(())
+    }
+}
+
+/// Print the `transitive` dependencies returned by [`transitive_dependencies`].
+fn print_transitive(transitive: &BTreeMap<String, BTreeMap<String, Vec<TransitiveDependency>>>) {
+    if transitive.is_empty() {
+        output!("\nNo ecosystem crates are only pulled in transitively.");
+        return;
+    }
+
+    output!("\nTransitive dependencies from the `Cargo.lock`:");
+    for (repo, references) in transitive {
+        output!("{}", repo);
+        for (reference, deps) in references {
+            output!("  {}:", reference);
+            deps.iter()
+                .for_each(|d| output!("    {} {}", d.name, d.version));
+        }
+
+        if references.len() > 1 {
+            output!(
+                "  WARNING: dependencies resolve to {} different revisions!",
+                references.len()
+            );
+        }
+    }
+}
+
+/// Returns the git packages of the `lock_file` from the repositories selected by the `matcher`
+/// that are not `declared` in any manifest, grouped by repository and resolved revision.
+///
+/// These are pulled in by other dependencies, e.g. an intermediate crate that still depends on
+/// the old branch after an update.
+fn transitive_dependencies(
+    lock_file: &LockFile,
+    declared: &BTreeSet<String>,
+    matcher: &Matcher,
+) -> BTreeMap<String, BTreeMap<String, Vec<TransitiveDependency>>> {
+    let mut transitive = BTreeMap::<String, BTreeMap<String, Vec<TransitiveDependency>>>::new();
+    for package in &lock_file.packages {
+        if declared.contains(&package.name) {
+            continue;
+        }
+        let source = match package.git_source() {
+            Some(source) => source,
+            None => continue,
+        };
+        let git = match GitUrl::parse(&source.url) {
+            Ok(git) if matcher.is_selected(&git) && matcher.classify(&git).is_some() => git,
+            _ => continue,
+        };
+
+        let reference = source
+            .reference
+            .map(|(k, v)| format!("{} = \"{}\"", k, v))
+            .unwrap_or_else(|| "default branch".into());
+        let commit = source.commit.get(..12).unwrap_or(&source.commit);
+
+        transitive
+            .entry(with_scheme(&git, UrlScheme::Https).unwrap_or_else(|_| git.to_string()))
+            .or_default()
+            .entry(format!("{} @ {}", reference, commit))
+            .or_default()
+            .push(TransitiveDependency {
+                name: package.name.clone(),
+                version: package.version.clone(),
+            });
+    }
+    transitive
+}
+
+/// Collect the git dependencies of the `manifest` into `repos` and the names of all crates it
+/// declares or defines into `declared`.
+fn collect_git_dependencies(
+    manifest: &Path,
+    repos: &mut BTreeMap<String, BTreeMap<String, Vec<GitDependency>>>,
+    declared: &mut BTreeSet<String>,
+) -> Result<()> {
+    let doc = Document::from_str(&fs::read_to_string(manifest)?)
+        .with_context(|| anyhow!("Failed to parse {}", manifest.display()))?;
+
+    declared.extend(declared_crates(&doc));
+
+    doc.iter()
+        .filter(|(k, _)| k.contains("dependencies"))
+        .filter_map(|(_, v)| v.as_table())
+        .flat_map(|t| t.iter())
+        .filter_map(|(name, dep)| dep.as_table_like().map(|d| (name, d)))
+        .for_each(|(name, dep)| {
+            let (git, embedded) = match dep.get("git").and_then(|g| g.as_str()) {
+                Some(git) => split_reference(git),
+                None => return,
+            };
+            let git = match GitUrl::parse(git) {
+                Ok(git) => git,
+                Err(_) => return,
+            };
+
+            let reference = ["branch", "tag", "rev"]
+                .iter()
+                .find_map(|k| {
+                    dep.get(k)
+                        .and_then(|v| v.as_str())
+                        .map(|v| format!("{} = \"{}\"", k, v))
+                })
+                .or_else(|| embedded.map(|(k, v)| format!("{} = \"{}\"", k, v)))
+                .unwrap_or_else(|| "default branch".into());
+
+            repos
+                .entry(with_scheme(&git, UrlScheme::Https).unwrap_or_else(|_| git.to_string()))
+                .or_default()
+                .entry(reference)
+                .or_default()
+                .push(GitDependency {
+                    name: name.into(),
+                    manifest: manifest.into(),
+                });
+        });
+
+    Ok(())
+}
+
+/// Returns the names of the crates the manifest `doc` depends on, including the workspace
+/// dependencies, and the name of its package.
+fn declared_crates(doc: &Document) -> Vec<String> {
+    let workspace = doc
+        .get("workspace")
+        .and_then(|w| w.get("dependencies"))
+        .and_then(|d| d.as_table());
+
+    let mut crates = dependency_tables(doc, |k| k.contains("dependencies"))
+        .into_iter()
+        .chain(workspace)
+        .flat_map(|t| t.iter())
+        .map(|(name, dep)| {
+            dep.get("package")
+                .and_then(|p| p.as_str())
+                .unwrap_or(name)
+                .to_string()
+        })
+        .collect::<Vec<_>>();
+    crates.extend(
+        doc.get("package")
+            .and_then(|p| p.get("name"))
+            .and_then(|n| n.as_str())
+            .map(String::from),
+    );
+    crates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undeclared_git_packages_are_transitive() {
+        let doc = Document::from_str(
+            r#"[package]
+name = "node"
+
+[dependencies]
+io = { package = "sp-io", git = "https://github.com/paritytech/substrate", branch = "master" }
+
+[workspace.dependencies]
+sp-core = { git = "https://github.com/paritytech/substrate", branch = "master" }
+"#,
+        )
+        .unwrap();
+        let declared = declared_crates(&doc).into_iter().collect::<BTreeSet<_>>();
+        assert_eq!(
+            declared,
+            ["node", "sp-core", "sp-io"]
+                .into_iter()
+                .map(String::from)
+                .collect()
+        );
+
+        let lock_file = LockFile::from_str(
+            r#"
+[[package]]
+name = "sp-io"
+version = "7.0.0"
+source = "git+https://github.com/paritytech/substrate?branch=master#0123456789abcdef0123456789abcdef01234567"
+
+[[package]]
+name = "sp-std"
+version = "5.0.0"
+source = "git+https://github.com/paritytech/substrate?branch=polkadot-v0.9.40#89abcdef0123456789abcdef0123456789abcdef"
+
+[[package]]
+name = "foo"
+version = "1.0.0"
+source = "git+https://github.com/someone/foo#0123456789abcdef0123456789abcdef01234567"
+
+[[package]]
+name = "serde"
+version = "1.0.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#,
+        )
+        .unwrap();
+        let matcher = Matcher::new(MatchOptions::from_iter_safe(["info"]).unwrap()).unwrap();
+
+        let transitive = transitive_dependencies(&lock_file, &declared, &matcher);
+        assert_eq!(transitive.len(), 1);
+        assert_eq!(
+            transitive["https://github.com/paritytech/substrate"]
+                ["branch = \"polkadot-v0.9.40\" @ 89abcdef0123"],
+            vec![TransitiveDependency {
+                name: "sp-std".into(),
+                version: "5.0.0".into(),
+            }]
+        );
+    }
+}
@@ -0,0 +1,38 @@
+use crate::exit_code::Interrupted;
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Instant,
+};
+
+/// Was a `SIGINT` or `SIGTERM` received?
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle_signal(_: libc::c_int) {
+    // A second signal terminates immediately, for when diener hangs on the network.
+    if INTERRUPTED.swap(true, Ordering::SeqCst) {
+        unsafe { libc::_exit(130) };
+    }
+}
+
+/// Install the handler for `SIGINT` (Ctrl-C) and `SIGTERM`.
+///
+/// Instead of terminating, the signal is recorded and the run stops at the next call to
+/// [`check`]. This way no file is left half written.
+pub(crate) fn install() {
+    let handler = handle_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+}
+
+/// Returns an error if the run was interrupted or the `deadline` has passed.
+pub(crate) fn check(deadline: Option<Instant>) -> Result<(), Interrupted> {
+    if INTERRUPTED.load(Ordering::SeqCst) {
+        Err(Interrupted::Signal)
+    } else if deadline.is_some_and(|d| Instant::now() >= d) {
+        Err(Interrupted::Timeout)
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,117 @@
+use std::{
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Condvar, Mutex, OnceLock,
+    },
+    thread,
+};
+
+/// The default number of jobs, if the number of CPUs is unknown.
+const DEFAULT_JOBS: usize = 4;
+
+/// The upper bound of the default number of jobs, more parallel requests only get rate limited.
+const MAX_DEFAULT_JOBS: usize = 8;
+
+/// The number of jobs given with `--jobs`.
+static JOBS: OnceLock<usize> = OnceLock::new();
+
+/// Set the number of jobs that may run at the same time.
+///
+/// Must be called before the first job is started, later calls are ignored.
+pub(crate) fn configure(jobs: NonZeroUsize) {
+    let _ = JOBS.set(jobs.get());
+}
+
+/// Returns the number of jobs that may run at the same time.
+///
+/// Defaults to the number of CPUs, but at most [`MAX_DEFAULT_JOBS`].
+pub(crate) fn jobs() -> usize {
+    *JOBS.get_or_init(|| {
+        thread::available_parallelism()
+            .map_or(DEFAULT_JOBS, NonZeroUsize::get)
+            .min(MAX_DEFAULT_JOBS)
+    })
+}
+
+/// Apply `f` to all `items` with up to [`jobs`] threads.
+///
+/// The results are returned in the order of the `items`.
+pub(crate) fn map<T: Sync, R: Send>(items: &[T], f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let workers = jobs().min(items.len());
+    if workers <= 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let mut results = thread::scope(|scope| {
+        let workers = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        match items.get(index) {
+                            Some(item) => results.push((index, f(item))),
+                            None => return results,
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        workers
+            .into_iter()
+            .flat_map(|w| w.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+            .collect::<Vec<_>>()
+    });
+
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// A permit to access the network, see [`network_permit`].
+pub(crate) struct Permit(());
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let (running, released) = network();
+        *running.lock().unwrap_or_else(|e| e.into_inner()) -= 1;
+        released.notify_one();
+    }
+}
+
+/// The number of running network requests and the signal that one finished.
+fn network() -> &'static (Mutex<usize>, Condvar) {
+    static NETWORK: OnceLock<(Mutex<usize>, Condvar)> = OnceLock::new();
+    NETWORK.get_or_init(Default::default)
+}
+
+/// Wait until less than [`jobs`] network requests are running.
+///
+/// The request may run until the returned permit is dropped.
+pub(crate) fn network_permit() -> Permit {
+    let (running, released) = network();
+    let mut running = released
+        .wait_while(running.lock().unwrap_or_else(|e| e.into_inner()), |r| {
+            *r >= jobs()
+        })
+        .unwrap_or_else(|e| e.into_inner());
+    *running += 1;
+    Permit(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn map_keeps_the_order() {
+        let items = (0..100).collect::<Vec<u32>>();
+        assert_eq!(
+            map(&items, |i| i * 2),
+            items.iter().map(|i| i * 2).collect::<Vec<_>>()
+        );
+        assert!(map(&[] as &[u32], |i| *i).is_empty());
+    }
+}
@@ -0,0 +1,133 @@
+use crate::{
+    lockfile::{LockFile, Package},
+    logging::output,
+};
+use anyhow::{ensure, Context, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use structopt::StructOpt;
+
+/// `lockdiff` subcommand options.
+#[derive(Debug, StructOpt)]
+pub struct LockDiff {
+    /// The old `Cargo.lock`, either a path, an url, `-` for stdin or
+    /// `release:OWNER/REPO@TAG` for a GitHub release.
+    old: String,
+
+    /// The new `Cargo.lock`, either a path, an url, `-` for stdin or
+    /// `release:OWNER/REPO@TAG` for a GitHub release.
+    new: String,
+}
+
+impl LockDiff {
+    /// Run this subcommand.
+    pub fn run(self) -> Result<()> {
+        ensure!(
+            self.old != "-" || self.new != "-",
+            "Only one `Cargo.lock` can be read from stdin."
+        );
+        let old = LockFile::load(&self.old).context("Failed to load the old `Cargo.lock`")?;
+        let new = LockFile::load(&self.new).context("Failed to load the new `Cargo.lock`")?;
+
+        let diff = diff(&old, &new);
+        if diff.is_empty() {
+            output!("No changes.");
+        } else {
+            diff.iter().for_each(|c| output!("{}", c));
+        }
+
+        Ok(())
+    }
+}
+
+/// A change of a package between two `Cargo.lock` files.
+enum Change<'a> {
+    Added(&'a Package),
+    Removed(&'a Package),
+    Changed { old: &'a Package, new: &'a Package },
+}
+
+impl std::fmt::Display for Change<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Added(p) => write!(f, "+ {} {}{}", p.name, p.version, source(p)),
+            Self::Removed(p) => write!(f, "- {} {}{}", p.name, p.version, source(p)),
+            Self::Changed { old, new } => {
+                write!(f, "~ {} {}", new.name, old.version)?;
+                if old.version != new.version {
+                    write!(f, " -> {}", new.version)?;
+                }
+
+                match (old.git_source(), new.git_source()) {
+                    (Some(o), Some(n)) if o.url == n.url && o.reference == n.reference => {
+                        write!(f, " (git rev {} -> {})", o.commit, n.commit)
+                    }
+                    _ if old.source != new.source => {
+                        write!(f, " ({} -> {})", source_name(old), source_name(new))
+                    }
+                    _ => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+fn source_name(package: &Package) -> &str {
+    package.source.as_deref().unwrap_or("local")
+}
+
+fn source(package: &Package) -> String {
+    package
+        .source
+        .as_ref()
+        .map(|s| format!(" ({})", s))
+        .unwrap_or_default()
+}
+
+/// Calculates the changes between `old` and `new`.
+///
+/// Packages are matched by name. If a package exists in multiple versions, only the versions
+/// that are not present in both files are reported as added or removed.
+fn diff<'a>(old: &'a LockFile, new: &'a LockFile) -> Vec<Change<'a>> {
+    let group = |lock: &'a LockFile| {
+        let mut packages = BTreeMap::<&str, Vec<&Package>>::new();
+        lock.packages
+            .iter()
+            .for_each(|p| packages.entry(&p.name).or_default().push(p));
+        packages
+    };
+    let old = group(old);
+    let new = group(new);
+
+    let names = old.keys().chain(new.keys()).collect::<BTreeSet<_>>();
+    let mut changes = Vec::new();
+
+    for name in names {
+        let old = old.get(name).map(|p| p.as_slice()).unwrap_or_default();
+        let new = new.get(name).map(|p| p.as_slice()).unwrap_or_default();
+
+        match (old, new) {
+            ([old], [new]) => {
+                if old.version != new.version || old.source != new.source {
+                    changes.push(Change::Changed { old, new });
+                }
+            }
+            _ => {
+                let same =
+                    |a: &Package, b: &Package| a.version == b.version && a.source == b.source;
+
+                changes.extend(
+                    old.iter()
+                        .filter(|o| !new.iter().any(|n| same(o, n)))
+                        .map(|o| Change::Removed(o)),
+                );
+                changes.extend(
+                    new.iter()
+                        .filter(|n| !old.iter().any(|o| same(o, n)))
+                        .map(|n| Change::Added(n)),
+                );
+            }
+        }
+    }
+
+    changes
+}
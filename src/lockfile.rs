@@ -0,0 +1,167 @@
+use crate::http;
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::{
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use toml_edit::Document;
+
+/// A package entry of a `Cargo.lock` file.
+#[derive(Debug, Clone)]
+pub(crate) struct Package {
+    pub name: String,
+    pub version: String,
+    pub source: Option<String>,
+}
+
+/// A git source of a package, e.g. `git+https://github.com/foo/bar?branch=master#abcdef`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct GitSource {
+    /// The repository url.
+    pub url: String,
+    /// The `branch`/`tag`/`rev` requested in the manifest, as `(key, value)`.
+    pub reference: Option<(String, String)>,
+    /// The commit the dependency is locked to.
+    pub commit: String,
+}
+
+impl Package {
+    /// Returns the git source of this package, if it is fetched from git.
+    pub(crate) fn git_source(&self) -> Option<GitSource> {
+        let source = self.source.as_deref()?.strip_prefix("git+")?;
+        let (url, commit) = source.split_once('#')?;
+        let (url, reference) = match url.split_once('?') {
+            Some((url, query)) => (
+                url,
+                query
+                    .split_once('=')
+                    .map(|(k, v)| (k.to_string(), v.to_string())),
+            ),
+            None => (url, None),
+        };
+
+        Some(GitSource {
+            url: url.into(),
+            reference,
+            commit: commit.into(),
+        })
+    }
+}
+
+/// A parsed `Cargo.lock` file.
+#[derive(Debug, Clone)]
+pub(crate) struct LockFile {
+    pub packages: Vec<Package>,
+}
+
+impl FromStr for LockFile {
+    type Err = anyhow::Error;
+
+    fn from_str(content: &str) -> Result<Self> {
+        let doc = Document::from_str(content)?;
+        let packages = doc
+            .get("package")
+            .and_then(|p| p.as_array_of_tables())
+            .ok_or_else(|| anyhow!("`Cargo.lock` doesn't contain any packages"))?
+            .iter()
+            .map(|p| {
+                let get = |key| p.get(key).and_then(|v| v.as_str()).map(String::from);
+
+                Ok(Package {
+                    name: get("name").ok_or_else(|| anyhow!("Package without a name"))?,
+                    version: get("version").ok_or_else(|| anyhow!("Package without a version"))?,
+                    source: get("source"),
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self { packages })
+    }
+}
+
+impl LockFile {
+    /// Read the `Cargo.lock` at the given `path`.
+    pub(crate) fn read(path: &Path) -> Result<Self> {
+        fs::read_to_string(path)
+            .with_context(|| anyhow!("Failed to read {}", path.display()))?
+            .parse()
+            .with_context(|| anyhow!("Failed to parse {}", path.display()))
+    }
+
+    /// Load a `Cargo.lock` from the given `source`.
+    ///
+    /// The `source` is either a path, an url, `-` to read it from stdin or
+    /// `release:OWNER/REPO@TAG` for the `Cargo.lock` of a GitHub release.
+    pub(crate) fn load(source: &str) -> Result<Self> {
+        if let Some(release) = source.strip_prefix("release:") {
+            release_lock_file(release)?
+                .parse()
+                .with_context(|| anyhow!("Failed to parse the `Cargo.lock` of {}", release))
+        } else if source == "-" {
+            let mut content = String::new();
+            io::stdin()
+                .read_to_string(&mut content)
+                .context("Failed to read from stdin")?;
+            content.parse().context("Failed to parse stdin")
+        } else if http::is_url(source) {
+            http::get_text_cached(source)?
+                .parse()
+                .with_context(|| anyhow!("Failed to parse {}", source))
+        } else {
+            Self::read(Path::new(source))
+        }
+    }
+
+    /// Find the `Cargo.lock` that belongs to the given directory.
+    ///
+    /// This is the first `Cargo.lock` found in `dir` or any of its parents.
+    pub(crate) fn find(dir: &Path) -> Option<PathBuf> {
+        dir.ancestors()
+            .map(|d| d.join("Cargo.lock"))
+            .find(|l| l.is_file())
+    }
+}
+
+/// Fetch the `Cargo.lock` of the GitHub release `OWNER/REPO@TAG`.
+///
+/// A `Cargo.lock` attached to the release is preferred, otherwise the one contained in the
+/// repository at the tag is taken.
+fn release_lock_file(release: &str) -> Result<String> {
+    #[derive(Deserialize)]
+    struct Release {
+        assets: Vec<Asset>,
+    }
+
+    #[derive(Deserialize)]
+    struct Asset {
+        name: String,
+        browser_download_url: String,
+    }
+
+    let (repository, tag) = release
+        .split_once('@')
+        .filter(|(r, t)| r.contains('/') && !t.is_empty())
+        .ok_or_else(|| {
+            anyhow!(
+                "Invalid release `{}`, expected `release:OWNER/REPO@TAG`.",
+                release
+            )
+        })?;
+
+    let asset =
+        http::github_api::<Release>(&format!("repos/{}/releases/tags/{}", repository, tag))?
+            .into_iter()
+            .flat_map(|r| r.assets)
+            .find(|a| a.name == "Cargo.lock");
+
+    match asset {
+        Some(asset) => http::get_text_cached(&asset.browser_download_url),
+        None => http::get_text_cached(&format!(
+            "https://raw.githubusercontent.com/{}/{}/Cargo.lock",
+            repository, tag
+        )),
+    }
+}
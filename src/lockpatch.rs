@@ -0,0 +1,264 @@
+use crate::{
+    atomic_write,
+    lockfile::LockFile,
+    patch::{workspace_dir, workspace_root_package},
+    repos::{MatchOptions, Matcher},
+    run_lock, unpatch,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use git_url_parse::GitUrl;
+use std::{
+    collections::{btree_map::Entry, BTreeMap},
+    env::current_dir,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use structopt::StructOpt;
+use toml_edit::{Document, InlineTable, Item, Table, Value};
+
+/// `lockpatch` subcommand options.
+#[derive(Debug, StructOpt)]
+pub struct LockPatch {
+    /// The `Cargo.lock` to take the revisions from, either a path, an url, `-` for stdin or
+    /// `release:OWNER/REPO@TAG` for a GitHub release.
+    lock: String,
+
+    /// The path to the project where the patch sections should be added.
+    ///
+    /// If not given, the current directory will be taken. If this points to a `Cargo.toml`
+    /// file, this file will be taken as the cargo workspace `Cargo.toml` file.
+    #[structopt(long)]
+    path: Option<PathBuf>,
+
+    #[structopt(flatten)]
+    match_options: MatchOptions,
+}
+
+impl LockPatch {
+    /// Run this subcommand.
+    pub fn run(self) -> Result<()> {
+        let path = match self.path {
+            Some(path) if !path.exists() => {
+                bail!("Given --path=`{}` does not exist!", path.display())
+            }
+            Some(path) => path,
+            None => current_dir().with_context(|| "Working directory is invalid.")?,
+        };
+        let manifest = workspace_root_package(&path)?;
+        let _lock = run_lock::acquire(workspace_dir(&manifest))?;
+
+        let lock_file = LockFile::load(&self.lock).context("Failed to load the `Cargo.lock`")?;
+        let patches = locked_revisions(&lock_file, &Matcher::new(self.match_options)?);
+        if patches.is_empty() {
+            log::info!("`{}` doesn't contain any matching git packages.", self.lock);
+            return Ok(());
+        }
+
+        add_patches(&manifest, &patches)
+    }
+}
+
+/// Returns the commit of every matching git package, grouped by the repository url.
+///
+/// Packages that are locked to multiple commits of the same repository are skipped, as a
+/// patch can only point to one of them.
+fn locked_revisions(
+    lock_file: &LockFile,
+    matcher: &Matcher,
+) -> BTreeMap<String, BTreeMap<String, String>> {
+    let mut patches = BTreeMap::<String, BTreeMap<String, Option<String>>>::new();
+
+    lock_file
+        .packages
+        .iter()
+        .filter_map(|p| p.git_source().map(|s| (p, s)))
+        .filter(|(_, s)| {
+            GitUrl::parse(&s.url)
+                .is_ok_and(|url| matcher.is_selected(&url) && matcher.classify(&url).is_some())
+        })
+        .for_each(|(package, source)| {
+            match patches
+                .entry(source.url)
+                .or_default()
+                .entry(package.name.clone())
+            {
+                Entry::Vacant(entry) => {
+                    entry.insert(Some(source.commit));
+                }
+                Entry::Occupied(mut entry) => {
+                    if entry.get().as_ref().is_some_and(|c| *c != source.commit) {
+                        log::warn!(
+                            "`{}` is locked to multiple revisions, not patching it.",
+                            package.name
+                        );
+                        entry.insert(None);
+                    }
+                }
+            }
+        });
+
+    patches
+        .into_iter()
+        .map(|(url, packages)| {
+            let packages = packages
+                .into_iter()
+                .filter_map(|(name, commit)| Some((name, commit?)))
+                .collect();
+            (url, packages)
+        })
+        .collect()
+}
+
+/// Add a `[patch."URL"]` section for every repository in `patches` to the given `manifest`.
+///
+/// Existing patch entries for the same packages are replaced.
+fn add_patches(
+    manifest: &Path,
+    patches: &BTreeMap<String, BTreeMap<String, String>>,
+) -> Result<()> {
+    let content = fs::read_to_string(manifest)
+        .with_context(|| anyhow!("Failed to read manifest at {}", manifest.display()))?;
+    let mut doc = Document::from_str(&content).context("Failed to parse Cargo.toml")?;
+
+    let patch_table = doc
+        .as_table_mut()
+        .entry("patch")
+        .or_insert(Item::Table(Default::default()))
+        .as_table_mut()
+        .ok_or_else(|| anyhow!("Patch table isn't a toml table!"))?;
+    patch_table.set_implicit(true);
+
+    for (url, packages) in patches {
+        if !patch_table.contains_key(url) {
+            let mut table = Table::new();
+            unpatch::mark_table(&mut table);
+            patch_table.insert(url, Item::Table(table));
+        }
+        let target = patch_table
+            .get_mut(url)
+            .and_then(|t| t.as_table_mut())
+            .ok_or_else(|| anyhow!("Patch target table for `{}` isn't a toml table!", url))?;
+
+        for (name, commit) in packages {
+            log::info!("Patching `{}` to {}", name, commit);
+
+            let mut patch = InlineTable::new();
+            patch.insert("git", Value::from(distinct_source(url).as_str()));
+            patch.insert("rev", Value::from(commit.as_str()));
+            patch.fmt();
+            let prefix = unpatch::marked_prefix(target, name);
+            target.insert(name, Item::Value(Value::InlineTable(patch)));
+            unpatch::mark_entry(target, name, prefix);
+        }
+    }
+
+    let new_content = doc.to_string();
+    if new_content != content {
+        atomic_write::write(manifest, new_content)
+            .with_context(|| anyhow!("Failed to write manifest to {}", manifest.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Returns an url of the repository at `url` that cargo treats as a different source.
+///
+/// Cargo rejects patches that point to the same source they patch. A `.git` suffix or a
+/// trailing `/` doesn't help, as cargo strips both when comparing sources. An empty path segment
+/// in front of the repository name is kept by cargo, but ignored by git servers.
+fn distinct_source(url: &str) -> String {
+    let url = url.trim_end_matches('/');
+    match url.rfind('/') {
+        Some(pos) if !url[..pos].ends_with('/') => format!("{}/{}", &url[..pos], &url[pos..]),
+        _ => url.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn distinct_source_adds_an_empty_segment() {
+        assert_eq!(
+            distinct_source("https://github.com/paritytech/substrate"),
+            "https://github.com/paritytech//substrate"
+        );
+        assert_eq!(
+            distinct_source("https://github.com/paritytech/substrate/"),
+            "https://github.com/paritytech//substrate"
+        );
+    }
+
+    fn run(dir: &Path, program: &str, args: &[&str]) -> String {
+        let output = Command::new(program)
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "`{} {}` failed: {}",
+            program,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn cargo_accepts_the_patches() {
+        let dir = std::env::temp_dir().join(format!("diener-lockpatch-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let repo = dir.join("repo");
+        let app = dir.join("app");
+        fs::create_dir_all(repo.join("src")).unwrap();
+        fs::create_dir_all(app.join("src")).unwrap();
+
+        fs::write(
+            repo.join("Cargo.toml"),
+            "[package]\nname = \"foo\"\nversion = \"0.1.0\"\n",
+        )
+        .unwrap();
+        fs::write(repo.join("src/lib.rs"), "").unwrap();
+        let git = |args: &[&str]| {
+            let args = [
+                &["-c", "user.name=diener", "-c", "user.email=diener@test"],
+                args,
+            ]
+            .concat();
+            run(&repo, "git", &args)
+        };
+        git(&["init", "-q", "-b", "main"]);
+        git(&["add", "-A"]);
+        git(&["commit", "-q", "-m", "first"]);
+        let commit = git(&["rev-parse", "HEAD"]).trim().to_string();
+        git(&["commit", "-q", "--allow-empty", "-m", "second"]);
+
+        let url = format!("file://{}", repo.display());
+        let manifest = app.join("Cargo.toml");
+        fs::write(
+            &manifest,
+            format!(
+                "[package]\nname = \"app\"\nversion = \"0.1.0\"\n\n\
+                 [dependencies]\nfoo = {{ git = \"{}\", branch = \"main\" }}\n",
+                url
+            ),
+        )
+        .unwrap();
+        fs::write(app.join("src/lib.rs"), "").unwrap();
+
+        let patches = BTreeMap::from([(
+            url.clone(),
+            BTreeMap::from([("foo".to_string(), commit.clone())]),
+        )]);
+        add_patches(&manifest, &patches).unwrap();
+
+        let metadata = run(&app, "cargo", &["metadata", "--format-version", "1"]);
+        assert!(metadata.contains(&commit), "{}", metadata);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,117 @@
+use anyhow::{bail, Result};
+use env_logger::Env;
+use log::kv::{self, VisitSource};
+use serde_json::{Map, Value};
+use std::{
+    io::Write,
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// The format of the log output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LogFormat {
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => bail!("Unknown log format `{}`, expected `text` or `json`.", s),
+        }
+    }
+}
+
+/// Is the log output using [`LogFormat::Json`]?
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// Returns `true` if the log output is JSON.
+pub(crate) fn is_json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+/// Initialize the logger with the given `format`.
+///
+/// The log level can be changed with `RUST_LOG`, it defaults to `info`.
+pub(crate) fn init(format: LogFormat) {
+    let mut builder = env_logger::Builder::from_env(Env::default().default_filter_or("info"));
+
+    if format == LogFormat::Json {
+        JSON.store(true, Ordering::Relaxed);
+        builder.format(|buf, record| {
+            let mut event = Map::new();
+            event.insert("timestamp".into(), buf.timestamp().to_string().into());
+            event.insert("level".into(), record.level().as_str().into());
+            event.insert("target".into(), record.target().into());
+            event.insert("message".into(), record.args().to_string().into());
+            // The structured fields of the event, e.g. the `manifest` or `dependency`.
+            let _ = record.key_values().visit(&mut Fields(&mut event));
+            writeln!(buf, "{}", Value::Object(event))
+        });
+    }
+
+    builder.init();
+}
+
+/// Collects the key-values of a log record into a JSON object.
+struct Fields<'a>(&'a mut Map<String, Value>);
+
+impl<'kvs> VisitSource<'kvs> for Fields<'_> {
+    fn visit_pair(&mut self, key: kv::Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        let value = if let Some(value) = value.to_u64() {
+            value.into()
+        } else if let Some(value) = value.to_bool() {
+            value.into()
+        } else {
+            value.to_string().into()
+        };
+        self.0.insert(key.to_string(), value);
+        Ok(())
+    }
+}
+
+/// Print a line of output, like `println!`.
+///
+/// With JSON logging the line is logged instead, so the whole output stays one JSON object per
+/// line.
+macro_rules! output {
+    ($($arg:tt)*) => {
+        if $crate::logging::is_json() {
+            log::info!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    };
+}
+
+pub(crate) use output;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_are_collected() {
+        let fields: &[(&str, kv::Value)] = &[
+            ("manifest", kv::Value::from("Cargo.toml")),
+            ("line", kv::Value::from(5u64)),
+        ];
+        let record = log::Record::builder()
+            .args(format_args!("message"))
+            .key_values(&fields)
+            .build();
+
+        let mut event = Map::new();
+        record.key_values().visit(&mut Fields(&mut event)).unwrap();
+        assert_eq!(
+            Value::Object(event),
+            serde_json::json!({ "manifest": "Cargo.toml", "line": 5 })
+        );
+    }
+}
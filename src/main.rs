@@ -28,8 +28,337 @@ diener update --branch diener-branch-2
 
 Diener also supports `tag` and `rev` as arguments.
 
-If a depdendency is belongs to Substrate, Polkadot or Cumulus is currently done by looking at the git url.
-It also only works for repos called `substrate`, `polkadot` or `cumulus`.
+An abbreviated `--rev`, e.g. `--rev 0123abcd`, is resolved to the full commit hash before anything
+is written, as cargo handles abbreviated hashes differently across versions. GitHub repositories
+are asked for the commit, elsewhere only commits a branch or tag points to can be resolved. The
+run fails if the hash is ambiguous or doesn't exist.
+
+`--git` changes the git url of the altered dependencies. The placeholders `{org}` and `{repo}`
+are replaced by the owner and name of the original url, so all repositories can be redirected to
+a mirror at once:
+
+```
+diener update --all --branch diener-branch --git "https://github.com/myorg/{repo}"
+```
+
+Several repositories can be updated at once by passing `--path` multiple times:
+
+```
+diener update --substrate --branch diener-branch --path ../runtime --path ../node
+```
+
+Every changed dependency is printed as `name: old-source -> new-source`, colored when running in
+a terminal (set `NO_COLOR` to disable the colors).
+
+All `crates.io` dependencies can be upgraded to their latest semver compatible version with
+`--crates-io-deps`, alone or together with the git dependencies:
+
+```
+diener update --crates-io-deps
+```
+
+Dependencies that are not published on `crates.io`, e.g. internal pallets, keep their version with
+a warning. `--on-missing skip` leaves them untouched silently and `--on-missing fail` aborts.
+
+The versions are looked up with up to `--jobs` requests at the same time, which defaults to the
+number of CPUs (at most 8). All requests share their connections. `--jobs 1` looks them up one
+after another, e.g. on CI runners with strict limits for outgoing connections.
+
+Crates that are declared with different `default-features` or `features` in the manifests of a
+workspace are reported on every run, as this often only breaks the `wasm` build of the runtime.
+`--normalize-features` unifies them, alone or together with the other options. The default
+features are disabled if one of the declarations disables them and all features of all
+declarations are enabled:
+
+```
+diener update --normalize-features
+```
+
+Release bumps usually also change the metadata of the workspace. `--workspace-package` sets a field
+of the `[workspace.package]` table, e.g. the `rust-version` or the `version` the members inherit,
+in the same run. Only fields the workspace already declares are changed:
+
+```
+diener update --polkadot --tag polkadot-stable2409 --workspace-package rust-version=1.81 --workspace-package version=1.16.0
+```
+
+The fields can also be declared in the `[workspace-package]` table of the config file:
+
+```toml
+[workspace-package]
+rust-version = "1.81"
+```
+
+Git dependencies can be switched to a release on `crates.io` with `--version`. It takes a version
+requirement, `latest` for the newest stable release or `latest:MAJOR` for the newest stable
+release with the given major version. The version is looked up for every crate on its own, as the
+crates of a repository are not always released together:
+
+```
+diener update --substrate --version latest:4
+```
+
+Crates that are not published on `crates.io` or have no matching release keep their git source with
+a warning.
+
+Before anything is written, the versions of all crates are resolved and diener warns if the crates
+of one repository would end up on different versions, e.g. because a release was published while
+the versions were looked up. `--consistent-release` instead takes the version of every crate from
+one `Cargo.lock`, given like for `--rev-from`, so all crates come from the same release:
+
+```
+diener update --polkadot --consistent-release release:paritytech/polkadot-sdk@polkadot-stable2409
+```
+
+When the tag names of a repository don't match the versions of its crates, `--version-from-tag`
+together with `--tag` uses the version every crate has in its `Cargo.toml` at that tag instead. The
+manifests are fetched from GitHub:
+
+```
+diener update --polkadot --tag polkadot-stable2409 --version-from-tag
+```
+
+Git dependencies that also carry a `version`, e.g. `{ git = "...", branch = "master", version = "4.0" }`
+to be publishable, keep their `version` when they are rewritten. `--drop-version` removes it and
+`--sync-version` sets it to the version the crate has at the new `branch`, `tag` or `rev`, with the
+manifests fetched from GitHub:
+
+```
+diener update --substrate --branch polkadot-v1.0.0 --sync-version
+```
+
+Dependencies that use a `branch` can be pinned to the `rev` they are currently locked to in the
+`Cargo.lock` with `--freeze`:
+
+```
+diener update --all --freeze
+```
+
+The revisions another project is pinned to can be adopted with `--rev-from`, which takes the
+`Cargo.lock` of the other project as path, url, `-` for stdin or `release:OWNER/REPO@TAG`. Every
+dependency gets the `rev` of its repository in that `Cargo.lock`:
+
+```
+diener update --all --rev-from https://raw.githubusercontent.com/polkadot-fellows/runtimes/main/Cargo.lock
+```
+
+Dependencies on a `branch` that no longer exists upstream, e.g. `master` after the repository
+switched to `main`, can be moved to the current default branch of their repository with
+`--fix-default-branch`. The branches are looked up with `git ls-remote`, dependencies whose branch
+still exists are not changed:
+
+```
+diener update --all --fix-default-branch
+```
+
+The `[patch]` sections and git `[source]` replacements in `.cargo/config.toml` files are
+rewritten as well when passing `--cargo-config`:
+
+```
+diener update --substrate --branch diener-branch --cargo-config
+```
+
+To test a companion pull request, diener can look up the fork and branch of the pull request
+on GitHub (set `GITHUB_TOKEN` or `--github-token` to avoid rate limits):
+
+```
+diener update --companion paritytech/polkadot#1234
+```
+
+When switching several repositories, `--check-compat` fetches the `Cargo.lock` of every
+selected repository at the selected version and warns if it pins one of the other repositories to
+a different version:
+
+```
+diener update --profile release-v1 --check-compat
+```
+
+Single crates can deviate from the requested version with an overrides file that maps crate
+names to their source:
+
+```toml
+sp-io = { rev = "abc123" }
+sp-core = { branch = "my-fix", git = "https://github.com/myorg/substrate" }
+```
+
+```
+diener update --substrate --branch master --overrides overrides.toml
+```
+
+Overrides can also be declared in the `[override]` table of the `diener.toml` config file.
+
+Crates that were renamed upstream can be renamed while updating. The dependency key (or the
+`package` of a renamed dependency) and references in `[features]` are changed to the new name:
+
+```
+diener update --substrate --branch master --rename beefy-primitives=sp-consensus-beefy
+```
+
+Renames can also be declared in the `[rename]` table of the `diener.toml` config file.
+
+A command can be run for every modified file with `--hook`, the path of the file is appended as
+argument. With `--hook-once` the command is run only once at the end with all modified files:
+
+```
+diener update --substrate --branch master --hook "taplo fmt" --hook-once
+```
+
+Directories listed in `workspace.exclude` of the `Cargo.toml` at `--path` are skipped, unless
+`--include-excluded` is passed.
+
+Git submodules with their own workspaces, e.g. a checkout of `polkadot-sdk` in a parachain
+repository, are often excluded. With `--recurse-submodules` every initialized submodule below
+`--path` is updated as well, like an additional `--path` with its own `workspace.exclude`, check
+for uncommitted changes and summary of the changes:
+
+```
+diener update --polkadot --branch master --recurse-submodules
+```
+
+With `--dev-only` only the `[dev-dependencies]` are rewritten, e.g. to test against a newer branch
+while the runtime dependencies stay untouched:
+
+```
+diener update --substrate --branch master --dev-only
+```
+
+Every workspace found below `--path`, including virtual manifests and workspaces nested in
+another one (e.g. examples with their own workspace), is processed as a unit. Nested workspaces are
+reported, as they get the same rules as the outer one, and if there is more than one workspace a
+summary is printed for each. With `--update-lockfiles` diener runs `cargo update --workspace` for
+every workspace with modified manifests, so each `Cargo.lock` is updated:
+
+```
+diener update --substrate --branch master --update-lockfiles
+```
+
+Vendored crates (directories with a `.cargo-checksum.json`) and manifests generated by cargo
+(starting with `# THIS FILE IS AUTOMATICALLY GENERATED BY CARGO`) are never touched, as changing
+them breaks the checksums cargo verifies for them.
+
+Diener refuses to run if `Cargo.toml` files at `--path` have uncommitted changes, to not mix its
+changes with manual ones. Pass `--force` to run anyway.
+
+With `--open-pr` the modified files are committed to a new branch, which is pushed and opened as
+pull request on GitHub (requires a GitHub token). The branch, remote, title and body can be set
+with `--pr-branch`, `--pr-remote`, `--pr-title` and `--pr-body`. The title and body support the
+placeholders `{rewritten}`, `{files}` and `{changes}`:
+
+```
+diener update --substrate --branch polkadot-v1.0.0 --open-pr --pr-title "Bump substrate ({rewritten} crates)"
+```
+
+With `--audit-log <file>` a record of every run is appended to the given file. Each record is a
+JSON object on its own line with a timestamp, the arguments, the modified files and the old and
+new source of every rewritten dependency.
+
+For humans, e.g. a CI bot that comments on a pull request, `--report markdown` writes a table
+with the crate, the file and the old and new source of every rewritten dependency to stdout, or
+to the file given with `--report-file`:
+
+```
+diener update --substrate --branch master --report markdown --report-file changes.md
+```
+
+Repositories and versions can also be stored as named profiles in a `diener.toml` config
+file at `--path` (or the file given with `--config`):
+
+```toml
+[profile.release-v1]
+substrate = { branch = "polkadot-v0.9.43" }
+polkadot = { branch = "release-v0.9.43", git = "https://github.com/myorg/polkadot" }
+```
+
+```
+diener update --profile release-v1
+```
+
+If a dependency belongs to Substrate, Polkadot or Cumulus is done by comparing the git url against
+the official `paritytech` repositories. Additional urls (e.g. for a fork) can be accepted with
+`--expected-url`. `--match-name-only` restores the old behavior of only looking at the repository
+name, which treats every repo called `substrate`, `polkadot` or `cumulus` as the official one.
+
+Mirrors whose repository isn't called like the official one can be assigned to a repository with
+url prefixes in the `[match]` table of the `diener.toml` config file. Every git url starting with
+one of the prefixes is treated as the given repository:
+
+```toml
+[match]
+substrate = ["https://git.mycorp.io/chain/substrate", "https://git.mycorp.io/mirrors/sdk"]
+```
+
+For more control, `[[classify]]` rules map a regex to a repository. The regex is matched against
+the url in the form `host/owner/name`, without scheme and `.git` suffix. The rules are checked in
+order before the url prefixes and the built-in rules, the first matching rule wins.
+`repo = "none"` excludes the matching urls, e.g. to fix a false positive of `--match-name-only`:
+
+```toml
+[[classify]]
+pattern = "^github\\.com/myorg/(polkadot-)?sdk$"
+repo = "polkadot"
+
+[[classify]]
+pattern = "^github\\.com/someone/substrate$"
+repo = "none"
+```
+
+The `git`, `branch`, `tag`, `rev` and `version` entries diener inserts follow the style of the
+entries around them, e.g. `{git="...", rev="..."}` stays without spaces. To match a formatter
+like `taplo` instead, the style can be set in the `[format]` table of the config file, with the
+options named like the ones of `taplo`:
+
+```toml
+[format]
+# `{a = 1}` instead of `{ a = 1 }`.
+compact-inline-tables = true
+# `a=1` instead of `a = 1`.
+compact-entries = false
+```
+
+Git dependencies that diener would silently skip, because their url can not be parsed or because
+their repository is called like a known one without matching an accepted url, can be turned into
+errors with `--strict`.
+
+On Ctrl-C, or when the time given with `--timeout` (in seconds) has passed, the run stops after
+the file that is currently processed and prints the files that were already modified. With
+`--revert-on-interrupt` these files are restored instead. A second Ctrl-C terminates immediately.
+
+Like `sed -i.bak`, `--backup-ext` keeps a copy of every modified file with the given extension
+appended:
+
+```
+diener update --substrate --branch master --backup-ext .bak
+```
+
+Concurrent runs on the same tree, e.g. two CI jobs, could interleave their writes. So every
+subcommand that modifies manifests holds an advisory lock on `.diener.lock` in the workspace root
+while it runs, and a second run waits for the first one to finish. The lock is released when diener
+exits, even if it was killed. `--no-lock` skips the lock.
+
+Source replacements (`replace-with`) in the `.cargo/config.toml` files that apply to `--path` are
+taken into account: a git dependency is also matched by the source cargo replaces it with. Diener
+warns if a rewritten dependency was replaced before, but its new source isn't, and if `crates.io`
+is replaced while upgrading registry dependencies.
+
+### Plan
+
+The `plan` subcommand takes the same options as `update`, but writes the changes to a TOML file
+instead of modifying the manifests. The plan lists the old and new source of every dependency per
+file and can be committed as a reviewable artifact. `apply-plan` later writes the planned files
+byte for byte, unless one of them changed since the plan was created:
+
+```
+diener plan --out plan.toml --substrate --branch polkadot-v1.0.0
+diener apply-plan plan.toml
+```
+
+`update --emit patch` doesn't modify anything either, it prints the rewrite as `[patch."URL"]`
+tables to stdout instead, to paste or pipe into the manifest of choice. Only git dependencies are
+included, as cargo can't patch a registry dependency with another version from the same registry:
+
+```
+diener update --substrate --branch polkadot-v1.0.0 --emit patch >> Cargo.toml
+```
 
 ### Patch
 
@@ -45,6 +374,170 @@ diener patch --crates-to-patch ../path/to/substrate/checkout --substrate
 This subcommand can be compared to `.cargo/config` without using a deprecated
 feature of Cargo ;)
 
+For local development, `--rust-analyzer-friendly` adds the patches to the `.cargo/config.toml` next
+to the workspace `Cargo.toml` instead. Cargo and rust-analyzer pick them up from there, so the IDE
+works with the local checkout while the committed manifests stay untouched:
+
+```
+diener patch --crates-to-patch ../path/to/polkadot-sdk --crates --rust-analyzer-friendly
+```
+
+`diener unpatch --rust-analyzer-friendly` removes them again, and the file as well if nothing else
+is left in it.
+
+### Umbrella
+
+The `umbrella` subcommand converts the individual `sp-*`, `frame-*` and `pallet-*` dependencies
+into the `polkadot-sdk` umbrella crate, enabling a feature for every converted crate. Entries of
+the `[features]` table like `sp-io/std` are rewritten to `polkadot-sdk/std`:
+
+```
+diener umbrella
+```
+
+Only dependencies from the `paritytech/polkadot-sdk` git repository or `crates.io` are converted,
+`workspace = true` dependencies are converted if their `[workspace.dependencies]` entry is. For
+`crates.io` dependencies the version of `polkadot-sdk` has to be given with `--version`, crates
+that aren't re-exported by this version are not converted. Features of a dependency are enabled
+through the feature of `polkadot-sdk` with the same name, dependencies with features that
+`polkadot-sdk` doesn't have are not converted.
+
+`--reverse` converts the umbrella crate back into the individual dependencies. Entries like
+`polkadot-sdk/try-runtime` are expanded to every individual crate, the crates that don't have the
+feature are reported and need to be removed manually.
+
+### Lockdiff
+
+The `lockdiff` subcommand compares two `Cargo.lock` files and prints all packages that were
+added, removed or changed their version or git revision. Both arguments can be paths or urls:
+
+```
+diener lockdiff old/Cargo.lock https://raw.githubusercontent.com/paritytech/polkadot/master/Cargo.lock
+```
+
+### Lockpatch
+
+The `lockpatch` subcommand pins all Substrate, Polkadot and Cumulus git dependencies to the
+revisions of a given `Cargo.lock` by adding `[patch]` sections to the workspace `Cargo.toml`.
+The `Cargo.lock` can be a path or an url:
+
+```
+diener lockpatch https://raw.githubusercontent.com/paritytech/polkadot/release-v0.9.43/Cargo.lock
+```
+
+Instead of a path or url, `-` reads the `Cargo.lock` from stdin:
+
+```
+curl -s https://raw.githubusercontent.com/paritytech/polkadot/master/Cargo.lock | diener lockpatch -
+```
+
+`release:OWNER/REPO@TAG` takes the `Cargo.lock` attached to the given GitHub release or, if there
+is none, the one in the repository at the tag:
+
+```
+diener lockpatch release:paritytech/polkadot@v0.9.43
+```
+
+Cargo doesn't allow a patch to point to the source it patches, so the patches use the repository
+url with an additional `/` in front of the repository name, e.g.
+`https://github.com/paritytech//substrate`.
+
+### Unpatch
+
+The patches added by `patch` and `lockpatch` are marked with a `# diener:patch` comment. The
+`unpatch` subcommand removes them from the workspace `Cargo.toml` again and restores the patches
+they replaced:
+
+```
+diener unpatch --path ../my-project
+```
+
+### Info
+
+The `info` subcommand prints the git repositories the dependencies point to, grouped by the
+`branch`, `tag` or `rev` they are used with, and warns about repositories that are used with
+different references:
+
+```
+diener info --verbose
+```
+
+With `--transitive`, it also parses the `Cargo.lock` and prints the ecosystem crates that are not
+declared in any manifest, but only pulled in by other dependencies, with the revision they resolve
+to. After an update this shows if an unpatched intermediate crate still pulls in the old branch.
+
+### Outdated
+
+The `outdated` subcommand prints all Polkadot ecosystem dependencies that are behind the latest
+version published on `crates.io` or, for git dependencies, the latest release tag:
+
+```
+diener outdated
+```
+
+With `--stable`, pre-releases like `-rc1` or `-dev` are ignored when looking up the latest version.
+
+Without `--all-crates`, only crates named like the ecosystem crates (`sp-*`, `frame-*`, `pallet-*`,
+...) are looked up. Repositories whose tags can not be fetched are reported as `unknown`.
+
+### Check-dependent
+
+The `check-dependent` subcommand verifies that the project depends on a repository at the given
+`--branch`, `--rev` or `--tag`, in all manifests and the `Cargo.lock`. Every mismatch is printed
+and the command fails, which makes it usable in the "check dependent project" CI jobs of the
+upstream repositories:
+
+```
+diener check-dependent --dependency substrate --rev 0123abcd
+```
+
+When running in GitHub Actions, every mismatch is also printed as an `::error` annotation pointing at
+the line of the dependency, so it shows up inline in the diff of the pull request.
+
+### Doctor
+
+The `doctor` subcommand checks the environment without modifying anything: it verifies that
+crates.io and GitHub are reachable, the GitHub token is valid, `--path` belongs to a cargo
+workspace and all manifests can be parsed:
+
+```
+diener doctor
+```
+
+### Self-update
+
+The `self-update` subcommand replaces the installed diener with the latest release from GitHub:
+
+```
+diener self-update
+```
+
+The downloaded archive is verified against the `.sha256` published with the release. Only
+platforms with a released archive are supported, currently macOS on x86_64. Everywhere else, update
+with `cargo install diener --force`.
+
+### Network
+
+Like cargo, diener reads the `[http]` and `[net]` tables of the cargo config files that apply to
+the working directory and the `CARGO_HTTP_*` and `CARGO_NET_OFFLINE` environment variables:
+
+- `http.proxy` and `http.cainfo` are used for all requests and the `git` commands that access a
+  remote.
+- `http.timeout` is the default of `--http-timeout`.
+- With `net.offline` or `CARGO_NET_OFFLINE=true`, nothing is fetched. Cached responses are used
+  regardless of their age and everything else fails.
+
+### Exit codes
+
+| Code | Meaning |
+| ---- | ------- |
+| 0    | Success. |
+| 1    | The run failed. |
+| 2    | No dependency matched the requested rewrite (only with `update --detailed-exit-codes` or `--expect-changes`). |
+| 3    | The run failed after some manifests were already modified. |
+| 4    | The run failed because of a network error. |
+| 130  | The run was interrupted by Ctrl-C or `--timeout`. |
+
 ## License
 
 Licensed under either of
@@ -56,22 +549,69 @@ Licensed under either of
 at your option.
 */
 
-use env_logger::Env;
+use anyhow::Context;
+use std::{env, num::NonZeroUsize, process, time::Duration};
 use structopt::{
     clap::{crate_name, crate_version},
     StructOpt,
 };
 
+mod atomic_write;
+mod audit;
+mod cache;
+mod check_dependent;
+mod compat;
+mod config;
+mod crates_io;
+mod doctor;
+mod exit_code;
+mod features;
+mod formatting;
+mod git;
+mod hook;
+mod http;
+mod info;
+mod interrupt;
+mod jobs;
+mod lockdiff;
+mod lockfile;
+mod lockpatch;
+mod logging;
+mod net_config;
+mod outdated;
 mod patch;
+mod plan;
+mod pull_request;
+mod report;
+mod repos;
+mod run_lock;
+mod self_update;
+mod source_replacement;
+mod tagged_manifest;
+mod umbrella;
+mod unpatch;
 mod update;
+mod workspaceify;
+mod workspaces;
 mod workspacify;
 
 /// diener is a tool for easily finding and changing Substrate or Polkadot dependency versions.
 /// diener will not modified the cargo.lock file but update specific dependencies in the Cargo.toml files or the project.
 #[derive(Debug, StructOpt)]
+#[allow(clippy::large_enum_variant)]
 enum SubCommands {
     /// Update all `Cargo.toml` files at a given path to some specific path/branch/commit.
     Update(update::Update),
+    /// Write the changes `update` would make to a reviewable plan, without modifying anything.
+    ///
+    /// Takes the same options as `update`. The plan lists the old and new source of every
+    /// dependency per file and can be committed and applied later with `apply-plan`.
+    Plan(plan::Plan),
+    /// Apply a plan written by `plan`.
+    ///
+    /// The files are written exactly as planned. Nothing is written if one of the files
+    /// changed since the plan was created.
+    ApplyPlan(plan::ApplyPlan),
     /// Patch all crates from a given cargo workspace in another given cargo workspace.
     ///
     /// This will get all crates from a given cargo workspace and add a patch
@@ -79,6 +619,11 @@ enum SubCommands {
     /// given cargo workspace. Essentially this is the same as using
     /// `.cargo/config`, but using a non-deprecated way.
     Patch(patch::Patch),
+    /// Remove the patches added by `patch` and `lockpatch`.
+    ///
+    /// The entries diener added to the `[patch]` sections of the workspace `Cargo.toml` are
+    /// marked with a comment. They are removed and the entries they replaced are restored.
+    Unpatch(unpatch::Unpatch),
     /// Creates a workspace from the supplied directory tree.
     ///
     /// This can be ran on existing workspaces to make sure everything is properly setup.
@@ -88,6 +633,57 @@ enum SubCommands {
     ///     - It will also be sorted alphabetically
     /// - The path dependency entries will be sorted into a canonical order.
     Workspacify(workspacify::Workspacify),
+    /// Moves dependencies of all workspace members into `[workspace.dependencies]`.
+    ///
+    /// - Every matching git (or, with `--crates-io`, registry) dependency is added to the
+    ///   `[workspace.dependencies]` table of the workspace `Cargo.toml`.
+    /// - The member dependencies will be rewritten to `foo = { workspace = true }`, keeping
+    ///   `features` and `optional`.
+    /// - Dependencies that are declared with different sources in different members are
+    ///   skipped.
+    Workspaceify(workspaceify::Workspaceify),
+    /// Convert the individual `sp-*`, `frame-*` and `pallet-*` dependencies into the
+    /// `polkadot-sdk` umbrella crate.
+    ///
+    /// - The dependencies are replaced by a single `polkadot-sdk` dependency with the same
+    ///   source, enabling a feature for every converted crate.
+    /// - Entries of the `[features]` table, e.g. `sp-io/std`, are rewritten in a best-effort
+    ///   way.
+    /// - With `--reverse`, the umbrella crate is converted back into individual dependencies.
+    Umbrella(umbrella::Umbrella),
+    /// Compare two `Cargo.lock` files.
+    ///
+    /// Prints all packages that were added, removed or changed their version, source or git
+    /// revision. The `Cargo.lock` files can be given as paths or urls.
+    Lockdiff(lockdiff::LockDiff),
+    /// Pin all git dependencies to the revisions of a given `Cargo.lock`.
+    ///
+    /// Adds a `[patch."URL"]` entry pointing to the locked `rev` for every git package of the
+    /// known repositories in the `Cargo.lock` to the workspace `Cargo.toml`. The `Cargo.lock`
+    /// can be given as path or url.
+    Lockpatch(lockpatch::LockPatch),
+    /// Print which branch/tag/rev the git dependencies at a given path point to.
+    ///
+    /// The dependencies are grouped by git repository and repositories that are used with
+    /// different references are flagged as inconsistent.
+    Info(info::Info),
+    /// Print all dependencies that are behind their latest release.
+    ///
+    /// Registry dependencies are compared against the latest version published on
+    /// `crates.io`, git dependencies against the latest release tag of their repository.
+    Outdated(outdated::Outdated),
+    /// Check that the project depends on a repository at the given version.
+    ///
+    /// Verifies that every dependency on the repository in the manifests and the `Cargo.lock`
+    /// uses the given `branch`, `rev` or `tag` and prints every mismatch. Nothing is modified.
+    CheckDependent(check_dependent::CheckDependent),
+    /// Check the environment before running any other subcommand.
+    ///
+    /// Verifies that crates.io and GitHub are reachable, the GitHub token is valid, the path
+    /// belongs to a cargo workspace and all manifests can be parsed. Nothing is modified.
+    Doctor(doctor::Doctor),
+    /// Replace the installed diener with the latest release from GitHub.
+    SelfUpdate(self_update::SelfUpdate),
 }
 
 /// Cli options of Diener
@@ -96,17 +692,134 @@ enum SubCommands {
     about = "Diener - dependency diener for replacing substrate, polkadot, cumulus or beefy versions in `Cargo.toml` files"
 )]
 struct Options {
+    /// The token used for requests to GitHub.
+    ///
+    /// Avoids the rate limits of anonymous requests and gives access to private repositories.
+    #[structopt(long, env = "GITHUB_TOKEN", hide_env_values = true, global = true)]
+    github_token: Option<String>,
+
+    /// The timeout in seconds for network requests, including `git` commands that access a
+    /// remote.
+    ///
+    /// Defaults to cargo's `http.timeout` or 30 seconds.
+    #[structopt(long, global = true)]
+    http_timeout: Option<u64>,
+
+    /// The number of network requests and lookups that run at the same time.
+    ///
+    /// Defaults to the number of CPUs, but at most 8. Use `--jobs 1` on CI runners with strict
+    /// limits for outgoing connections.
+    #[structopt(long, global = true)]
+    jobs: Option<NonZeroUsize>,
+
+    /// Don't lock the workspace for the duration of the run.
+    ///
+    /// By default, concurrent runs on the same workspace wait for each other, so their writes
+    /// can't interleave.
+    #[structopt(long, global = true)]
+    no_lock: bool,
+
+    /// The user agent that is sent with network requests.
+    #[structopt(long, default_value = http::DEFAULT_USER_AGENT, global = true)]
+    user_agent: String,
+
+    /// How long cached responses of crates.io and fetched `Cargo.lock` files stay valid, in
+    /// seconds.
+    ///
+    /// The responses are cached in `$XDG_CACHE_HOME/diener` or `~/.cache/diener`.
+    #[structopt(long, default_value = "3600", global = true)]
+    cache_ttl: u64,
+
+    /// Don't use the on-disk cache for network responses.
+    #[structopt(long, global = true)]
+    no_cache: bool,
+
+    /// Ignore the cached network responses and fetch them again.
+    #[structopt(long, global = true)]
+    refresh: bool,
+
+    /// The format of the log output.
+    ///
+    /// `json` prints one JSON object per log event, e.g. for indexing the logs in CI. All output
+    /// is logged then, events about a dependency carry the `manifest`, `dependency`, `old` and
+    /// `new` as separate fields.
+    #[structopt(long, default_value = "text", possible_values = &[ "text", "json" ], global = true)]
+    log_format: logging::LogFormat,
+
     #[structopt(subcommand)]
     subcommand: SubCommands,
 }
 
-fn main() -> anyhow::Result<()> {
-    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+fn main() {
+    let options = Options::from_args();
+    logging::init(options.log_format);
     log::info!("Running {} v{}", crate_name!(), crate_version!());
 
-    match Options::from_args().subcommand {
+    let code = match run(options) {
+        Ok(()) => exit_code::SUCCESS,
+        Err(e) => {
+            let code = exit_code::from_error(&e);
+            if code == exit_code::NOTHING_MATCHED {
+                log::warn!("{}", e);
+            } else if logging::is_json() {
+                log::error!("{:?}", e);
+            } else {
+                eprintln!("Error: {:?}", e);
+            }
+            code
+        }
+    };
+
+    process::exit(code)
+}
+
+fn run(options: Options) -> anyhow::Result<()> {
+    if let Some(jobs) = options.jobs {
+        jobs::configure(jobs);
+    }
+    if options.no_lock {
+        run_lock::disable();
+    }
+    let dir = env::current_dir().with_context(|| "Working directory is invalid.")?;
+    let net_config = net_config::NetConfig::load(&dir)?;
+    http::set_github_token(options.github_token);
+    http::configure(http::Settings {
+        timeout: options
+            .http_timeout
+            .map(Duration::from_secs)
+            .or(net_config.timeout)
+            .unwrap_or(Duration::from_secs(30)),
+        user_agent: options.user_agent,
+        offline: net_config.offline,
+        proxy: net_config.proxy,
+        cainfo: net_config.cainfo,
+    });
+    cache::configure(cache::Settings {
+        dir: (!options.no_cache).then(cache::default_dir).flatten(),
+        // Offline, every cached response is better than none.
+        ttl: if net_config.offline {
+            Duration::MAX
+        } else {
+            Duration::from_secs(options.cache_ttl)
+        },
+        refresh: options.refresh && !net_config.offline,
+    });
+
+    match options.subcommand {
         SubCommands::Update(update) => update.run(),
+        SubCommands::Plan(plan) => plan.run(),
+        SubCommands::ApplyPlan(apply_plan) => apply_plan.run(),
         SubCommands::Patch(patch) => patch.run(),
+        SubCommands::Unpatch(unpatch) => unpatch.run(),
         SubCommands::Workspacify(workspacify) => workspacify.run(),
+        SubCommands::Workspaceify(workspaceify) => workspaceify.run(),
+        SubCommands::Umbrella(umbrella) => umbrella.run(),
+        SubCommands::Lockdiff(lockdiff) => lockdiff.run(),
+        SubCommands::Lockpatch(lockpatch) => lockpatch.run(),
+        SubCommands::Info(info) => info.run(),
+        SubCommands::Outdated(outdated) => outdated.run(),
+        SubCommands::CheckDependent(check_dependent) => check_dependent.run(),
+        SubCommands::Doctor(doctor) => doctor.run(),
+        SubCommands::SelfUpdate(self_update) => self_update.run(),
     }
 }
@@ -0,0 +1,125 @@
+use crate::source_replacement;
+use anyhow::{anyhow, Context, Result};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+use toml_edit::Document;
+
+/// The network settings of cargo, so diener behaves like the cargo commands run next to it.
+///
+/// Read from the `[http]` and `[net]` tables of the cargo config files and the `CARGO_HTTP_*`
+/// and `CARGO_NET_OFFLINE` environment variables, which take precedence.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct NetConfig {
+    /// `net.offline`, no network requests are made at all.
+    pub offline: bool,
+    /// `http.proxy`, e.g. `host:port`.
+    pub proxy: Option<String>,
+    /// `http.cainfo`, a file with additional CA certificates.
+    pub cainfo: Option<PathBuf>,
+    /// `http.timeout`.
+    pub timeout: Option<Duration>,
+}
+
+impl NetConfig {
+    /// Load the settings that apply in `dir`.
+    pub(crate) fn load(dir: &Path) -> Result<Self> {
+        let mut config = Self::default();
+        for file in source_replacement::config_files(dir) {
+            let content = fs::read_to_string(&file)
+                .with_context(|| anyhow!("Failed to read cargo config {}", file.display()))?;
+            let doc = Document::from_str(&content)
+                .with_context(|| anyhow!("Failed to parse cargo config {}", file.display()))?;
+            // Relative paths are relative to the directory that contains the `.cargo` directory.
+            let base = file
+                .parent()
+                .and_then(Path::parent)
+                .unwrap_or_else(|| Path::new("."));
+            config.extend(&doc, base);
+        }
+
+        config.extend_from_env(|name| env::var(name).ok(), dir);
+        Ok(config)
+    }
+
+    fn extend(&mut self, doc: &Document, base: &Path) {
+        if let Some(offline) = doc
+            .get("net")
+            .and_then(|n| n.get("offline"))
+            .and_then(|o| o.as_bool())
+        {
+            self.offline = offline;
+        }
+
+        let http = match doc.get("http") {
+            Some(http) => http,
+            None => return,
+        };
+        if let Some(proxy) = http.get("proxy").and_then(|p| p.as_str()) {
+            self.proxy = Some(proxy.into()).filter(|p: &String| !p.is_empty());
+        }
+        if let Some(cainfo) = http.get("cainfo").and_then(|c| c.as_str()) {
+            self.cainfo = Some(base.join(cainfo));
+        }
+        if let Some(timeout) = http.get("timeout").and_then(|t| t.as_integer()) {
+            self.timeout = u64::try_from(timeout).ok().map(Duration::from_secs);
+        }
+    }
+
+    /// Apply the environment variables returned by `var`, relative paths are relative to `dir`.
+    fn extend_from_env(&mut self, var: impl Fn(&str) -> Option<String>, dir: &Path) {
+        if let Some(offline) = var("CARGO_NET_OFFLINE") {
+            self.offline = offline == "true";
+        }
+        if let Some(proxy) = var("CARGO_HTTP_PROXY") {
+            self.proxy = Some(proxy).filter(|p| !p.is_empty());
+        }
+        if let Some(cainfo) = var("CARGO_HTTP_CAINFO") {
+            self.cainfo = Some(dir.join(cainfo));
+        }
+        if let Some(timeout) = var("CARGO_HTTP_TIMEOUT").and_then(|t| t.parse().ok()) {
+            self.timeout = Some(Duration::from_secs(timeout));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn environment_takes_precedence() {
+        let doc = Document::from_str(
+            "[net]\noffline = false\n\n[http]\nproxy = \"proxy:3128\"\ncainfo = \"certs/ca.pem\"\ntimeout = 60\n",
+        )
+        .unwrap();
+        let mut config = NetConfig::default();
+        config.extend(&doc, Path::new("/project"));
+        assert_eq!(
+            config,
+            NetConfig {
+                offline: false,
+                proxy: Some("proxy:3128".into()),
+                cainfo: Some("/project/certs/ca.pem".into()),
+                timeout: Some(Duration::from_secs(60)),
+            }
+        );
+
+        config.extend_from_env(
+            |name| match name {
+                "CARGO_NET_OFFLINE" => Some("true".into()),
+                "CARGO_HTTP_PROXY" => Some("".into()),
+                "CARGO_HTTP_TIMEOUT" => Some("5".into()),
+                _ => None,
+            },
+            Path::new("/work"),
+        );
+        assert!(config.offline);
+        assert_eq!(config.proxy, None);
+        assert_eq!(config.cainfo, Some("/project/certs/ca.pem".into()));
+        assert_eq!(config.timeout, Some(Duration::from_secs(5)));
+    }
+}
@@ -0,0 +1,262 @@
+use crate::{
+    crates_io, git, jobs,
+    logging::{self, output},
+    repos::{split_reference, with_scheme, MatchOptions, Matcher, UrlScheme},
+    update::manifests,
+};
+use anyhow::{anyhow, ensure, Context, Result};
+use git_url_parse::GitUrl;
+use globset::GlobSet;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env::current_dir,
+    fs,
+    path::PathBuf,
+    str::FromStr,
+};
+use structopt::StructOpt;
+use toml_edit::{Document, Item};
+
+/// The organization that publishes the Polkadot ecosystem crates.
+const ECOSYSTEM_ORG: &str = "paritytech";
+
+/// The name prefixes of the crates published from the Polkadot ecosystem repositories.
+///
+/// Only crates with one of these prefixes are looked up on crates.io without `--all-crates`.
+const ECOSYSTEM_PREFIXES: &[&str] = &[
+    "sp-",
+    "sc-",
+    "frame-",
+    "pallet-",
+    "polkadot-",
+    "cumulus-",
+    "substrate-",
+    "staging-",
+    "xcm",
+    "bp-",
+    "bridge-",
+    "snowbridge-",
+    "parachains-",
+];
+
+/// `outdated` subcommand options.
+#[derive(Debug, StructOpt)]
+pub struct Outdated {
+    /// The path where Diener should search for `Cargo.toml` files.
+    #[structopt(long)]
+    path: Option<PathBuf>,
+
+    /// Check all dependencies, not only the ones of the Polkadot ecosystem.
+    #[structopt(long)]
+    all_crates: bool,
+
+    /// Compare against the latest stable version, ignoring pre-releases like `-rc1` or `-dev`.
+    ///
+    /// Git tags are always compared against the latest stable version.
+    #[structopt(long)]
+    stable: bool,
+
+    #[structopt(flatten)]
+    match_options: MatchOptions,
+}
+
+/// All dependencies found in the scanned manifests.
+#[derive(Default)]
+struct Dependencies {
+    /// crate name -> version requirements
+    registry: BTreeMap<String, BTreeSet<String>>,
+    /// repository -> references
+    git: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl Outdated {
+    /// Run this subcommand.
+    pub fn run(self) -> Result<()> {
+        let path = self
+            .path
+            .map(Ok)
+            .unwrap_or_else(|| current_dir().with_context(|| "Working directory is invalid."))?;
+        ensure!(
+            path.is_dir(),
+            "Path '{}' is not a directory.",
+            path.display()
+        );
+        let matcher = Matcher::new(self.match_options)?;
+
+        let manifests = manifests(&path, &GlobSet::empty()).collect::<Vec<_>>();
+        let docs = jobs::map(&manifests, |manifest| {
+            Document::from_str(&fs::read_to_string(manifest)?)
+                .with_context(|| anyhow!("Failed to parse {}", manifest.display()))
+        });
+
+        let mut deps = Dependencies::default();
+        for doc in docs {
+            doc?.iter()
+                .filter(|(k, _)| k.contains("dependencies"))
+                .filter_map(|(_, v)| v.as_table())
+                .flat_map(|t| t.iter())
+                .for_each(|(name, dep)| deps.add(name, dep, &matcher, self.all_crates));
+        }
+
+        let registry = deps
+            .registry
+            .iter()
+            .filter(|(name, _)| {
+                self.all_crates || ECOSYSTEM_PREFIXES.iter().any(|p| name.starts_with(p))
+            })
+            .collect::<Vec<_>>();
+        let crates = jobs::map(&registry, |(name, _)| crates_io::get_crate(name));
+
+        let mut rows = Vec::new();
+        for ((name, requirements), krate) in registry.into_iter().zip(crates) {
+            let krate = match krate? {
+                Some(krate) => krate,
+                None => {
+                    log::warn!("`{}` is not published on crates.io.", name);
+                    continue;
+                }
+            };
+
+            if !self.all_crates && !is_ecosystem_repository(krate.repository.as_deref()) {
+                continue;
+            }
+
+            let latest = if self.stable {
+                match krate.max_stable_version {
+                    Some(latest) => latest,
+                    None => continue,
+                }
+            } else {
+                krate.max_version
+            };
+
+            for requirement in requirements {
+                let behind =
+                    semver::VersionReq::parse(requirement).map_or(true, |r| !r.matches(&latest));
+                if behind {
+                    rows.push([name.clone(), requirement.clone(), latest.to_string()]);
+                }
+            }
+        }
+
+        let git = deps.git.iter().collect::<Vec<_>>();
+        let tags = jobs::map(&git, |(repo, _)| git::remote_tags(repo));
+        for ((repo, references), tags) in git.into_iter().zip(tags) {
+            let tags = match tags {
+                Ok(tags) => tags,
+                Err(e) => {
+                    log::warn!("Failed to fetch the tags of {}: {:#}", repo, e);
+                    references
+                        .iter()
+                        .for_each(|r| rows.push([repo.clone(), r.clone(), "unknown".into()]));
+                    continue;
+                }
+            };
+            let latest = match git::latest_version_tag(&tags) {
+                Some(latest) => latest,
+                None => continue,
+            };
+
+            references
+                .iter()
+                .filter(|r| **r != format!("tag = {}", latest))
+                .for_each(|r| rows.push([repo.clone(), r.clone(), format!("tag = {}", latest)]));
+        }
+
+        if rows.is_empty() {
+            output!("All dependencies are up to date.");
+        } else if logging::is_json() {
+            for [dependency, current, latest] in &rows {
+                log::info!(
+                    dependency = dependency.as_str(),
+                    old = current.as_str(),
+                    new = latest.as_str();
+                    "{}: {} is behind {}", dependency, current, latest
+                );
+            }
+        } else {
+            print_table(["Dependency", "Current", "Latest"], &rows);
+        }
+
+        Ok(())
+    }
+}
+
+impl Dependencies {
+    fn add(&mut self, name: &str, dep: &Item, matcher: &Matcher, all_crates: bool) {
+        if let Some(version) = dep.as_str() {
+            self.registry
+                .entry(name.into())
+                .or_default()
+                .insert(version.into());
+            return;
+        }
+
+        let dep = match dep.as_table_like() {
+            Some(dep) => dep,
+            None => return,
+        };
+        let get = |key| dep.get(key).and_then(|v| v.as_str());
+
+        let git = get("git").map(split_reference);
+        if let Some((git, embedded)) = git.and_then(|(g, e)| Some((GitUrl::parse(g).ok()?, e))) {
+            if !all_crates && matcher.classify(&git).is_none() {
+                return;
+            }
+
+            let reference = ["branch", "tag", "rev"]
+                .iter()
+                .find_map(|k| get(k).map(|v| format!("{} = {}", k, v)))
+                .or_else(|| embedded.map(|(k, v)| format!("{} = {}", k, v)))
+                .unwrap_or_else(|| "default branch".into());
+            self.git
+                .entry(with_scheme(&git, UrlScheme::Https).unwrap_or_else(|_| git.to_string()))
+                .or_default()
+                .insert(reference);
+        } else if let Some(version) = get("version") {
+            if dep.contains_key("path") || dep.contains_key("registry") {
+                return;
+            }
+
+            self.registry
+                .entry(get("package").unwrap_or(name).into())
+                .or_default()
+                .insert(version.into());
+        }
+    }
+}
+
+/// Returns `true` if the given repository belongs to the Polkadot ecosystem.
+fn is_ecosystem_repository(repository: Option<&str>) -> bool {
+    repository
+        .and_then(|r| GitUrl::parse(r).ok())
+        .is_some_and(|r| {
+            r.host.as_deref() == Some("github.com")
+                && r.owner
+                    .as_deref()
+                    .is_some_and(|o| o.eq_ignore_ascii_case(ECOSYSTEM_ORG))
+        })
+}
+
+fn print_table<const N: usize>(header: [&str; N], rows: &[[String; N]]) {
+    let mut widths = header.map(|h| h.len());
+    rows.iter().for_each(|row| {
+        row.iter()
+            .enumerate()
+            .for_each(|(i, c)| widths[i] = widths[i].max(c.len()))
+    });
+
+    let print_row = |row: [&str; N]| {
+        let line = row
+            .iter()
+            .enumerate()
+            .map(|(i, c)| format!("{:width$}", c, width = widths[i]))
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("{}", line.trim_end());
+    };
+
+    print_row(header);
+    rows.iter()
+        .for_each(|row| print_row(std::array::from_fn(|i| row[i].as_str())));
+}
@@ -1,3 +1,4 @@
+use crate::{atomic_write, config::Config, formatting::Formatting, run_lock, unpatch};
 use anyhow::{anyhow, bail, Context, Error, Result};
 use std::{
     env::current_dir,
@@ -6,7 +7,7 @@ use std::{
     str::FromStr,
 };
 use structopt::StructOpt;
-use toml_edit::{Document, Item, Value};
+use toml_edit::{Document, Item, Table, Value};
 
 enum PatchTarget {
     Crates,
@@ -148,6 +149,14 @@ pub struct Patch {
         conflicts_with_all = &[ "target", "substrate", "polkadot", "cumulus", "beefy" ]
     )]
     crates: bool,
+
+    /// Add the patches to the `.cargo/config.toml` next to the workspace `Cargo.toml` instead
+    /// of the `Cargo.toml` itself.
+    ///
+    /// Cargo and rust-analyzer pick up the patches from there, while the manifests stay
+    /// untouched. Remove them again with `diener unpatch --rust-analyzer-friendly`.
+    #[structopt(long)]
+    rust_analyzer_friendly: bool,
 }
 
 impl Patch {
@@ -168,7 +177,22 @@ impl Patch {
             })?;
 
         // Get the path to the `Cargo.toml` where we need to add the patches
-        let cargo_toml_to_patch = workspace_root_package(&path)?;
+        let mut cargo_toml_to_patch = workspace_root_package(&path)?;
+        let _lock = run_lock::acquire(workspace_dir(&cargo_toml_to_patch))?;
+        let formatting = Config::load(None, workspace_dir(&cargo_toml_to_patch))?.formatting;
+        if self.rust_analyzer_friendly {
+            cargo_toml_to_patch = override_file(&cargo_toml_to_patch);
+            if !cargo_toml_to_patch.exists() {
+                let dir = cargo_toml_to_patch
+                    .parent()
+                    .unwrap_or_else(|| Path::new("."));
+                fs::create_dir_all(dir)
+                    .and_then(|_| fs::write(&cargo_toml_to_patch, ""))
+                    .with_context(|| {
+                        anyhow!("Failed to create {}", cargo_toml_to_patch.display())
+                    })?;
+            }
+        }
 
         let point_to = PointTo::from_cli(
             self.point_to_git,
@@ -181,6 +205,7 @@ impl Patch {
             &patch_target,
             workspace_packages(&self.crates_to_patch)?,
             point_to,
+            formatting,
         )
     }
 
@@ -211,7 +236,7 @@ impl Patch {
     }
 }
 
-fn workspace_root_package(path: &Path) -> Result<PathBuf> {
+pub(crate) fn workspace_root_package(path: &Path) -> Result<PathBuf> {
     if path.ends_with("Cargo.toml") {
         return Ok(path.into());
     }
@@ -224,6 +249,17 @@ fn workspace_root_package(path: &Path) -> Result<PathBuf> {
     Ok(metadata.workspace_root.join("Cargo.toml").into())
 }
 
+/// Returns the directory of the workspace `manifest`.
+pub(crate) fn workspace_dir(manifest: &Path) -> &Path {
+    manifest.parent().unwrap_or_else(|| Path::new("."))
+}
+
+/// Returns the cargo config file next to the workspace `manifest` that holds the patches with
+/// `--rust-analyzer-friendly`.
+pub(crate) fn override_file(manifest: &Path) -> PathBuf {
+    workspace_dir(manifest).join(".cargo").join("config.toml")
+}
+
 /// Returns all package names of the given `workspace`.
 fn workspace_packages(workspace: &Path) -> Result<impl Iterator<Item = cargo_metadata::Package>> {
     let metadata = cargo_metadata::MetadataCommand::new()
@@ -243,10 +279,12 @@ fn add_patches_for_packages(
     patch_target: &PatchTarget,
     mut packages: impl Iterator<Item = cargo_metadata::Package>,
     point_to: PointTo,
+    formatting: Formatting,
 ) -> Result<()> {
     let content = fs::read_to_string(cargo_toml)
         .with_context(|| anyhow!("Failed to read manifest at {}", cargo_toml.display()))?;
-    let mut doc = Document::from_str(&content).context("Failed to parse Cargo.toml")?;
+    let mut doc = Document::from_str(&content)
+        .with_context(|| anyhow!("Failed to parse {}", cargo_toml.display()))?;
 
     let patch_table = doc
         .as_table_mut()
@@ -257,18 +295,25 @@ fn add_patches_for_packages(
 
     patch_table.set_implicit(true);
 
+    if !patch_table.contains_key(patch_target.as_str()) {
+        let mut table = Table::new();
+        unpatch::mark_table(&mut table);
+        patch_table.insert(patch_target.as_str(), Item::Table(table));
+    }
     let patch_target_table = patch_table
-        .entry(patch_target.as_str())
-        .or_insert(Item::Table(Default::default()))
-        .as_table_mut()
+        .get_mut(patch_target.as_str())
+        .and_then(|t| t.as_table_mut())
         .ok_or_else(|| anyhow!("Patch target table isn't a toml table!"))?;
 
     packages.try_for_each(|mut p| {
         log::info!("Adding patch for `{}`.", p.name);
 
+        let prefix = unpatch::marked_prefix(patch_target_table, &p.name);
         let patch = patch_target_table
             .entry(&p.name)
-            .or_insert(Item::Value(Value::InlineTable(Default::default())))
+            .or_insert(Item::Value(Value::InlineTable(Default::default())));
+        let style = formatting.style(patch);
+        let patch = patch
             .as_inline_table_mut()
             .ok_or_else(|| anyhow!("Patch entry for `{}` isn't an inline table!", p.name))?;
 
@@ -280,24 +325,21 @@ fn add_patches_for_packages(
 
         match &point_to {
             PointTo::Path => {
-                *patch.get_or_insert("path", "") =
-                    Value::from(path.display().to_string()).decorated(" ", " ");
+                style.insert(patch, "path", path.display().to_string());
             }
             PointTo::GitBranch { repository, branch } => {
-                *patch.get_or_insert("git", "") =
-                    Value::from(repository.clone()).decorated(" ", " ");
-                *patch.get_or_insert("branch", "") =
-                    Value::from(branch.clone()).decorated(" ", " ");
+                style.insert(patch, "git", repository.as_str());
+                style.insert(patch, "branch", branch.as_str());
             }
             PointTo::GitCommit { repository, commit } => {
-                *patch.get_or_insert("git", "") =
-                    Value::from(repository.clone()).decorated(" ", " ");
-                *patch.get_or_insert("rev", "") = Value::from(commit.clone()).decorated(" ", " ");
+                style.insert(patch, "git", repository.as_str());
+                style.insert(patch, "rev", commit.as_str());
             }
         }
+        unpatch::mark_entry(patch_target_table, &p.name, prefix);
         Ok::<_, Error>(())
     })?;
 
-    fs::write(cargo_toml, doc.to_string())
+    atomic_write::write(cargo_toml, doc.to_string())
         .with_context(|| anyhow!("Failed to write manifest to {}", cargo_toml.display()))
 }
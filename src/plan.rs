@@ -0,0 +1,294 @@
+use crate::{atomic_write, logging::output, report, report::Change, run_lock, update::Update};
+use anyhow::{anyhow, bail, Context, Result};
+use std::{
+    env::current_dir,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use structopt::StructOpt;
+use toml_edit::{Array, ArrayOfTables, Document, InlineTable, Item, Table, Value};
+
+/// The comment at the top of every plan.
+const HEADER: &str = "# Generated by `diener plan`, apply it with `diener apply-plan`.\n\
+                      # The `content` of every file is written as is, the `changes` are only \
+                      for review.\n\n";
+
+/// A file that is modified by a plan.
+#[derive(Debug)]
+pub(crate) struct File {
+    path: PathBuf,
+    /// The checksum of the file when the plan was created.
+    checksum: String,
+    changes: Vec<Change>,
+    /// The content of the file after applying the plan.
+    content: String,
+}
+
+impl File {
+    pub(crate) fn new(path: PathBuf, old: &str, content: String, changes: Vec<Change>) -> Self {
+        Self {
+            path,
+            checksum: checksum(old),
+            changes,
+            content,
+        }
+    }
+}
+
+/// `plan` subcommand options.
+#[derive(Debug, StructOpt)]
+pub struct Plan {
+    /// The file the plan is written to.
+    #[structopt(long)]
+    out: PathBuf,
+
+    #[structopt(flatten)]
+    update: Update,
+}
+
+impl Plan {
+    /// Run this subcommand.
+    pub fn run(self) -> Result<()> {
+        let files = self.update.plan()?;
+        let base = plan_dir(&self.out)?;
+
+        fs::write(&self.out, serialize(&files, &base)?)
+            .with_context(|| anyhow!("Failed to write the plan to {}", self.out.display()))?;
+
+        output!(
+            "Wrote the plan for {} files to {}.",
+            files.len(),
+            self.out.display()
+        );
+        Ok(())
+    }
+}
+
+/// `apply-plan` subcommand options.
+#[derive(Debug, StructOpt)]
+pub struct ApplyPlan {
+    /// The plan written by `diener plan`.
+    plan: PathBuf,
+}
+
+impl ApplyPlan {
+    /// Run this subcommand.
+    pub fn run(self) -> Result<()> {
+        let content = fs::read_to_string(&self.plan)
+            .with_context(|| anyhow!("Failed to read the plan {}", self.plan.display()))?;
+        let base = plan_dir(&self.plan)?;
+        let _lock = run_lock::acquire(&base)?;
+        let planned = deserialize(&content, &base)
+            .with_context(|| anyhow!("Failed to parse the plan {}", self.plan.display()))?;
+
+        let mut files = Vec::new();
+        let mut stale = Vec::new();
+        for file in planned {
+            let old = fs::read_to_string(&file.path)
+                .with_context(|| anyhow!("Failed to read {}", file.path.display()))?;
+            if old == file.content {
+                log::info!("Already applied: {}", file.path.display());
+                continue;
+            }
+            if checksum(&old) != file.checksum {
+                stale.push(file.path.display().to_string());
+                continue;
+            }
+
+            files.push(file);
+        }
+
+        // Nothing is written if only one of the files changed since the plan was created.
+        if !stale.is_empty() {
+            bail!(
+                "The following files changed since the plan was created:\n  {}\n\
+                 Create a new plan.",
+                stale.join("\n  ")
+            );
+        }
+
+        for file in &files {
+            log::info!("Applying: {}", file.path.display());
+            atomic_write::write(&file.path, &file.content)
+                .with_context(|| anyhow!("Failed to write {}", file.path.display()))?;
+            file.changes
+                .iter()
+                .for_each(|c| report::change(&file.path, c));
+        }
+
+        output!("Applied the plan to {} files.", files.len());
+        Ok(())
+    }
+}
+
+/// Serialize the planned `files`, their paths are written relative to `base`.
+fn serialize(files: &[File], base: &Path) -> Result<String> {
+    let mut tables = ArrayOfTables::new();
+    for file in files {
+        let path =
+            pathdiff::diff_paths(absolute(&file.path)?, base).unwrap_or_else(|| file.path.clone());
+
+        let mut changes = Array::new();
+        for change in &file.changes {
+            let mut table = InlineTable::new();
+            table.insert("name", change.name.as_str().into());
+            table.insert("old", change.old.as_str().into());
+            table.insert("new", change.new.as_str().into());
+            changes.push_formatted(Value::InlineTable(table).decorated("\n    ", ""));
+        }
+        changes.set_trailing("\n");
+        changes.set_trailing_comma(true);
+
+        let mut table = Table::new();
+        table.insert("path", toml_edit::value(path.to_string_lossy().as_ref()));
+        table.insert("checksum", toml_edit::value(file.checksum.as_str()));
+        table.insert("changes", toml_edit::value(changes));
+        table.insert("content", Item::Value(content_value(&file.content)));
+        tables.push(table);
+    }
+
+    let mut doc = Document::new();
+    doc.insert("file", Item::ArrayOfTables(tables));
+    Ok(format!("{}{}", HEADER, doc))
+}
+
+/// Parse the files of a plan, their paths are relative to `base`.
+fn deserialize(content: &str, base: &Path) -> Result<Vec<File>> {
+    let doc = Document::from_str(content)?;
+
+    let mut files = Vec::new();
+    for table in doc
+        .get("file")
+        .and_then(|f| f.as_array_of_tables())
+        .into_iter()
+        .flatten()
+    {
+        let get = |key| {
+            table
+                .get(key)
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow!("Invalid plan, a file has no `{}`.", key))
+        };
+
+        let changes = table
+            .get("changes")
+            .and_then(|c| c.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|c| c.as_inline_table())
+            .filter_map(|c| {
+                let get = |key| c.get(key).and_then(|v| v.as_str()).map(String::from);
+                Some(Change {
+                    name: get("name")?,
+                    old: get("old")?,
+                    new: get("new")?,
+                })
+            })
+            .collect();
+        files.push(File {
+            path: base.join(get("path")?),
+            checksum: get("checksum")?.into(),
+            changes,
+            content: get("content")?.into(),
+        });
+    }
+
+    Ok(files)
+}
+
+/// Returns the `content` of a file as a multi-line string, so it stays readable in the plan.
+///
+/// Falls back to a regular string if the content can not be represented as literal string.
+fn content_value(content: &str) -> Value {
+    let literal = !content.contains("'''")
+        && !content
+            .chars()
+            .any(|c| c.is_control() && c != '\n' && c != '\t');
+    literal
+        .then(|| Value::from_str(&format!("'''\n{}'''", content)).ok())
+        .flatten()
+        .filter(|v| v.as_str() == Some(content))
+        .unwrap_or_else(|| content.into())
+        .decorated(" ", "")
+}
+
+/// Returns the directory of the `plan`, the paths in the plan are relative to it.
+fn plan_dir(plan: &Path) -> Result<PathBuf> {
+    Ok(absolute(plan)?.parent().map(Into::into).unwrap_or_default())
+}
+
+/// Returns `path` as absolute path, relative paths are relative to the working directory.
+fn absolute(path: &Path) -> Result<PathBuf> {
+    if path.is_absolute() {
+        Ok(path.into())
+    } else {
+        Ok(current_dir()
+            .with_context(|| "Working directory is invalid.")?
+            .join(path))
+    }
+}
+
+/// Returns the checksum of `content`, to detect files that changed since the plan was created.
+///
+/// This is the 64 bit FNV-1a hash, which is good enough to detect changes and stable across
+/// platforms and versions.
+fn checksum(content: &str) -> String {
+    let hash = content
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    format!("fnv1a:{:016x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plan_round_trip() {
+        let base = Path::new("/project");
+        let change = Change {
+            name: "sp-io".into(),
+            old: "https://github.com/paritytech/substrate?branch=master".into(),
+            new: "https://github.com/paritytech/substrate?tag=v1.0.0".into(),
+        };
+        let files = vec![
+            File::new(
+                base.join("Cargo.toml"),
+                "[dependencies]\n",
+                "[dependencies]\nsp-io = { git = \"https://github.com/paritytech/substrate\", tag = \"v1.0.0\" }\n".into(),
+                vec![change.clone()],
+            ),
+            // Can not be written as literal string.
+            File::new(
+                base.join("nested/Cargo.toml"),
+                "",
+                "a = '''b'''\r\n".into(),
+                Vec::new(),
+            ),
+        ];
+
+        let content = serialize(&files, base).unwrap();
+        assert!(content.starts_with(HEADER));
+        assert!(content.contains("path = \"nested/Cargo.toml\""));
+
+        let parsed = deserialize(&content, base).unwrap();
+        assert_eq!(parsed.len(), files.len());
+        for (parsed, file) in parsed.iter().zip(&files) {
+            assert_eq!(parsed.path, file.path);
+            assert_eq!(parsed.checksum, file.checksum);
+            assert_eq!(parsed.content, file.content);
+            assert_eq!(parsed.changes.len(), file.changes.len());
+        }
+        assert_eq!(parsed[0].changes[0].name, change.name);
+        assert_eq!(parsed[0].changes[0].old, change.old);
+        assert_eq!(parsed[0].changes[0].new, change.new);
+    }
+
+    #[test]
+    fn reject_invalid_plan() {
+        assert!(deserialize("[[file]]\npath = \"Cargo.toml\"", Path::new("/")).is_err());
+    }
+}
@@ -0,0 +1,139 @@
+use crate::{audit, git, http};
+use anyhow::{anyhow, ensure, Result};
+use git_url_parse::GitUrl;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The default title of the pull request.
+const DEFAULT_TITLE: &str = "Update {rewritten} dependencies";
+
+/// The default body of the pull request.
+const DEFAULT_BODY: &str = "Rewritten by diener:\n\n{changes}";
+
+/// The pull request that is opened for the changes with `--open-pr`.
+#[derive(Debug, Clone)]
+pub(crate) struct PullRequest {
+    /// The git remote the branch is pushed to.
+    pub remote: String,
+    /// The branch the changes are committed to.
+    pub branch: Option<String>,
+    /// The title template, see [`expand`].
+    pub title: Option<String>,
+    /// The body template, see [`expand`].
+    pub body: Option<String>,
+}
+
+impl PullRequest {
+    /// Returns the owner and name of the GitHub repository the `remote` of the repository at
+    /// `dir` points to.
+    pub(crate) fn repository(&self, dir: &Path) -> Result<(String, String)> {
+        let remote_url = git::remote_url(dir, &self.remote)?;
+        let remote = GitUrl::parse(&remote_url)
+            .map_err(|e| anyhow!("Failed to parse the url of `{}`: {}", self.remote, e))?;
+        ensure!(
+            remote.host.as_deref() == Some("github.com"),
+            "Pull requests can only be opened on GitHub, `{}` is {}.",
+            self.remote,
+            remote_url
+        );
+        let owner = remote
+            .owner
+            .ok_or_else(|| anyhow!("The url of `{}` has no owner.", self.remote))?;
+
+        Ok((owner, remote.name))
+    }
+
+    /// Commit the modified `files` of the repository at `dir` to a new branch, push it and
+    /// open a pull request for it.
+    ///
+    /// Returns the url of the pull request.
+    pub(crate) fn open(
+        &self,
+        dir: &Path,
+        files: &[audit::File],
+        rewritten: usize,
+    ) -> Result<String> {
+        #[derive(Serialize)]
+        struct NewPullRequest<'a> {
+            title: &'a str,
+            body: &'a str,
+            head: &'a str,
+            base: &'a str,
+        }
+
+        #[derive(Deserialize)]
+        struct Created {
+            html_url: String,
+        }
+
+        let (owner, name) = self.repository(dir)?;
+
+        let base = git::current_branch(dir)?;
+        let branch = self.branch.clone().unwrap_or_else(|| {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default();
+            format!("diener/update-{}", timestamp)
+        });
+        let title = expand(
+            self.title.as_deref().unwrap_or(DEFAULT_TITLE),
+            dir,
+            files,
+            rewritten,
+        );
+        let body = expand(
+            self.body.as_deref().unwrap_or(DEFAULT_BODY),
+            dir,
+            files,
+            rewritten,
+        );
+
+        log::info!("Committing the changes to branch `{}`", branch);
+        // `git -C` resolves relative paths from `dir`.
+        let paths = files
+            .iter()
+            .map(|f| f.path.strip_prefix(dir).unwrap_or(&f.path).into())
+            .collect::<Vec<PathBuf>>();
+        git::commit_to_new_branch(dir, &branch, &paths, &title)?;
+        git::push(dir, &self.remote, &branch)?;
+
+        let created: Created = http::github_api_post(
+            &format!("repos/{}/{}/pulls", owner, name),
+            &NewPullRequest {
+                title: &title,
+                body: &body,
+                head: &branch,
+                base: &base,
+            },
+        )?;
+        Ok(created.html_url)
+    }
+}
+
+/// Expand the placeholders of a title or body `template`.
+///
+/// - `{rewritten}`: the number of rewritten dependencies
+/// - `{files}`: the number of modified files
+/// - `{changes}`: a markdown list of the changed dependencies per file
+fn expand(template: &str, dir: &Path, files: &[audit::File], rewritten: usize) -> String {
+    let mut changes = String::new();
+    for file in files {
+        let path = file.path.strip_prefix(dir).unwrap_or(&file.path);
+        changes.push_str(&format!("- `{}`\n", path.display()));
+        for change in file.changes.iter().filter(|c| c.old != c.new) {
+            changes.push_str(&format!(
+                "  - `{}`: {} -> {}\n",
+                change.name, change.old, change.new
+            ));
+        }
+    }
+
+    template
+        .replace("{rewritten}", &rewritten.to_string())
+        .replace("{files}", &files.len().to_string())
+        .replace("{changes}", &changes)
+}
@@ -0,0 +1,220 @@
+use crate::{audit, logging};
+use anyhow::{bail, Result};
+use serde::Serialize;
+use std::{
+    env, fs,
+    io::{self, IsTerminal},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::OnceLock,
+};
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const RESET: &str = "\x1b[0m";
+
+/// Returns `true` if the output should be colored.
+///
+/// Colors are only used when stdout is a terminal and `NO_COLOR` isn't set.
+fn colored() -> bool {
+    static COLORED: OnceLock<bool> = OnceLock::new();
+    *COLORED.get_or_init(|| io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none())
+}
+
+/// Formats a git source like in a `Cargo.lock`, e.g. `https://github.com/foo/bar?branch=master`.
+pub(crate) fn git_source(url: &str, reference: Option<(&str, &str)>) -> String {
+    match reference {
+        Some((key, value)) => format!("{}?{}={}", url, key, value),
+        None => url.into(),
+    }
+}
+
+/// A rewritten dependency.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct Change {
+    /// The name of the dependency.
+    pub name: String,
+    /// The source before the rewrite, e.g. `https://github.com/foo/bar?branch=master` or `1.0`.
+    pub old: String,
+    /// The source after the rewrite.
+    pub new: String,
+}
+
+/// Print that the source of the dependency in `manifest` changed.
+///
+/// Dependencies whose source didn't change aren't printed. With JSON logging, the change is
+/// logged instead.
+pub(crate) fn change(manifest: &Path, change: &Change) {
+    let Change { name, old, new } = change;
+    if old == new {
+        return;
+    }
+
+    if logging::is_json() {
+        log::info!(
+            manifest:% = manifest.display(),
+            dependency = name.as_str(),
+            old = old.as_str(),
+            new = new.as_str();
+            "{}: {} -> {}", name, old, new
+        );
+    } else if colored() {
+        println!(
+            "{}: {}{}{} -> {}{}{}",
+            name, RED, old, RESET, GREEN, new, RESET
+        );
+    } else {
+        println!("{}: {} -> {}", name, old, new);
+    }
+}
+
+/// The format of the summary written by `--report`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Format {
+    /// A table of all changes, see [`markdown`].
+    Markdown,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "markdown" => Ok(Self::Markdown),
+            _ => bail!("Unknown `--report` format `{}`, expected `markdown`.", s),
+        }
+    }
+}
+
+/// Returns a markdown table of the changes in the modified `files`, to be posted as a pull
+/// request comment.
+///
+/// The paths of the files are relative to the `roots` they were found in.
+pub(crate) fn markdown(files: &[audit::File], roots: &[PathBuf]) -> String {
+    let rows = files
+        .iter()
+        .flat_map(|file| {
+            let path = roots
+                .iter()
+                .find_map(|r| file.path.strip_prefix(r).ok())
+                .unwrap_or(&file.path);
+            file.changes
+                .iter()
+                .filter(|c| c.old != c.new)
+                .map(move |c| {
+                    format!(
+                        "| `{}` | `{}` | `{}` → `{}` |\n",
+                        escape_cell(&c.name),
+                        escape_cell(&path.display().to_string()),
+                        escape_cell(&c.old),
+                        escape_cell(&c.new)
+                    )
+                })
+        })
+        .collect::<Vec<_>>();
+
+    if rows.is_empty() {
+        return "No dependencies were rewritten.\n".into();
+    }
+
+    let files = files
+        .iter()
+        .filter(|f| f.changes.iter().any(|c| c.old != c.new))
+        .count();
+    format!(
+        "Rewrote {} dependenc{} in {} file{}.\n\n| Crate | File | Change |\n| --- | --- | --- |\n{}",
+        rows.len(),
+        if rows.len() == 1 { "y" } else { "ies" },
+        files,
+        if files == 1 { "" } else { "s" },
+        rows.concat()
+    )
+}
+
+/// Escapes the `|` that would end a table cell.
+fn escape_cell(cell: &str) -> String {
+    cell.replace('|', "\\|")
+}
+
+/// Returns `true` if diener runs in a GitHub Actions workflow.
+pub(crate) fn in_github_actions() -> bool {
+    env::var_os("GITHUB_ACTIONS").is_some_and(|v| v == "true")
+}
+
+/// Print a GitHub Actions `error` annotation for the given `line` of `file`.
+///
+/// GitHub shows these inline in the diff of a pull request. Annotations are always printed as
+/// workflow commands, also with JSON logging, as GitHub doesn't recognize them otherwise. The `file` is made relative to the
+/// checkout of the repository, as GitHub expects.
+pub(crate) fn github_error(file: &Path, line: Option<usize>, message: &str) {
+    let file = fs::canonicalize(file).unwrap_or_else(|_| file.into());
+    let workspace = env::var_os("GITHUB_WORKSPACE").and_then(|w| fs::canonicalize(w).ok());
+    let file = workspace
+        .and_then(|w| file.strip_prefix(w).ok().map(Into::into))
+        .unwrap_or(file);
+
+    let mut properties = format!("file={}", escape_property(&file.to_string_lossy()));
+    if let Some(line) = line {
+        properties.push_str(&format!(",line={}", line));
+    }
+    println!("::error {}::{}", properties, escape_data(message));
+}
+
+/// Escapes the message of a workflow command.
+fn escape_data(data: &str) -> String {
+    data.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escapes a property value of a workflow command.
+fn escape_property(property: &str) -> String {
+    escape_data(property)
+        .replace(':', "%3A")
+        .replace(',', "%2C")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(name: &str, old: &str, new: &str) -> Change {
+        Change {
+            name: name.into(),
+            old: old.into(),
+            new: new.into(),
+        }
+    }
+
+    #[test]
+    fn markdown_lists_the_changes() {
+        let files = [
+            audit::File {
+                path: "/project/node/Cargo.toml".into(),
+                changes: vec![
+                    change(
+                        "sp-io",
+                        "https://github.com/paritytech/substrate?branch=master",
+                        "https://github.com/paritytech/substrate?tag=v1.0",
+                    ),
+                    change("sp-core", "1.0", "1.0"),
+                ],
+            },
+            audit::File {
+                path: "/other/Cargo.toml".into(),
+                changes: vec![change("serde", "1.0", ">=1.0|2")],
+            },
+        ];
+
+        assert_eq!(
+            markdown(&files, &["/project".into()]),
+            "Rewrote 2 dependencies in 2 files.\n\n\
+             | Crate | File | Change |\n\
+             | --- | --- | --- |\n\
+             | `sp-io` | `node/Cargo.toml` | `https://github.com/paritytech/substrate?branch=master` \
+             → `https://github.com/paritytech/substrate?tag=v1.0` |\n\
+             | `serde` | `/other/Cargo.toml` | `1.0` → `>=1.0\\|2` |\n"
+        );
+        assert_eq!(markdown(&[], &[]), "No dependencies were rewritten.\n");
+    }
+}
@@ -0,0 +1,638 @@
+use crate::update::Rewrite;
+use anyhow::{anyhow, bail, Context, Result};
+use git_url_parse::{GitUrl, Scheme};
+use regex::Regex;
+use std::str::FromStr;
+use structopt::StructOpt;
+
+/// The repositories diener knows about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Repo {
+    Substrate,
+    Polkadot,
+    Cumulus,
+    Beefy,
+}
+
+impl Repo {
+    pub(crate) const ALL: [Self; 4] = [Self::Substrate, Self::Polkadot, Self::Cumulus, Self::Beefy];
+
+    /// The name of the git repository.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Self::Substrate => "substrate",
+            Self::Polkadot => "polkadot",
+            Self::Cumulus => "cumulus",
+            Self::Beefy => "grandpa-bridge-gadget",
+        }
+    }
+
+    /// The url of the official git repository.
+    pub(crate) fn url(self) -> String {
+        format!("https://github.com/paritytech/{}", self.name())
+    }
+
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|r| r.name() == name)
+    }
+
+    /// Returns the rewrite for this repository, using the given `git` url.
+    pub(crate) fn rewrite(self, git: Option<String>) -> Rewrite {
+        match self {
+            Self::Substrate => Rewrite::Substrate(git),
+            Self::Polkadot => Rewrite::Polkadot(git),
+            Self::Cumulus => Rewrite::Cumulus(git),
+            Self::Beefy => Rewrite::Beefy(git),
+        }
+    }
+
+    /// Parse the name used for the repository in the config file, e.g. `substrate`.
+    pub(crate) fn from_config_name(name: &str) -> Option<Self> {
+        match name {
+            "substrate" => Some(Self::Substrate),
+            "polkadot" => Some(Self::Polkadot),
+            "cumulus" => Some(Self::Cumulus),
+            "beefy" => Some(Self::Beefy),
+            _ => None,
+        }
+    }
+}
+
+/// Classifies the git urls matching `pattern` as `repo`.
+///
+/// The pattern is matched against the url in the form `host[:port]/owner/name`, lowercased and
+/// without scheme, user and `.git` suffix, e.g. `github.com/paritytech/substrate`.
+#[derive(Debug, Clone)]
+pub(crate) struct ClassifyRule {
+    pattern: Regex,
+    /// `None` for urls that don't belong to any of the known repositories.
+    repo: Option<Repo>,
+}
+
+impl ClassifyRule {
+    /// Create a new rule, fails if the `pattern` isn't a valid regex.
+    pub(crate) fn new(pattern: &str, repo: Option<Repo>) -> Result<Self> {
+        Ok(Self {
+            pattern: Regex::new(pattern)
+                .with_context(|| anyhow!("Invalid pattern `{}`", pattern))?,
+            repo,
+        })
+    }
+
+    /// Create a rule for all urls starting with the given url `prefix`.
+    ///
+    /// Only whole path segments are compared, so `github.com/foo` doesn't match
+    /// `github.com/foobar/substrate`.
+    fn prefix(repo: Repo, prefix: &str) -> Self {
+        Self {
+            pattern: literal(&format!("^{}(/|$)", regex::escape(&normalize_url(prefix)))),
+            repo: Some(repo),
+        }
+    }
+
+    /// Create a rule for exactly the repository at `git`.
+    fn exact(repo: Repo, git: &GitUrl) -> Self {
+        Self {
+            pattern: literal(&format!("^{}$", regex::escape(&location(git)))),
+            repo: Some(repo),
+        }
+    }
+
+    /// Create a rule for all repositories with the name of `repo`, independent of the owner
+    /// and host.
+    fn name(repo: Repo) -> Self {
+        Self {
+            pattern: literal(&format!("/{}$", regex::escape(repo.name()))),
+            repo: Some(repo),
+        }
+    }
+}
+
+/// Compile a pattern built from escaped urls, which is always valid.
+fn literal(pattern: &str) -> Regex {
+    Regex::new(pattern).expect("Escaped patterns are valid")
+}
+
+/// Options for matching dependencies against the known repositories.
+#[derive(Debug, StructOpt)]
+pub(crate) struct MatchOptions {
+    /// An additional git url that should be accepted for one of the known repositories.
+    ///
+    /// By default only the official `paritytech` repositories are matched. The repository
+    /// is determined by the name of the repository in the url, e.g.
+    /// `https://github.com/myorg/substrate` is treated as Substrate. Any git host is
+    /// supported, e.g. `https://gitlab.mycorp.io/chain/forks/substrate`.
+    #[structopt(long)]
+    expected_url: Vec<String>,
+
+    /// Match dependencies only by the name of the git repository.
+    ///
+    /// This is the legacy behavior that treats any repository called `substrate` as
+    /// Substrate, independent of the owner or host.
+    #[structopt(long)]
+    match_name_only: bool,
+
+    /// Only match dependencies from repositories of the given owner/organization.
+    ///
+    /// For nested (GitLab) groups, `group` also matches repositories in `group/subgroup`.
+    /// Can be passed multiple times. As only the official repositories are matched by
+    /// default, this is mostly useful together with `--expected-url` or `--match-name-only`.
+    #[structopt(long)]
+    match_org: Vec<String>,
+
+    /// Only match dependencies whose git url starts with the given prefix.
+    ///
+    /// The scheme is ignored, so `https://github.com/myorg` also matches
+    /// `git@github.com:myorg/substrate.git`. Can be passed multiple times.
+    #[structopt(long)]
+    match_url: Vec<String>,
+}
+
+/// Decides to which of the known repositories a git url belongs.
+///
+/// The first matching rule wins, the rules of the config file are checked before the built-in
+/// rules for the official repositories, `--expected-url` and `--match-name-only`.
+#[derive(Debug)]
+pub(crate) struct Matcher {
+    rules: Vec<ClassifyRule>,
+    builtin_rules: Vec<ClassifyRule>,
+    name_only: bool,
+    orgs: Vec<String>,
+    url_prefixes: Vec<String>,
+}
+
+impl Matcher {
+    /// Create a new instance from the given options.
+    pub(crate) fn new(options: MatchOptions) -> Result<Self> {
+        let mut builtin_rules = Repo::ALL
+            .into_iter()
+            .map(|r| {
+                GitUrl::parse(&r.url())
+                    .map(|url| ClassifyRule::exact(r, &url))
+                    .map_err(|e| anyhow!("{}", e))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        for url in options.expected_url {
+            let git = GitUrl::parse(&url)
+                .map_err(|e| anyhow!("{}", e))
+                .with_context(|| anyhow!("Invalid `--expected-url`: {}", url))?;
+            let repo = Repo::from_name(&git.name).ok_or_else(|| {
+                anyhow!(
+                    "`--expected-url` {} doesn't point to any of the known repositories.",
+                    url
+                )
+            })?;
+            builtin_rules.push(ClassifyRule::exact(repo, &git));
+        }
+
+        if options.match_name_only {
+            builtin_rules.extend(Repo::ALL.into_iter().map(ClassifyRule::name));
+        }
+
+        Ok(Self {
+            rules: Vec::new(),
+            builtin_rules,
+            name_only: options.match_name_only,
+            orgs: options.match_org,
+            url_prefixes: options.match_url.iter().map(|p| normalize_url(p)).collect(),
+        })
+    }
+
+    /// Treat all urls starting with one of the given prefixes as the associated repository.
+    pub(crate) fn with_url_rules(mut self, rules: &[(Repo, String)]) -> Self {
+        self.rules
+            .extend(rules.iter().map(|(repo, p)| ClassifyRule::prefix(*repo, p)));
+        self
+    }
+
+    /// Check the given rules before the built-in ones, in order.
+    pub(crate) fn with_classify_rules(mut self, rules: &[ClassifyRule]) -> Self {
+        self.rules.extend(rules.iter().cloned());
+        self
+    }
+
+    /// Returns `true` if only the repository name should be taken into account.
+    pub(crate) fn name_only(&self) -> bool {
+        self.name_only
+    }
+
+    /// Returns `true` if the given `git` url passes the `--match-org`/`--match-url` filters.
+    pub(crate) fn is_selected(&self, git: &GitUrl) -> bool {
+        let path = repository_path(git);
+
+        // GitLab supports nested groups, so the owner is everything in front of the name.
+        let namespace = path.rsplit_once('/').map_or("", |(namespace, _)| namespace);
+        let org_matches = self.orgs.is_empty()
+            || self.orgs.iter().any(|org| {
+                let org = org.trim_matches('/').to_lowercase();
+                namespace == org || namespace.starts_with(&format!("{}/", org))
+            });
+
+        let url_matches = self.url_prefixes.is_empty() || {
+            let location = location(git);
+            self.url_prefixes.iter().any(|p| has_prefix(&location, p))
+        };
+
+        org_matches && url_matches
+    }
+
+    /// Returns the known repository the given `git` url belongs to.
+    pub(crate) fn classify(&self, git: &GitUrl) -> Option<Repo> {
+        let location = location(git);
+        self.rules
+            .iter()
+            .chain(&self.builtin_rules)
+            .find(|r| r.pattern.is_match(&location))
+            .and_then(|r| r.repo)
+    }
+}
+
+/// Checks if both urls point to the same repository, ignoring the scheme and `.git` suffix.
+///
+/// The full repository path is compared, to distinguish repositories in nested (GitLab) groups.
+pub(crate) fn same_repository(a: &GitUrl, b: &GitUrl) -> bool {
+    let host_eq = match (&a.host, &b.host) {
+        (Some(a), Some(b)) => a.eq_ignore_ascii_case(b),
+        (None, None) => true,
+        _ => false,
+    };
+
+    host_eq && repository_path(a) == repository_path(b)
+}
+
+/// Returns the path of the repository on its host, e.g. `paritytech/substrate`.
+///
+/// For repositories in nested groups, all groups are part of the path.
+fn repository_path(git: &GitUrl) -> String {
+    git.path
+        .trim_matches('/')
+        .trim_end_matches(".git")
+        .to_lowercase()
+}
+
+/// Returns the host of the repository, including the port if the url has one.
+fn host(git: &GitUrl) -> String {
+    let host = git.host.as_deref().unwrap_or_default();
+    match git.port {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_string(),
+    }
+}
+
+/// Returns the location of the repository in the form `host[:port]/owner/name`.
+fn location(git: &GitUrl) -> String {
+    format!("{}/{}", host(git), repository_path(git)).to_lowercase()
+}
+
+/// Checks if the `location` starts with the given url `prefix`.
+///
+/// Only whole path segments are compared, so `github.com/foo` doesn't match
+/// `github.com/foobar/substrate`.
+fn has_prefix(location: &str, prefix: &str) -> bool {
+    location == prefix
+        || location
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Brings the given url into the form `host/owner/name`, dropping scheme, user and `.git` suffix.
+fn normalize_url(url: &str) -> String {
+    let url = url.trim().to_lowercase();
+    let (url, scp_like) = match url.split_once("://") {
+        Some((_, rest)) => (rest.to_string(), false),
+        None => (url, true),
+    };
+
+    let url = match url.split_once('@') {
+        Some((user, rest)) if !user.contains('/') => rest.to_string(),
+        _ => url,
+    };
+
+    // `git@github.com:owner/repo` style urls separate the host with a `:`.
+    let url = match url.split_once(':') {
+        Some((host, rest)) if scp_like && !host.contains('/') => format!("{}/{}", host, rest),
+        _ => url,
+    };
+
+    url.trim_end_matches('/')
+        .trim_end_matches(".git")
+        .to_string()
+}
+
+/// Returns `true` if the given `--git` url contains a `{org}` or `{repo}` placeholder.
+pub(crate) fn is_git_template(template: &str) -> bool {
+    template.contains("{org}") || template.contains("{repo}")
+}
+
+/// Replaces the `{org}` and `{repo}` placeholders in the given `template` with the owner and
+/// name of the `git` url.
+///
+/// For repositories in nested groups, `{org}` is replaced by all groups.
+pub(crate) fn expand_git_template(template: &str, git: &GitUrl) -> String {
+    if !is_git_template(template) {
+        return template.into();
+    }
+
+    let path = git.path.trim_matches('/').trim_end_matches(".git");
+    let (org, repo) = path.rsplit_once('/').unwrap_or(("", path));
+    template.replace("{org}", org).replace("{repo}", repo)
+}
+
+/// A `branch`, `tag` or `rev` as `(key, value)`.
+pub(crate) type Reference = (&'static str, String);
+
+/// Splits a reference that is embedded into the given git `url` off the url.
+///
+/// Supports the `?branch=NAME`, `?tag=NAME` and `?rev=SHA` queries known from `Cargo.lock` and
+/// a trailing `#SHA` fragment. A query takes precedence over a fragment.
+pub(crate) fn split_reference(url: &str) -> (&str, Option<Reference>) {
+    let (url, fragment) = match url.split_once('#') {
+        Some((url, fragment)) => (url, Some(fragment)),
+        None => (url, None),
+    };
+    let (url, query) = match url.split_once('?') {
+        Some((url, query)) => (url, Some(query)),
+        None => (url, None),
+    };
+
+    let reference = query
+        .into_iter()
+        .flat_map(|q| q.split('&'))
+        .filter_map(|p| p.split_once('='))
+        .find_map(|(key, value)| {
+            ["branch", "tag", "rev"]
+                .into_iter()
+                .find(|k| *k == key)
+                .map(|k| (k, value.to_string()))
+        })
+        .or_else(|| {
+            fragment
+                .filter(|f| !f.is_empty())
+                .map(|f| ("rev", f.to_string()))
+        });
+
+    (url, reference)
+}
+
+/// The scheme of a git url.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum UrlScheme {
+    Https,
+    Ssh,
+}
+
+impl FromStr for UrlScheme {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "https" => Ok(Self::Https),
+            "ssh" => Ok(Self::Ssh),
+            _ => bail!("Unknown url scheme `{}`, expected `https` or `ssh`.", s),
+        }
+    }
+}
+
+/// Returns the url of the given repository using the requested `scheme`.
+///
+/// `ssh` urls use the `ssh://git@host/path.git` form, as Cargo doesn't support the
+/// `git@host:path` shorthand.
+///
+/// A custom port is kept, but it can not be carried over to the other scheme, as the port
+/// belongs to the service behind it.
+pub(crate) fn with_scheme(git: &GitUrl, scheme: UrlScheme) -> Result<String> {
+    let path = git.path.trim_matches('/').trim_end_matches(".git");
+    let current = match git.scheme {
+        Scheme::Https => Some(UrlScheme::Https),
+        Scheme::Ssh | Scheme::GitSsh => Some(UrlScheme::Ssh),
+        _ => None,
+    };
+    if let Some(port) = git.port.filter(|_| current != Some(scheme)) {
+        bail!(
+            "Can not switch {} to {}, the port {} only applies to its current scheme.",
+            git,
+            match scheme {
+                UrlScheme::Https => "https",
+                UrlScheme::Ssh => "ssh",
+            },
+            port
+        );
+    }
+
+    match scheme {
+        UrlScheme::Https => Ok(format!("https://{}/{}", host(git), path)),
+        UrlScheme::Ssh => Ok(format!("ssh://git@{}/{}.git", host(git), path)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher(match_url: &[&str]) -> Matcher {
+        Matcher::new(MatchOptions {
+            expected_url: Vec::new(),
+            match_name_only: true,
+            match_org: Vec::new(),
+            match_url: match_url.iter().map(|u| u.to_string()).collect(),
+        })
+        .unwrap()
+    }
+
+    fn git(url: &str) -> GitUrl {
+        GitUrl::parse(url).unwrap()
+    }
+
+    #[test]
+    fn match_url_only_matches_whole_path_segments() {
+        let matcher = matcher(&["https://github.com/paritytech"]);
+
+        assert!(matcher.is_selected(&git("https://github.com/paritytech/substrate")));
+        assert!(matcher.is_selected(&git("git@github.com:paritytech/substrate.git")));
+        assert!(!matcher.is_selected(&git("https://github.com/paritytechfork/substrate")));
+    }
+
+    #[test]
+    fn url_rules_only_match_whole_path_segments() {
+        let matcher = matcher(&[])
+            .with_url_rules(&[(Repo::Substrate, "https://git.corp.io/chain/sdk".into())]);
+
+        assert_eq!(
+            matcher.classify(&git("https://git.corp.io/chain/sdk/core")),
+            Some(Repo::Substrate)
+        );
+        assert_eq!(
+            matcher.classify(&git("https://git.corp.io/chain/sdk-core")),
+            None
+        );
+    }
+
+    #[test]
+    fn classify_rules_are_checked_in_order() {
+        let matcher = matcher(&[]).with_classify_rules(&[
+            ClassifyRule::new("^github\\.com/unrelated/substrate$", None).unwrap(),
+            ClassifyRule::new("^github\\.com/myorg/(polkadot-)?sdk$", Some(Repo::Polkadot))
+                .unwrap(),
+        ]);
+
+        assert_eq!(
+            matcher.classify(&git("https://github.com/myorg/polkadot-sdk")),
+            Some(Repo::Polkadot)
+        );
+        assert_eq!(
+            matcher.classify(&git("git@github.com:MyOrg/SDK.git")),
+            Some(Repo::Polkadot)
+        );
+        assert_eq!(
+            matcher.classify(&git("https://github.com/unrelated/substrate")),
+            None
+        );
+        // The built-in rules still apply.
+        assert_eq!(
+            matcher.classify(&git("https://github.com/myorg/substrate")),
+            Some(Repo::Substrate)
+        );
+        assert!(ClassifyRule::new("(", None).is_err());
+    }
+
+    #[test]
+    fn location_includes_the_port() {
+        assert_eq!(
+            location(&git("ssh://git@git.corp.io:2222/Chain/SDK.git")),
+            "git.corp.io:2222/chain/sdk"
+        );
+        assert_eq!(
+            location(&git("https://git.corp.io:8443/chain/sdk")),
+            "git.corp.io:8443/chain/sdk"
+        );
+        assert_eq!(
+            location(&git("git@github.com:paritytech/substrate.git")),
+            "github.com/paritytech/substrate"
+        );
+    }
+
+    #[test]
+    fn match_url_respects_the_port() {
+        let matcher = matcher(&["ssh://git@git.corp.io:2222/chain"]);
+
+        assert!(matcher.is_selected(&git("ssh://git@git.corp.io:2222/chain/sdk.git")));
+        assert!(!matcher.is_selected(&git("https://git.corp.io/chain/sdk")));
+    }
+
+    #[test]
+    fn with_scheme_switches_the_scheme() {
+        let git = git("git@github.com:paritytech/substrate.git");
+
+        assert_eq!(
+            with_scheme(&git, UrlScheme::Https).unwrap(),
+            "https://github.com/paritytech/substrate"
+        );
+        assert_eq!(
+            with_scheme(&git, UrlScheme::Ssh).unwrap(),
+            "ssh://git@github.com/paritytech/substrate.git"
+        );
+    }
+
+    #[test]
+    fn with_scheme_keeps_the_port() {
+        assert_eq!(
+            with_scheme(&git("ssh://git@git.corp.io:2222/chain/sdk"), UrlScheme::Ssh).unwrap(),
+            "ssh://git@git.corp.io:2222/chain/sdk.git"
+        );
+        assert_eq!(
+            with_scheme(&git("https://git.corp.io:8443/chain/sdk"), UrlScheme::Https).unwrap(),
+            "https://git.corp.io:8443/chain/sdk"
+        );
+    }
+
+    #[test]
+    fn with_scheme_refuses_to_move_a_custom_port() {
+        assert!(with_scheme(&git("https://git.corp.io:8443/chain/sdk"), UrlScheme::Ssh).is_err());
+        assert!(with_scheme(
+            &git("ssh://git@git.corp.io:2222/chain/sdk"),
+            UrlScheme::Https
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn split_reference_handles_queries_and_fragments() {
+        assert_eq!(
+            split_reference("https://github.com/paritytech/substrate"),
+            ("https://github.com/paritytech/substrate", None)
+        );
+        assert_eq!(
+            split_reference("https://github.com/paritytech/substrate?branch=master"),
+            (
+                "https://github.com/paritytech/substrate",
+                Some(("branch", "master".into()))
+            )
+        );
+        assert_eq!(
+            split_reference("https://github.com/paritytech/substrate?foo=bar&tag=v1.0.0"),
+            (
+                "https://github.com/paritytech/substrate",
+                Some(("tag", "v1.0.0".into()))
+            )
+        );
+        assert_eq!(
+            split_reference("https://github.com/paritytech/substrate#0123abcd"),
+            (
+                "https://github.com/paritytech/substrate",
+                Some(("rev", "0123abcd".into()))
+            )
+        );
+        assert_eq!(
+            split_reference("https://github.com/paritytech/substrate?branch=master#0123abcd"),
+            (
+                "https://github.com/paritytech/substrate",
+                Some(("branch", "master".into()))
+            )
+        );
+        assert_eq!(
+            split_reference("https://github.com/paritytech/substrate#"),
+            ("https://github.com/paritytech/substrate", None)
+        );
+    }
+
+    #[test]
+    fn normalize_url_drops_scheme_user_and_suffix() {
+        for url in [
+            "https://github.com/paritytech/substrate",
+            "https://github.com/paritytech/substrate.git",
+            "https://GitHub.com/ParityTech/Substrate/",
+            "ssh://git@github.com/paritytech/substrate.git",
+            "git@github.com:paritytech/substrate.git",
+        ] {
+            assert_eq!(
+                normalize_url(url),
+                "github.com/paritytech/substrate",
+                "{}",
+                url
+            );
+        }
+        assert_eq!(
+            normalize_url("ssh://git@git.corp.io:2222/chain/sdk"),
+            "git.corp.io:2222/chain/sdk"
+        );
+    }
+
+    #[test]
+    fn classify_by_repository_name() {
+        let matcher = matcher(&[]);
+
+        assert_eq!(
+            matcher.classify(&git("https://github.com/paritytech/substrate")),
+            Some(Repo::Substrate)
+        );
+        assert_eq!(
+            matcher.classify(&git("https://github.com/myfork/polkadot.git")),
+            Some(Repo::Polkadot)
+        );
+        assert_eq!(
+            matcher.classify(&git("https://github.com/paritytech/substrate-frontier")),
+            None
+        );
+    }
+}
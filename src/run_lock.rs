@@ -0,0 +1,109 @@
+use anyhow::{anyhow, Context, Result};
+use std::{
+    fs::{self, File, OpenOptions, TryLockError},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// The name of the lock file in the root of the workspace.
+const LOCK_FILE_NAME: &str = ".diener.lock";
+
+/// Set by `--no-lock`.
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Disable the run lock, see [`acquire`].
+pub(crate) fn disable() {
+    DISABLED.store(true, Ordering::Relaxed);
+}
+
+/// An advisory lock on a workspace, released when dropped.
+pub(crate) struct RunLock {
+    path: PathBuf,
+    // Keeps the file and with it the lock open.
+    _file: File,
+}
+
+impl Drop for RunLock {
+    fn drop(&mut self) {
+        // The next run creates a new file, so the lock is only removed while it is held.
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Lock the workspace in `dir` for the duration of a run, so concurrent runs on the same tree
+/// (e.g. two CI jobs) don't interleave their writes.
+///
+/// Waits until other runs are finished. The lock is advisory, it only excludes other diener
+/// runs, and is released by the OS if diener is killed. Returns `None` with `--no-lock`.
+pub(crate) fn acquire(dir: &Path) -> Result<Option<RunLock>> {
+    if DISABLED.load(Ordering::Relaxed) {
+        return Ok(None);
+    }
+
+    let path = dir.join(LOCK_FILE_NAME);
+    loop {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .with_context(|| anyhow!("Failed to create the lock file {}", path.display()))?;
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                log::warn!(
+                    "Waiting for another diener run on {} to finish, pass `--no-lock` to skip \
+                     waiting.",
+                    dir.display()
+                );
+                file.lock()
+                    .with_context(|| anyhow!("Failed to lock {}", path.display()))?;
+            }
+            Err(TryLockError::Error(e)) => {
+                return Err(e).with_context(|| anyhow!("Failed to lock {}", path.display()))
+            }
+        }
+
+        // The previous holder removes the file when it is done, so the lock is only valid if
+        // the file is still the one at `path`.
+        if is_same_file(&file, &path) {
+            return Ok(Some(RunLock { path, _file: file }));
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_same_file(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (file.metadata(), fs::metadata(path)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_same_file(_: &File, path: &Path) -> bool {
+    path.exists()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lock_file_is_removed_after_the_run() {
+        let dir = std::env::temp_dir().join(format!("diener-run-lock-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let lock = acquire(&dir).unwrap().unwrap();
+        let other = File::open(dir.join(LOCK_FILE_NAME)).unwrap();
+        assert!(matches!(other.try_lock(), Err(TryLockError::WouldBlock)));
+        drop(other);
+
+        drop(lock);
+        assert!(!dir.join(LOCK_FILE_NAME).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
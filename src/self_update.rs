@@ -0,0 +1,192 @@
+use crate::{atomic_write, http};
+use anyhow::{anyhow, ensure, Context, Result};
+use serde::Deserialize;
+use std::{
+    env, fs,
+    path::Path,
+    process::{self, Command},
+};
+use structopt::StructOpt;
+
+/// The GitHub repository the releases are published at.
+const REPOSITORY: &str = "paritytech/diener";
+
+/// `self-update` subcommand options.
+#[derive(Debug, StructOpt)]
+pub struct SelfUpdate {
+    /// Only check if there is a newer version, without installing it.
+    #[structopt(long)]
+    check: bool,
+}
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl SelfUpdate {
+    /// Run this subcommand.
+    pub fn run(self) -> Result<()> {
+        let release =
+            http::github_api::<Release>(&format!("repos/{}/releases/latest", REPOSITORY))?
+                .ok_or_else(|| anyhow!("No release of {} found.", REPOSITORY))?;
+
+        let current = semver::Version::parse(env!("CARGO_PKG_VERSION"))?;
+        let latest = semver::Version::parse(release.tag_name.trim_start_matches('v'))
+            .with_context(|| anyhow!("Invalid release tag `{}`", release.tag_name))?;
+        if latest <= current {
+            log::info!("diener v{} is up to date.", current);
+            return Ok(());
+        }
+
+        log::info!("diener v{} is available (installed: v{}).", latest, current);
+        if self.check {
+            return Ok(());
+        }
+
+        let no_binary = || {
+            anyhow!(
+                "Release {} has no binary for {}-{}, update with `cargo install diener --force`.",
+                release.tag_name,
+                env::consts::OS,
+                env::consts::ARCH
+            )
+        };
+        let name = asset_name(&release.tag_name).ok_or_else(no_binary)?;
+        let find = |name: &str| release.assets.iter().find(|a| a.name == name);
+        let asset = find(&name).ok_or_else(no_binary)?;
+        let checksum = find(&format!("{}.sha256", name)).ok_or_else(|| {
+            anyhow!(
+                "Release {} has no checksum for {}, not installing it.",
+                release.tag_name,
+                name
+            )
+        })?;
+
+        let binary = download_binary(asset, checksum)?;
+        let exe = env::current_exe().context("Failed to find the current executable")?;
+        atomic_write::write(&exe, binary)
+            .with_context(|| anyhow!("Failed to replace {}", exe.display()))?;
+
+        log::info!("Updated {} to v{}.", exe.display(), latest);
+        Ok(())
+    }
+}
+
+/// Returns the name of the release asset with the binary for the current platform.
+///
+/// Only platforms with a `.tar.gz` archive are supported, the Debian package for Linux can not
+/// be installed by replacing the binary.
+fn asset_name(tag: &str) -> Option<String> {
+    match (env::consts::OS, env::consts::ARCH) {
+        ("macos", "x86_64") => Some(format!("diener_macos_{}.tar.gz", tag)),
+        _ => None,
+    }
+}
+
+/// Download the given `asset`, verify it against the `checksum` asset and return the `diener`
+/// binary.
+fn download_binary(asset: &Asset, checksum: &Asset) -> Result<Vec<u8>> {
+    log::info!("Downloading {}", asset.browser_download_url);
+    let content = http::get_bytes(&asset.browser_download_url)?;
+    let expected = http::get_text(&checksum.browser_download_url)?;
+    let expected = expected
+        .split_whitespace()
+        .next()
+        .ok_or_else(|| anyhow!("{} is empty", checksum.name))?;
+
+    let dir = env::temp_dir().join(format!("diener-self-update-{}", process::id()));
+    fs::create_dir_all(&dir).with_context(|| anyhow!("Failed to create {}", dir.display()))?;
+    let result = extract_binary(&dir, &asset.name, &content, expected);
+    let _ = fs::remove_dir_all(&dir);
+    result
+}
+
+/// Extract the `diener` binary from the `archive` inside the given `dir`, after checking that
+/// the sha256 of the `archive` is `expected`.
+fn extract_binary(dir: &Path, name: &str, archive: &[u8], expected: &str) -> Result<Vec<u8>> {
+    let archive_path = dir.join(name);
+    fs::write(&archive_path, archive)
+        .with_context(|| anyhow!("Failed to write {}", archive_path.display()))?;
+
+    let actual = sha256(&archive_path)?;
+    ensure!(
+        actual.eq_ignore_ascii_case(expected),
+        "The sha256 of {} is {}, but the release publishes {}.",
+        name,
+        actual,
+        expected
+    );
+
+    let status = Command::new("tar")
+        .arg("-xzf")
+        .arg(&archive_path)
+        .arg("-C")
+        .arg(dir)
+        .status()
+        .with_context(|| "Failed to run `tar`, is it installed?")?;
+    ensure!(status.success(), "Failed to extract {}", name);
+
+    let binary = walkdir::WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .find(|e| e.file_type().is_file() && e.file_name() == "diener")
+        .map(|e| e.into_path())
+        .ok_or_else(|| anyhow!("{} doesn't contain a `diener` binary", name))?;
+
+    fs::read(&binary).with_context(|| anyhow!("Failed to read {}", binary.display()))
+}
+
+/// Returns the sha256 of the file at `path` as hex string.
+///
+/// Uses `sha256sum` or, if that isn't installed like on macOS, `shasum`.
+fn sha256(path: &Path) -> Result<String> {
+    let output = Command::new("sha256sum")
+        .arg(path)
+        .output()
+        .or_else(|_| {
+            Command::new("shasum")
+                .args(["-a", "256"])
+                .arg(path)
+                .output()
+        })
+        .with_context(|| "Failed to run `sha256sum` or `shasum`, is one of them installed?")?;
+    ensure!(
+        output.status.success(),
+        "Failed to calculate the sha256 of {}: {}",
+        path.display(),
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+
+    String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .next()
+        .map(Into::into)
+        .ok_or_else(|| anyhow!("Failed to calculate the sha256 of {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuse_archive_with_wrong_checksum() {
+        let dir = env::temp_dir().join(format!("diener-self-update-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let result = extract_binary(&dir, "diener.tar.gz", b"abc", &"0".repeat(64));
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,176 @@
+use crate::{
+    report,
+    repos::{same_repository, split_reference, Reference},
+};
+use anyhow::{anyhow, Context, Result};
+use git_url_parse::GitUrl;
+use std::{
+    collections::BTreeMap,
+    env, fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use toml_edit::{Document, TableLike};
+
+/// The name cargo uses for the `crates.io` source.
+const CRATES_IO: &str = "crates-io";
+
+/// A source declared in a `[source.NAME]` table of a cargo config file.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Source {
+    /// A git repository at the given reference.
+    Git(String, Option<Reference>),
+    /// Any other kind of source, e.g. `directory` or `registry`, described by its kind and
+    /// location.
+    Other(&'static str, String),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Git(url, reference) => write!(
+                f,
+                "{}",
+                report::git_source(url, reference.as_ref().map(|(k, v)| (*k, v.as_str())))
+            ),
+            Self::Other(kind, location) => write!(f, "{} `{}`", kind, location),
+        }
+    }
+}
+
+impl Source {
+    fn parse(source: &dyn TableLike) -> Option<Self> {
+        let get = |key| source.get(key).and_then(|v| v.as_str());
+
+        if let Some(git) = get("git") {
+            let (url, embedded) = split_reference(git);
+            let reference = ["branch", "tag", "rev"]
+                .into_iter()
+                .find_map(|k| get(k).map(|v| (k, v.to_string())))
+                .or(embedded);
+            return Some(Self::Git(url.into(), reference));
+        }
+
+        ["registry", "local-registry", "directory"]
+            .into_iter()
+            .find_map(|k| get(k).map(|v| Self::Other(k, v.into())))
+    }
+}
+
+/// The source replacements configured with `replace-with` in the cargo config files.
+#[derive(Debug, Default)]
+pub(crate) struct Replacements {
+    /// The sources by their name.
+    sources: BTreeMap<String, Source>,
+    /// Source name -> name of the source replacing it.
+    replace_with: BTreeMap<String, String>,
+}
+
+impl Replacements {
+    /// Load the replacements that apply to the cargo workspace in `dir`.
+    ///
+    /// Like cargo, this reads `.cargo/config.toml` in `dir` and all its parents and the one in
+    /// `CARGO_HOME`. Config files closer to `dir` take precedence.
+    pub(crate) fn load(dir: &Path) -> Result<Self> {
+        let mut replacements = Self::default();
+        for file in config_files(dir) {
+            let content = fs::read_to_string(&file)
+                .with_context(|| anyhow!("Failed to read cargo config {}", file.display()))?;
+            let doc = Document::from_str(&content)
+                .with_context(|| anyhow!("Failed to parse cargo config {}", file.display()))?;
+            replacements.extend(&doc);
+        }
+
+        Ok(replacements)
+    }
+
+    fn extend(&mut self, doc: &Document) {
+        let sources = match doc.get("source").and_then(|s| s.as_table_like()) {
+            Some(sources) => sources,
+            None => return,
+        };
+
+        for (name, source) in sources.iter() {
+            let source = match source.as_table_like() {
+                Some(source) => source,
+                None => continue,
+            };
+
+            if let Some(parsed) = Source::parse(source) {
+                self.sources.insert(name.into(), parsed);
+            }
+            if let Some(replace_with) = source.get("replace-with").and_then(|r| r.as_str()) {
+                self.replace_with.insert(name.into(), replace_with.into());
+            }
+        }
+    }
+
+    /// Returns the source that replaces `crates.io`.
+    pub(crate) fn crates_io(&self) -> Option<&Source> {
+        self.resolve(CRATES_IO)
+    }
+
+    /// Returns the source that replaces the git repository `git` at `reference`.
+    pub(crate) fn git(&self, git: &GitUrl, reference: Option<&Reference>) -> Option<&Source> {
+        let name = self
+            .sources
+            .iter()
+            .find_map(|(name, source)| match source {
+                Source::Git(url, r)
+                    if r.as_ref() == reference
+                        && GitUrl::parse(url).is_ok_and(|url| same_repository(&url, git)) =>
+                {
+                    Some(name)
+                }
+                _ => None,
+            })?;
+
+        self.resolve(name)
+    }
+
+    /// Follows the `replace-with` chain of the source with the given `name`.
+    fn resolve(&self, name: &str) -> Option<&Source> {
+        let mut name = self.replace_with.get(name)?;
+        // Cargo rejects cycles, but a broken config shouldn't hang us.
+        for _ in 0..self.replace_with.len() {
+            match self.replace_with.get(name) {
+                Some(next) => name = next,
+                None => break,
+            }
+        }
+
+        self.sources.get(name)
+    }
+}
+
+/// Returns the cargo config files that apply to `dir`, the ones that take precedence last.
+///
+/// Like cargo, these are `.cargo/config.toml` in `dir` and all its parents and the one in
+/// `CARGO_HOME`.
+pub(crate) fn config_files(dir: &Path) -> Vec<PathBuf> {
+    let mut dirs = dir
+        .ancestors()
+        .map(|d| d.join(".cargo"))
+        .collect::<Vec<_>>();
+    dirs.extend(cargo_home());
+
+    dirs.into_iter()
+        .rev()
+        .filter_map(|d| config_file(&d))
+        .collect()
+}
+
+/// Returns the cargo home directory.
+fn cargo_home() -> Option<PathBuf> {
+    env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".cargo")))
+}
+
+/// Returns the config file in the given `.cargo` directory, if there is one.
+fn config_file(dir: &Path) -> Option<PathBuf> {
+    ["config.toml", "config"]
+        .into_iter()
+        .map(|f| dir.join(f))
+        .find(|f| f.is_file())
+}
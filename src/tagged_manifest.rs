@@ -0,0 +1,189 @@
+use crate::http;
+use anyhow::{anyhow, bail, Context, Result};
+use git_url_parse::GitUrl;
+use serde::Deserialize;
+use std::str::FromStr;
+use toml_edit::Document;
+
+/// The manifests of a GitHub repository at a tag, used by `--version-from-tag`.
+///
+/// Works with any git reference, `--sync-version` also passes branches and commits.
+#[derive(Debug, Clone)]
+pub(crate) struct TaggedManifests {
+    /// `OWNER/REPO` of the repository.
+    repository: String,
+    tag: String,
+    /// The paths of all `Cargo.toml` files in the repository.
+    paths: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Tree {
+    tree: Vec<TreeEntry>,
+    truncated: bool,
+}
+
+#[derive(Deserialize)]
+struct TreeEntry {
+    path: String,
+}
+
+impl TaggedManifests {
+    /// Fetch the list of manifests of the repository `git` at `tag`.
+    ///
+    /// Only repositories on GitHub are supported.
+    pub(crate) fn fetch(git: &GitUrl, tag: &str) -> Result<Self> {
+        let owner = match (git.host.as_deref(), &git.owner) {
+            (Some("github.com"), Some(owner)) => owner,
+            _ => bail!(
+                "The manifests can only be fetched from GitHub repositories, not from {}.",
+                git
+            ),
+        };
+        let repository = format!("{}/{}", owner, git.name);
+
+        let tree = http::github_api::<Tree>(&format!(
+            "repos/{}/git/trees/{}?recursive=1",
+            repository, tag
+        ))
+        .with_context(|| anyhow!("Failed to list the files of {} at `{}`", repository, tag))?
+        .ok_or_else(|| anyhow!("{} has no tag `{}`.", repository, tag))?;
+        if tree.truncated {
+            log::warn!(
+                "The file list of {} at `{}` is incomplete, not all crates may be found.",
+                repository,
+                tag
+            );
+        }
+
+        Ok(Self {
+            paths: tree
+                .tree
+                .into_iter()
+                .map(|e| e.path)
+                .filter(|p| p == "Cargo.toml" || p.ends_with("/Cargo.toml"))
+                .collect(),
+            repository,
+            tag: tag.into(),
+        })
+    }
+
+    /// Returns the version of `package` in its manifest at the tag.
+    ///
+    /// Returns `None` if the repository has no crate with that name.
+    pub(crate) fn version(&self, package: &str) -> Result<Option<String>> {
+        for path in self.paths.iter().filter(|p| is_candidate(p, package)) {
+            let manifest = self.get(path)?;
+            let version = version_of(&manifest, package, || {
+                self.get("Cargo.toml")
+                    .map(|c| Some(c).filter(|_| path != "Cargo.toml"))
+            })
+            .with_context(|| anyhow!("Invalid manifest {} of {}", path, self.repository))?;
+            if version.is_some() {
+                return Ok(version);
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Fetch the file at `path`.
+    fn get(&self, path: &str) -> Result<String> {
+        http::get_text_cached(&format!(
+            "https://raw.githubusercontent.com/{}/{}/{}",
+            self.repository, self.tag, path
+        ))
+    }
+}
+
+/// Returns `true` if the manifest at `path` could be the one of `package`.
+///
+/// Only the manifests in a directory named like the crate are fetched, with or without the
+/// prefix (`frame/balances` for `pallet-balances`), as fetching all of them takes too long.
+fn is_candidate(path: &str, package: &str) -> bool {
+    let dir = path
+        .strip_suffix("Cargo.toml")
+        .unwrap_or(path)
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or_default();
+    dir == package
+        || package
+            .strip_suffix(dir)
+            .is_some_and(|prefix| prefix.ends_with('-'))
+}
+
+/// Returns the version of `manifest`, if it is the manifest of `package`.
+///
+/// A version inherited from the workspace is looked up in the manifest returned by `root`.
+fn version_of(
+    manifest: &str,
+    package: &str,
+    root: impl FnOnce() -> Result<Option<String>>,
+) -> Result<Option<String>> {
+    let doc = Document::from_str(manifest)?;
+    let table = match doc.get("package") {
+        Some(table) if table.get("name").and_then(|n| n.as_str()) == Some(package) => table,
+        _ => return Ok(None),
+    };
+
+    let version = table
+        .get("version")
+        .ok_or_else(|| anyhow!("`{}` has no version.", package))?;
+    if let Some(version) = version.as_str() {
+        return Ok(Some(version.into()));
+    }
+    if version.get("workspace").and_then(|w| w.as_bool()) != Some(true) {
+        bail!("`{}` has an invalid version.", package);
+    }
+
+    let root = root()?.ok_or_else(|| anyhow!("`{}` has no workspace.", package))?;
+    Document::from_str(&root)?
+        .get("workspace")
+        .and_then(|w| w.get("package"))
+        .and_then(|p| p.get("version"))
+        .and_then(|v| v.as_str())
+        .map(|v| Some(v.into()))
+        .ok_or_else(|| anyhow!("The workspace of `{}` has no version.", package))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn candidates_are_named_like_the_crate() {
+        assert!(is_candidate(
+            "substrate/frame/balances/Cargo.toml",
+            "pallet-balances"
+        ));
+        assert!(is_candidate("primitives/io/Cargo.toml", "sp-io"));
+        assert!(is_candidate("sp-io/Cargo.toml", "sp-io"));
+        assert!(!is_candidate("primitives/core/Cargo.toml", "sp-io"));
+        assert!(!is_candidate("frame/support/Cargo.toml", "pallet-asupport"));
+    }
+
+    #[test]
+    fn version_is_read_from_the_manifest() {
+        let manifest = "[package]\nname = \"sp-io\"\nversion = \"30.0.0\"\n";
+        let no_root = || unreachable!();
+        assert_eq!(
+            version_of(manifest, "sp-io", no_root).unwrap().as_deref(),
+            Some("30.0.0")
+        );
+        assert_eq!(version_of(manifest, "sp-core", no_root).unwrap(), None);
+
+        let inherited = "[package]\nname = \"sp-io\"\nversion.workspace = true\n";
+        let root = || {
+            Ok(Some(
+                "[workspace.package]\nversion = \"1.2.0\"\n".to_string(),
+            ))
+        };
+        assert_eq!(
+            version_of(inherited, "sp-io", root).unwrap().as_deref(),
+            Some("1.2.0")
+        );
+        assert!(version_of(inherited, "sp-io", || Ok(None)).is_err());
+    }
+}
@@ -0,0 +1,735 @@
+use crate::{
+    atomic_write, crates_io,
+    repos::{split_reference, Reference},
+    run_lock,
+    update::{dependency_tables, dependency_tables_mut},
+    workspacify::{manifest_iter, read_toml},
+};
+use anyhow::{anyhow, ensure, Context, Result};
+use git_url_parse::GitUrl;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env::current_dir,
+    path::PathBuf,
+};
+use structopt::StructOpt;
+use toml_edit::{value, Array, Document, InlineTable, Item, TableLike, Value};
+
+/// The name of the umbrella crate.
+const UMBRELLA: &str = "polkadot-sdk";
+
+/// The owner of the git repository of the umbrella crate.
+const UMBRELLA_OWNER: &str = "paritytech";
+
+/// The prefixes of the crates that are re-exported by the umbrella crate.
+///
+/// The umbrella crate has a feature with the name of every crate it re-exports.
+const PREFIXES: &[&str] = &["sp-", "frame-", "pallet-"];
+
+/// `umbrella` subcommand options.
+#[derive(Debug, StructOpt)]
+pub struct Umbrella {
+    /// The path to the workspace root directory.
+    ///
+    /// Uses the working directory if none is supplied.
+    #[structopt(long)]
+    path: Option<PathBuf>,
+
+    /// Convert the `polkadot-sdk` umbrella crate back into individual dependencies.
+    #[structopt(long)]
+    reverse: bool,
+
+    /// The version of the `polkadot-sdk` crate to use for registry dependencies.
+    ///
+    /// Only required if the converted dependencies are fetched from `crates.io`.
+    #[structopt(long)]
+    version: Option<String>,
+}
+
+impl Umbrella {
+    /// Run this subcommand.
+    pub fn run(self) -> Result<()> {
+        let workspace = self
+            .path
+            .map(Ok)
+            .unwrap_or_else(|| current_dir().with_context(|| "Working directory is invalid."))?;
+        ensure!(
+            workspace.is_dir(),
+            "Path '{}' is not a directory.",
+            workspace.display()
+        );
+        let _lock = run_lock::acquire(&workspace)?;
+
+        let manifests = manifest_iter(&workspace)
+            .map(|m| read_toml(&m, false).map(|doc| (m, doc)))
+            .collect::<Result<Vec<_>>>()?;
+
+        // `[workspace.dependencies]` is converted into all crates used by any member.
+        let used_crates = manifests
+            .iter()
+            .flat_map(|(_, doc)| umbrella_crates(doc))
+            .collect::<BTreeSet<_>>();
+
+        // `workspace = true` dependencies are converted if their workspace entry is converted.
+        let root = workspace.join("Cargo.toml");
+        let workspace_sources = manifests
+            .iter()
+            .find(|(path, _)| *path == root)
+            .and_then(|(_, doc)| doc.get("workspace")?.get("dependencies")?.as_table_like())
+            .into_iter()
+            .flat_map(|deps| deps.iter())
+            .filter_map(|(name, dep)| Some((name.to_string(), Source::of(dep)?)))
+            .collect::<BTreeMap<_, _>>();
+
+        let reexported = match self.version.as_deref().filter(|_| !self.reverse) {
+            Some(version) => {
+                Some(crates_io::get_features(UMBRELLA, version)?.ok_or_else(|| {
+                    anyhow!("`{}` {} is not published on crates.io.", UMBRELLA, version)
+                })?)
+            }
+            None => None,
+        };
+        let convertible = Convertible {
+            workspace: workspace_sources,
+            reexported,
+        };
+
+        for (path, mut doc) in manifests {
+            let old = doc.to_string();
+            if self.reverse {
+                from_umbrella(&mut doc, &used_crates);
+            } else {
+                to_umbrella(&mut doc, self.version.as_deref(), &convertible)
+                    .with_context(|| anyhow!("Failed to convert {}", path.display()))?;
+            }
+
+            let new = doc.to_string();
+            if new != old {
+                log::info!("Converted {}", path.display());
+                atomic_write::write(&path, new)
+                    .with_context(|| anyhow!("Failed to write manifest to {}", path.display()))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Where a dependency is fetched from.
+#[derive(Debug, Clone, PartialEq)]
+enum Source {
+    /// `workspace = true`
+    Workspace,
+    /// `crates.io`, the version is specific to every crate.
+    Registry,
+    /// The `polkadot-sdk` git repository at the given url and reference.
+    Git(String, Option<Reference>),
+}
+
+impl Source {
+    /// Returns the source of the given dependency.
+    ///
+    /// Returns `None` for sources that can not be converted, e.g. `path` dependencies or git
+    /// repositories other than `polkadot-sdk`.
+    fn of(dep: &Item) -> Option<Self> {
+        if dep.is_str() {
+            return Some(Self::Registry);
+        }
+
+        let dep = dep.as_table_like()?;
+        if dep.contains_key("path") || dep.contains_key("registry") {
+            return None;
+        }
+        if dep.get("workspace").and_then(|w| w.as_bool()) == Some(true) {
+            return Some(Self::Workspace);
+        }
+
+        match dep.get("git").and_then(|g| g.as_str()) {
+            Some(git) => {
+                let (url, embedded) = split_reference(git);
+                let git = GitUrl::parse(url).ok()?;
+                let is_umbrella_repository = git.name.eq_ignore_ascii_case(UMBRELLA)
+                    && git
+                        .owner
+                        .is_some_and(|o| o.eq_ignore_ascii_case(UMBRELLA_OWNER));
+                if !is_umbrella_repository {
+                    return None;
+                }
+
+                let reference = ["branch", "tag", "rev"]
+                    .into_iter()
+                    .find_map(|k| dep.get(k).and_then(|v| v.as_str()).map(|v| (k, v.into())))
+                    .or(embedded);
+                Some(Self::Git(url.into(), reference))
+            }
+            None if dep.contains_key("version") => Some(Self::Registry),
+            None => None,
+        }
+    }
+
+    /// Insert the keys of this source into the given `dep`.
+    ///
+    /// `version` is used for [`Self::Registry`].
+    fn insert_into(&self, dep: &mut InlineTable, version: Option<&str>) {
+        match self {
+            Self::Workspace => {
+                dep.insert("workspace", true.into());
+            }
+            Self::Registry => {
+                if let Some(version) = version {
+                    dep.insert("version", version.into());
+                }
+            }
+            Self::Git(url, reference) => {
+                dep.insert("git", url.as_str().into());
+                if let Some((key, value)) = reference {
+                    dep.insert(*key, value.as_str().into());
+                }
+            }
+        }
+    }
+}
+
+/// Decides which of the dependencies are converted into the umbrella crate.
+struct Convertible {
+    /// The sources of the `[workspace.dependencies]` of the workspace root.
+    workspace: BTreeMap<String, Source>,
+    /// The features of the `--version` of the umbrella crate on crates.io, one for every crate
+    /// it re-exports.
+    reexported: Option<BTreeSet<String>>,
+}
+
+impl Convertible {
+    /// Returns the source the dependency `name` with the given `source` is actually fetched
+    /// from, if it can be converted.
+    ///
+    /// `workspace = true` is resolved through the `[workspace.dependencies]`, so e.g. local
+    /// crates of the workspace are not converted.
+    fn resolve<'a>(&'a self, name: &str, source: &'a Source) -> Option<&'a Source> {
+        let resolved = match source {
+            Source::Workspace => self.workspace.get(name)?,
+            source => source,
+        };
+
+        match (resolved, &self.reexported) {
+            (Source::Registry, Some(reexported)) if !reexported.contains(name) => {
+                log::warn!(
+                    "`{}` is not re-exported by `{}`, not converting it.",
+                    name,
+                    UMBRELLA
+                );
+                None
+            }
+            (Source::Workspace, _) => None,
+            (resolved, _) => Some(resolved),
+        }
+    }
+}
+
+/// Returns `true` if the crate with the given `name` is re-exported by the umbrella crate.
+fn is_umbrella_crate(name: &str) -> bool {
+    PREFIXES.iter().any(|p| name.starts_with(p))
+}
+
+/// Returns the features of the given dependency.
+fn features(dep: &Item) -> Vec<String> {
+    dep.as_table_like()
+        .and_then(|d| d.get("features"))
+        .and_then(|f| f.as_array())
+        .into_iter()
+        .flat_map(|f| f.iter())
+        .filter_map(|f| f.as_str())
+        .map(Into::into)
+        .collect()
+}
+
+/// Returns the value of the boolean `key` of the given dependency.
+fn flag(dep: &Item, key: &str) -> Option<bool> {
+    dep.as_table_like()
+        .and_then(|d| d.get(key))
+        .and_then(|v| v.as_bool())
+}
+
+/// Returns the `[workspace.dependencies]` of the given manifest.
+fn workspace_dependencies(doc: &mut Document) -> Option<&mut dyn TableLike> {
+    doc.get_mut("workspace")?
+        .get_mut("dependencies")?
+        .as_table_like_mut()
+}
+
+/// Returns the crates that are enabled as features of the umbrella crate in the given manifest.
+fn umbrella_crates(doc: &Document) -> Vec<String> {
+    let workspace = doc
+        .get("workspace")
+        .and_then(|w| w.get("dependencies"))
+        .and_then(|d| d.as_table_like());
+    dependency_tables(doc, |k| k.contains("dependencies"))
+        .into_iter()
+        .map(|t| t as &dyn TableLike)
+        .chain(workspace)
+        .filter_map(|deps| deps.get(UMBRELLA).map(features))
+        .flatten()
+        .filter(|f| is_umbrella_crate(f))
+        .collect()
+}
+
+/// Convert all individual dependencies of the umbrella crate into a single `polkadot-sdk`
+/// dependency.
+///
+/// `version` is the version of the umbrella crate to use for registry dependencies.
+fn to_umbrella(doc: &mut Document, version: Option<&str>, convertible: &Convertible) -> Result<()> {
+    let mut converted = BTreeSet::new();
+    for deps in dependency_tables_mut(doc, |k| k.contains("dependencies")) {
+        converted.extend(convert_to_umbrella(deps, false, version, convertible)?);
+    }
+    if let Some(deps) = workspace_dependencies(doc) {
+        converted.extend(convert_to_umbrella(deps, true, version, convertible)?);
+    }
+
+    rewrite_features(doc, |feature| {
+        vec![match parse_feature(feature) {
+            Some(Feature::Dependency(name)) if converted.contains(name) => {
+                format!("dep:{}", UMBRELLA)
+            }
+            Some(Feature::Propagate(name, weak, feature)) if converted.contains(name) => {
+                format!("{}{}/{}", UMBRELLA, if weak { "?" } else { "" }, feature)
+            }
+            _ => feature.into(),
+        }]
+    });
+
+    Ok(())
+}
+
+/// Convert the individual dependencies of the umbrella crate in the dependency table `deps`.
+///
+/// `is_workspace` is `true` for `[workspace.dependencies]`. Returns the converted crates.
+fn convert_to_umbrella(
+    deps: &mut dyn TableLike,
+    is_workspace: bool,
+    version: Option<&str>,
+    convertible: &Convertible,
+) -> Result<Vec<String>> {
+    let mut source = None;
+    let mut crates = Vec::new();
+    let mut crate_features = BTreeSet::new();
+    let mut no_default_features = false;
+    let mut from_registry = false;
+    for (name, dep) in deps.iter() {
+        let package = dep
+            .as_table_like()
+            .and_then(|d| d.get("package"))
+            .and_then(|p| p.as_str());
+        if !is_umbrella_crate(name)
+            || package.is_some_and(|p| p != name)
+            || flag(dep, "optional") == Some(true)
+        {
+            continue;
+        }
+
+        let dep_source = match Source::of(dep) {
+            Some(dep_source) => dep_source,
+            None => continue,
+        };
+        let is_registry = match convertible.resolve(name, &dep_source) {
+            Some(resolved) => *resolved == Source::Registry,
+            None => continue,
+        };
+
+        // The features of the crate are enabled through the features of the umbrella crate with
+        // the same name.
+        let dep_features = features(dep);
+        let missing = dep_features
+            .iter()
+            .filter(|f| {
+                !convertible
+                    .reexported
+                    .as_ref()
+                    .is_some_and(|r| r.contains(*f))
+            })
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            log::warn!(
+                "`{}` has no features {:?} to enable them for `{}`, not converting it.",
+                UMBRELLA,
+                missing,
+                name
+            );
+            continue;
+        }
+
+        match &source {
+            Some(source) if *source != dep_source => {
+                log::warn!(
+                    "`{}` uses a different source than the other dependencies, not converting it.",
+                    name
+                );
+                continue;
+            }
+            Some(_) => {}
+            None => source = Some(dep_source),
+        }
+
+        crate_features.extend(dep_features);
+        no_default_features |= flag(dep, "default-features") == Some(false);
+        from_registry |= is_registry;
+        crates.push(name.to_string());
+    }
+
+    let source = match source {
+        Some(source) => source,
+        None => return Ok(Vec::new()),
+    };
+    ensure!(
+        !from_registry || version.is_some(),
+        "{:?} are fetched from `crates.io`, pass `--version` with the version of `{}` to use.",
+        crates,
+        UMBRELLA
+    );
+
+    crates.iter().for_each(|name| {
+        deps.remove(name);
+    });
+
+    // Crates in `[workspace.dependencies]` are enabled by the members.
+    let new_features = if is_workspace {
+        Vec::new()
+    } else {
+        crates.clone()
+    }
+    .into_iter()
+    .chain(crate_features)
+    .collect::<Vec<_>>();
+    match deps.get_mut(UMBRELLA).and_then(|u| u.as_table_like_mut()) {
+        Some(umbrella) => {
+            let features = umbrella
+                .entry("features")
+                .or_insert(value(Array::new()))
+                .as_array_mut()
+                .ok_or_else(|| anyhow!("`{}.features` is not an array", UMBRELLA))?;
+            for feature in new_features {
+                if !features.iter().any(|f| f.as_str() == Some(&feature)) {
+                    features.push(feature);
+                }
+            }
+        }
+        None => {
+            let mut umbrella = InlineTable::new();
+            source.insert_into(&mut umbrella, version);
+            if no_default_features {
+                umbrella.insert("default-features", false.into());
+            }
+            if !new_features.is_empty() {
+                umbrella.insert("features", Value::Array(new_features.into_iter().collect()));
+            }
+            umbrella.fmt();
+            deps.insert(UMBRELLA, value(umbrella));
+        }
+    }
+
+    log::info!("  {:?} => `{}`", crates, UMBRELLA);
+    Ok(crates)
+}
+
+/// Convert the `polkadot-sdk` dependency into the individual crates it enables.
+///
+/// `used_crates` are the crates that are used in any manifest, they are used for
+/// `[workspace.dependencies]`.
+fn from_umbrella(doc: &mut Document, used_crates: &BTreeSet<String>) {
+    let mut crates = BTreeSet::new();
+    for deps in dependency_tables_mut(doc, |k| k.contains("dependencies")) {
+        crates.extend(convert_from_umbrella(deps, false, used_crates));
+    }
+    if let Some(deps) = workspace_dependencies(doc) {
+        crates.extend(convert_from_umbrella(deps, true, used_crates));
+    }
+
+    if crates.is_empty() {
+        return;
+    }
+
+    rewrite_features(doc, |feature| match parse_feature(feature) {
+        Some(Feature::Dependency(UMBRELLA)) => {
+            crates.iter().map(|c| format!("dep:{}", c)).collect()
+        }
+        Some(Feature::Propagate(UMBRELLA, weak, propagated)) => {
+            if propagated != "std" {
+                log::warn!(
+                    "`{}` is propagated to all of {:?}, remove it from the crates that don't \
+                     have the feature `{}`.",
+                    feature,
+                    crates,
+                    propagated
+                );
+            }
+            crates
+                .iter()
+                .map(|c| format!("{}{}/{}", c, if weak { "?" } else { "" }, propagated))
+                .collect()
+        }
+        _ => vec![feature.into()],
+    });
+}
+
+/// Convert the `polkadot-sdk` dependency in the dependency table `deps` into the individual
+/// crates it enables.
+///
+/// `is_workspace` is `true` for `[workspace.dependencies]`. Returns the enabled crates.
+fn convert_from_umbrella(
+    deps: &mut dyn TableLike,
+    is_workspace: bool,
+    used_crates: &BTreeSet<String>,
+) -> BTreeSet<String> {
+    let umbrella = match deps.get(UMBRELLA) {
+        Some(umbrella) => umbrella,
+        None => return BTreeSet::new(),
+    };
+
+    if flag(umbrella, "optional") == Some(true) {
+        log::warn!("Optional `{}` can not be converted.", UMBRELLA);
+        return BTreeSet::new();
+    }
+    let source = match Source::of(umbrella) {
+        Some(Source::Registry) => {
+            log::warn!(
+                "`{}` is fetched from `crates.io`, the versions of the individual crates are unknown.",
+                UMBRELLA
+            );
+            return BTreeSet::new();
+        }
+        Some(source) => source,
+        None => return BTreeSet::new(),
+    };
+
+    let (enabled, others) = features(umbrella)
+        .into_iter()
+        .partition::<Vec<_>, _>(|f| is_umbrella_crate(f));
+    let others = others
+        .into_iter()
+        .filter(|f| f != "std")
+        .collect::<Vec<_>>();
+    if !others.is_empty() {
+        log::warn!(
+            "The features {:?} of `{}` can not be converted, please check them manually.",
+            others,
+            UMBRELLA
+        );
+    }
+    let enabled = if is_workspace {
+        used_crates.clone()
+    } else {
+        enabled.into_iter().collect()
+    };
+    let no_default_features = flag(umbrella, "default-features") == Some(false);
+
+    deps.remove(UMBRELLA);
+    for name in &enabled {
+        if deps.contains_key(name) {
+            continue;
+        }
+
+        let mut dep = InlineTable::new();
+        source.insert_into(&mut dep, None);
+        if no_default_features {
+            dep.insert("default-features", false.into());
+        }
+        dep.fmt();
+        deps.insert(name, value(dep));
+    }
+
+    log::info!("  `{}` => {:?}", UMBRELLA, enabled);
+    enabled
+}
+
+/// An entry of a feature in the `[features]` table.
+enum Feature<'a> {
+    /// `dep:NAME`
+    Dependency(&'a str),
+    /// `NAME/FEATURE` or, if weak, `NAME?/FEATURE`.
+    Propagate(&'a str, bool, &'a str),
+}
+
+/// Parse an entry of the `[features]` table, returns `None` for plain features.
+fn parse_feature(feature: &str) -> Option<Feature<'_>> {
+    if let Some(name) = feature.strip_prefix("dep:") {
+        return Some(Feature::Dependency(name));
+    }
+
+    let (name, feature) = feature.split_once('/')?;
+    Some(match name.strip_suffix('?') {
+        Some(name) => Feature::Propagate(name, true, feature),
+        None => Feature::Propagate(name, false, feature),
+    })
+}
+
+/// Rewrite every entry of the `[features]` table with `rewrite`.
+///
+/// Duplicated entries are removed.
+fn rewrite_features(doc: &mut Document, rewrite: impl Fn(&str) -> Vec<String>) {
+    let features = match doc.get_mut("features").and_then(|f| f.as_table_like_mut()) {
+        Some(features) => features,
+        None => return,
+    };
+
+    for (_, entries) in features.iter_mut() {
+        let entries = match entries.as_array_mut() {
+            Some(entries) => entries,
+            None => continue,
+        };
+
+        let old = entries.clone();
+        entries.clear();
+        let mut seen = BTreeSet::new();
+        for entry in old.iter() {
+            let new = match entry.as_str() {
+                Some(e) => rewrite(e),
+                None => continue,
+            };
+            let new = new
+                .into_iter()
+                .filter(|n| seen.insert(n.clone()))
+                .collect::<Vec<_>>();
+            let decor = entry.decor();
+            let last = new.len().saturating_sub(1);
+            for (i, new) in new.into_iter().enumerate() {
+                // An entry that is expanded into several is followed by the next one with a
+                // space, the comments stay in front of the first and after the last one.
+                let prefix = match i {
+                    0 => decor.prefix().and_then(|p| p.as_str()).unwrap_or(""),
+                    _ => " ",
+                };
+                let suffix = if i == last {
+                    decor.suffix().and_then(|s| s.as_str()).unwrap_or("")
+                } else {
+                    ""
+                };
+                entries.push_formatted(Value::from(new).decorated(prefix, suffix));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn convert(manifest: &str, convertible: &Convertible) -> String {
+        let mut doc = Document::from_str(manifest).unwrap();
+        to_umbrella(&mut doc, Some("1.0.0"), convertible).unwrap();
+        doc.to_string()
+    }
+
+    #[test]
+    fn workspace_dependencies_are_resolved() {
+        let convertible = Convertible {
+            workspace: BTreeMap::from([
+                (
+                    "sp-core".into(),
+                    Source::Git("https://github.com/paritytech/polkadot-sdk".into(), None),
+                ),
+                ("pallet-template".into(), Source::Registry),
+            ]),
+            reexported: Some(BTreeSet::from(["sp-core".into()])),
+        };
+
+        let converted = convert(
+            "[dependencies]\n\
+             sp-core = { workspace = true }\n\
+             pallet-template = { workspace = true }\n\
+             pallet-local = { workspace = true }\n",
+            &convertible,
+        );
+        assert_eq!(
+            converted,
+            "[dependencies]\n\
+             pallet-template = { workspace = true }\n\
+             pallet-local = { workspace = true }\n\
+             polkadot-sdk = { workspace = true, features = [\"sp-core\"] }\n"
+        );
+    }
+
+    #[test]
+    fn only_umbrella_sources_are_converted() {
+        let convertible = Convertible {
+            workspace: BTreeMap::new(),
+            reexported: Some(BTreeSet::from(["sp-io".into()])),
+        };
+
+        let manifest = "[dependencies]\n\
+                        sp-core = { git = \"https://github.com/myfork/polkadot-sdk\" }\n\
+                        pallet-template = { path = \"../pallet\" }\n\
+                        pallet-custom = \"1.0\"\n";
+        assert_eq!(convert(manifest, &convertible), manifest);
+    }
+
+    #[test]
+    fn features_are_converted_in_both_directions() {
+        let convertible = Convertible {
+            workspace: BTreeMap::new(),
+            reexported: None,
+        };
+        let split = "[dependencies]\n\
+                     frame-support = { git = \"https://github.com/paritytech/polkadot-sdk\", default-features = false }\n\
+                     sp-io = { git = \"https://github.com/paritytech/polkadot-sdk\", default-features = false }\n\
+                     \n\
+                     [target.'cfg(unix)'.dependencies]\n\
+                     sp-core = { git = \"https://github.com/paritytech/polkadot-sdk\" }\n\
+                     \n\
+                     [features]\n\
+                     std = [\"frame-support/std\", \"sp-io?/std\", \"serde/std\"]\n\
+                     try-runtime = [\"frame-support/try-runtime\"]\n";
+
+        let umbrella = convert(split, &convertible);
+        assert_eq!(
+            umbrella,
+            "[dependencies]\n\
+             polkadot-sdk = { git = \"https://github.com/paritytech/polkadot-sdk\", default-features = false, features = [\"frame-support\", \"sp-io\"] }\n\
+             \n\
+             [target.'cfg(unix)'.dependencies]\n\
+             polkadot-sdk = { git = \"https://github.com/paritytech/polkadot-sdk\", features = [\"sp-core\"] }\n\
+             \n\
+             [features]\n\
+             std = [\"polkadot-sdk/std\", \"polkadot-sdk?/std\", \"serde/std\"]\n\
+             try-runtime = [\"polkadot-sdk/try-runtime\"]\n"
+        );
+
+        let mut doc = Document::from_str(&umbrella).unwrap();
+        from_umbrella(&mut doc, &BTreeSet::new());
+        assert_eq!(
+            doc.to_string(),
+            "[dependencies]\n\
+             frame-support = { git = \"https://github.com/paritytech/polkadot-sdk\", default-features = false }\n\
+             sp-io = { git = \"https://github.com/paritytech/polkadot-sdk\", default-features = false }\n\
+             \n\
+             [target.'cfg(unix)'.dependencies]\n\
+             sp-core = { git = \"https://github.com/paritytech/polkadot-sdk\" }\n\
+             \n\
+             [features]\n\
+             std = [\"frame-support/std\", \"sp-core/std\", \"sp-io/std\", \"frame-support?/std\", \"sp-core?/std\", \"sp-io?/std\", \"serde/std\"]\n\
+             try-runtime = [\"frame-support/try-runtime\", \"sp-core/try-runtime\", \"sp-io/try-runtime\"]\n"
+        );
+    }
+
+    #[test]
+    fn crate_features_need_an_umbrella_feature() {
+        let manifest = "[dependencies]\n\
+                        frame-support = { version = \"1.0\", features = [\"experimental\"] }\n\
+                        sp-io = { version = \"1.0\", features = [\"unknown\"] }\n";
+        let convertible = Convertible {
+            workspace: BTreeMap::new(),
+            reexported: Some(BTreeSet::from([
+                "frame-support".into(),
+                "sp-io".into(),
+                "experimental".into(),
+            ])),
+        };
+
+        assert_eq!(
+            convert(manifest, &convertible),
+            "[dependencies]\n\
+             sp-io = { version = \"1.0\", features = [\"unknown\"] }\n\
+             polkadot-sdk = { version = \"1.0.0\", features = [\"frame-support\", \"experimental\"] }\n"
+        );
+    }
+}
@@ -0,0 +1,266 @@
+use crate::{
+    atomic_write,
+    logging::output,
+    patch::{override_file, workspace_dir, workspace_root_package},
+    run_lock,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use std::{env::current_dir, fs, path::PathBuf, str::FromStr};
+use structopt::StructOpt;
+use toml_edit::{Document, Item, Table, Value};
+
+/// The comment in front of every patch entry and patch table that diener added.
+///
+/// Entries that replaced an existing entry carry the previous value after the marker, e.g.
+/// `# diener:patch was = { path = "../sp-io" }`.
+const MARKER: &str = "# diener:patch";
+
+/// Returns the prefix of the key `name` in the patch `table` that marks the entry as added by
+/// diener.
+///
+/// Must be called before the entry is modified, as the current value is recorded to restore it
+/// with `diener unpatch`. Entries that are already marked keep their original marker.
+pub(crate) fn marked_prefix(table: &Table, name: &str) -> String {
+    let prefix = table
+        .key_decor(name)
+        .and_then(|d| d.prefix())
+        .and_then(|p| p.as_str())
+        .unwrap_or_default();
+    if prefix.lines().any(is_marker) {
+        return prefix.into();
+    }
+
+    match table.get(name).and_then(|i| i.as_value()) {
+        Some(previous) => format!(
+            "{}{} was = {}\n",
+            prefix,
+            MARKER,
+            previous.to_string().trim()
+        ),
+        None => format!("{}{}\n", prefix, MARKER),
+    }
+}
+
+/// Set the `prefix` returned by [`marked_prefix`] for the key `name` after the entry was
+/// modified.
+pub(crate) fn mark_entry(table: &mut Table, name: &str, prefix: String) {
+    if let Some(decor) = table.key_decor_mut(name) {
+        decor.set_prefix(prefix);
+    }
+}
+
+/// Mark a patch `table` that was created by diener, it is removed with the last entry.
+pub(crate) fn mark_table(table: &mut Table) {
+    table.decor_mut().set_prefix(format!("\n{}\n", MARKER));
+}
+
+/// Returns `true` if `line` is a marker line.
+fn is_marker(line: &str) -> bool {
+    line == MARKER || line.starts_with(&format!("{} ", MARKER))
+}
+
+/// Returns `true` if the `prefix` of a key or table contains the marker.
+fn has_marker(prefix: Option<&str>) -> bool {
+    prefix.is_some_and(|p| p.lines().any(is_marker))
+}
+
+/// `unpatch` subcommand options.
+#[derive(Debug, StructOpt)]
+pub struct Unpatch {
+    /// The path to the project where the patches should be removed.
+    ///
+    /// If not given, the current directory will be taken. If this points to a `Cargo.toml`
+    /// file, this file will be taken as the cargo workspace `Cargo.toml` file.
+    #[structopt(long)]
+    path: Option<PathBuf>,
+
+    /// Remove the patches from the `.cargo/config.toml` next to the workspace `Cargo.toml`,
+    /// where `diener patch --rust-analyzer-friendly` added them.
+    ///
+    /// The file is removed if nothing else is left in it.
+    #[structopt(long)]
+    rust_analyzer_friendly: bool,
+}
+
+impl Unpatch {
+    /// Run this subcommand.
+    pub fn run(self) -> Result<()> {
+        let path = match self.path {
+            Some(path) if !path.exists() => {
+                bail!("Given --path=`{}` does not exist!", path.display())
+            }
+            Some(path) => path,
+            None => current_dir().with_context(|| "Working directory is invalid.")?,
+        };
+        let mut manifest = workspace_root_package(&path)?;
+        let _lock = run_lock::acquire(workspace_dir(&manifest))?;
+        if self.rust_analyzer_friendly {
+            manifest = override_file(&manifest);
+            if !manifest.exists() {
+                output!("{} does not exist, nothing to remove.", manifest.display());
+                return Ok(());
+            }
+        }
+
+        let content = fs::read_to_string(&manifest)
+            .with_context(|| anyhow!("Failed to read manifest at {}", manifest.display()))?;
+        let mut doc = Document::from_str(&content)
+            .with_context(|| anyhow!("Failed to parse {}", manifest.display()))?;
+
+        let removed = unpatch(&mut doc)?;
+        if removed == 0 {
+            output!("{} has no patches added by diener.", manifest.display());
+            return Ok(());
+        }
+
+        let content = doc.to_string();
+        if self.rust_analyzer_friendly && content.trim().is_empty() {
+            fs::remove_file(&manifest)
+                .with_context(|| anyhow!("Failed to remove {}", manifest.display()))?;
+        } else {
+            atomic_write::write(&manifest, content)
+                .with_context(|| anyhow!("Failed to write manifest to {}", manifest.display()))?;
+        }
+        output!(
+            "Removed {} patches added by diener from {}.",
+            removed,
+            manifest.display()
+        );
+        Ok(())
+    }
+}
+
+/// Remove all patch entries diener added to `doc` and restore the entries they replaced.
+///
+/// Returns the number of removed or restored entries.
+fn unpatch(doc: &mut Document) -> Result<usize> {
+    let patch = match doc.get_mut("patch").and_then(|p| p.as_table_mut()) {
+        Some(patch) => patch,
+        None => return Ok(0),
+    };
+
+    let mut removed = 0;
+    let targets = patch.iter().map(|(k, _)| k.to_string()).collect::<Vec<_>>();
+    for target in targets {
+        let table = match patch.get_mut(&target).and_then(|t| t.as_table_mut()) {
+            Some(table) => table,
+            None => continue,
+        };
+
+        let names = table.iter().map(|(k, _)| k.to_string()).collect::<Vec<_>>();
+        for name in names {
+            let prefix = table
+                .key_decor(&name)
+                .and_then(|d| d.prefix())
+                .and_then(|p| p.as_str())
+                .unwrap_or_default()
+                .to_string();
+            if !has_marker(Some(&prefix)) {
+                continue;
+            }
+
+            let previous = prefix
+                .lines()
+                .find(|l| is_marker(l))
+                .and_then(|l| l.strip_prefix(MARKER))
+                .and_then(|l| l.trim().strip_prefix("was ="));
+            match previous {
+                Some(previous) => {
+                    let value = Value::from_str(previous.trim()).map_err(|e| {
+                        anyhow!("Invalid previous value of the patch for `{}`: {}", name, e)
+                    })?;
+                    log::info!("Restoring the patch for `{}`", name);
+                    table.insert(&name, Item::Value(value.decorated(" ", "")));
+
+                    let prefix = prefix
+                        .lines()
+                        .filter(|l| !is_marker(l))
+                        .map(|l| format!("{}\n", l))
+                        .collect::<String>();
+                    mark_entry(table, &name, prefix);
+                }
+                None => {
+                    log::info!("Removing the patch for `{}`", name);
+                    table.remove(&name);
+                }
+            }
+            removed += 1;
+        }
+
+        let prefix = table.decor().prefix().and_then(|p| p.as_str());
+        if table.is_empty() && has_marker(prefix) {
+            patch.remove(&target);
+        }
+    }
+
+    if patch.is_empty() && patch.is_implicit() {
+        doc.remove("patch");
+    }
+
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unpatch_restores_the_previous_entries() {
+        let original = r#"[package]
+name = "foo"
+
+[patch."https://github.com/paritytech/substrate"]
+# Local checkout.
+sp-io = { path = "../sp-io" }
+"#;
+        let mut doc = Document::from_str(original).unwrap();
+        let patch = doc["patch"].as_table_mut().unwrap();
+        patch.set_implicit(true);
+
+        let substrate = patch["https://github.com/paritytech/substrate"]
+            .as_table_mut()
+            .unwrap();
+        for name in ["sp-io", "sp-core"] {
+            let prefix = marked_prefix(substrate, name);
+            substrate.insert(name, toml_edit::value("patched"));
+            mark_entry(substrate, name, prefix);
+        }
+
+        let mut polkadot = Table::new();
+        mark_table(&mut polkadot);
+        let prefix = marked_prefix(&polkadot, "xcm");
+        polkadot.insert("xcm", toml_edit::value("patched"));
+        mark_entry(&mut polkadot, "xcm", prefix);
+        patch.insert(
+            "https://github.com/paritytech/polkadot",
+            Item::Table(polkadot),
+        );
+
+        let patched = doc.to_string();
+        assert!(patched.contains("# diener:patch was = { path = \"../sp-io\" }\n"));
+        assert!(patched
+            .contains("\n# diener:patch\n[patch.\"https://github.com/paritytech/polkadot\"]"));
+
+        let mut doc = Document::from_str(&patched).unwrap();
+        assert_eq!(unpatch(&mut doc).unwrap(), 3);
+        assert_eq!(doc.to_string(), original);
+    }
+
+    #[test]
+    fn unpatch_removes_the_patch_table() {
+        let mut doc = Document::from_str("[package]\nname = \"foo\"\n").unwrap();
+        let mut patch = Table::new();
+        patch.set_implicit(true);
+        let mut target = Table::new();
+        mark_table(&mut target);
+        let prefix = marked_prefix(&target, "sp-io");
+        target.insert("sp-io", toml_edit::value("patched"));
+        mark_entry(&mut target, "sp-io", prefix);
+        patch.insert("crates-io", Item::Table(target));
+        doc.insert("patch", Item::Table(patch));
+
+        let mut doc = Document::from_str(&doc.to_string()).unwrap();
+        assert_eq!(unpatch(&mut doc).unwrap(), 1);
+        assert_eq!(doc.to_string(), "[package]\nname = \"foo\"\n");
+    }
+}
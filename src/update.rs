@@ -1,34 +1,399 @@
-use anyhow::{bail, ensure, Context, Result};
+use crate::{
+    atomic_write, audit, compat,
+    config::{self, Config, Overrides},
+    crates_io,
+    exit_code::{Interrupted, NothingMatched, PartialFailure},
+    features::{self, FeatureSet},
+    formatting::{Formatting, Style},
+    git,
+    hook::Hook,
+    http, interrupt, jobs,
+    lockfile::LockFile,
+    logging::output,
+    plan,
+    pull_request::PullRequest,
+    report::{self, Change},
+    repos::{
+        expand_git_template, is_git_template, same_repository, split_reference, with_scheme,
+        MatchOptions, Matcher, Repo, UrlScheme,
+    },
+    run_lock,
+    source_replacement::{self, Replacements},
+    tagged_manifest::TaggedManifests,
+    workspaces,
+};
+use anyhow::{anyhow, bail, ensure, Context, Result};
 use git_url_parse::GitUrl;
-use std::{env::current_dir, fs, path::PathBuf, str::FromStr};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::Deserialize;
+use std::{
+    cell::RefCell,
+    collections::{hash_map::Entry, BTreeMap, BTreeSet, HashMap},
+    env::current_dir,
+    fmt, fs,
+    io::Read,
+    path::{Path, PathBuf},
+    str::FromStr,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
 use structopt::StructOpt;
-use toml_edit::{Document, InlineTable, Value};
+use toml_edit::{Document, Item, Key, Table, TableLike, Value};
 use walkdir::{DirEntry, WalkDir};
 
 /// Which dependencies should be rewritten?
 #[derive(Debug, Clone)]
-enum Rewrite {
-    All,
+pub(crate) enum Rewrite {
+    All(Option<String>),
     Substrate(Option<String>),
     Polkadot(Option<String>),
     Cumulus(Option<String>),
     Beefy(Option<String>),
 }
 
+impl Rewrite {
+    /// Checks if a dependency pointing to the given `git` url should be rewritten.
+    ///
+    /// Returns the new `git` url that should be used, if the dependency matches.
+    pub(crate) fn matches(&self, git: &GitUrl, matcher: &Matcher) -> Option<&Option<String>> {
+        if !matcher.is_selected(git) {
+            return None;
+        }
+
+        // Legacy behavior: `--all` rewrites every git dependency.
+        if let Self::All(new_git) = self {
+            if matcher.name_only() {
+                return Some(new_git);
+            }
+        }
+
+        match (self, matcher.classify(git)?) {
+            (Self::All(new_git), _) => Some(new_git),
+            (Self::Substrate(new_git), Repo::Substrate) => Some(new_git),
+            (Self::Polkadot(new_git), Repo::Polkadot) => Some(new_git),
+            (Self::Cumulus(new_git), Repo::Cumulus) => Some(new_git),
+            (Self::Beefy(new_git), Repo::Beefy) => Some(new_git),
+            _ => None,
+        }
+    }
+
+    /// Returns the repositories that are rewritten, with the new `git` url to use for them.
+    ///
+    /// `git` templates are expanded with the official url of each repository.
+    pub(crate) fn repositories(&self) -> Vec<(Repo, Option<String>)> {
+        let (repos, git) = match self {
+            Self::All(git) => (Repo::ALL.to_vec(), git),
+            Self::Substrate(git) => (vec![Repo::Substrate], git),
+            Self::Polkadot(git) => (vec![Repo::Polkadot], git),
+            Self::Cumulus(git) => (vec![Repo::Cumulus], git),
+            Self::Beefy(git) => (vec![Repo::Beefy], git),
+        };
+
+        repos
+            .into_iter()
+            .map(|r| {
+                let git = git.as_ref().map(|g| {
+                    GitUrl::parse(&r.url())
+                        .map(|official| expand_git_template(g, &official))
+                        .unwrap_or_else(|_| g.clone())
+                });
+                (r, git)
+            })
+            .collect()
+    }
+}
+
 /// The version the dependencies should be switched to.
 #[derive(Debug, Clone)]
-enum Version {
+pub(crate) enum Version {
     Tag(String),
     Branch(String),
     Rev(String),
+    /// Pin `branch` dependencies to the `rev` they are locked to in the `Cargo.lock`.
+    Freeze,
+    /// Use the `rev` that is pinned in the `Cargo.lock` given to `--rev-from`.
+    RevFrom,
+    /// Switch `branch` dependencies whose branch no longer exists upstream to the default
+    /// branch of the repository.
+    DefaultBranch,
+    /// Switch to a version on `crates.io`.
+    Registry(RegistryVersion),
+}
+
+impl Version {
+    /// Returns the version as `(key, value)`, e.g. `("branch", "master")`.
+    ///
+    /// Returns `None` for `Freeze`, `RevFrom` and `DefaultBranch`, as the version depends on
+    /// the dependency, and for `Registry`, which isn't a git reference.
+    pub(crate) fn reference(&self) -> Option<(&'static str, &str)> {
+        match self {
+            Self::Tag(tag) => Some(("tag", tag)),
+            Self::Branch(branch) => Some(("branch", branch)),
+            Self::Rev(rev) => Some(("rev", rev)),
+            Self::Freeze | Self::RevFrom | Self::DefaultBranch | Self::Registry(_) => None,
+        }
+    }
+}
+
+/// The version on `crates.io` given with `--version`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum RegistryVersion {
+    /// The newest stable release.
+    Latest,
+    /// The newest stable release with the given major version, `latest:MAJOR`.
+    LatestMajor(u64),
+    /// A version requirement, written as is.
+    Requirement(String),
+    /// The version in the manifest of the crate at the given tag of its repository, see
+    /// `--version-from-tag`.
+    FromTag(String),
+    /// The version of the crate in the `Cargo.lock` of `--consistent-release`.
+    Locked,
+}
+
+impl FromStr for RegistryVersion {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(':') {
+            _ if s == "latest" => Ok(Self::Latest),
+            Some(("latest", major)) => major
+                .parse()
+                .map(Self::LatestMajor)
+                .map_err(|_| anyhow!("Invalid `--version` `{}`, expected `latest:MAJOR`.", s)),
+            _ => {
+                semver::VersionReq::parse(s)
+                    .with_context(|| anyhow!("Invalid `--version` `{}`", s))?;
+                Ok(Self::Requirement(s.into()))
+            }
+        }
+    }
+}
+
+impl fmt::Display for RegistryVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Latest => write!(f, "latest"),
+            Self::LatestMajor(major) => write!(f, "latest:{}", major),
+            Self::Requirement(requirement) | Self::FromTag(requirement) => {
+                write!(f, "{}", requirement)
+            }
+            Self::Locked => write!(f, "locked"),
+        }
+    }
+}
+
+impl RegistryVersion {
+    /// Select the version to use from the published `versions` of a crate.
+    ///
+    /// Pre-releases are only selected by an explicit requirement.
+    fn select(&self, versions: &[semver::Version]) -> Option<String> {
+        let stable = versions.iter().filter(|v| v.pre.is_empty());
+        match self {
+            Self::Latest => stable.max().map(ToString::to_string),
+            Self::LatestMajor(major) => stable
+                .filter(|v| v.major == *major)
+                .max()
+                .map(ToString::to_string),
+            Self::Requirement(requirement) => Some(requirement.clone()),
+            Self::FromTag(_) | Self::Locked => None,
+        }
+    }
+}
+
+/// What to do with a registry dependency that isn't published on `crates.io`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OnMissing {
+    /// Leave the dependency untouched without a warning.
+    Skip,
+    /// Keep the current version of the dependency and warn about it.
+    Keep,
+    /// Abort the run.
+    Fail,
+}
+
+impl FromStr for OnMissing {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "skip" => Ok(Self::Skip),
+            "keep" => Ok(Self::Keep),
+            "fail" => Ok(Self::Fail),
+            _ => bail!(
+                "Unknown `--on-missing` `{}`, expected `skip`, `keep` or `fail`.",
+                s
+            ),
+        }
+    }
+}
+
+/// What happens to the `version` of a git dependency, e.g. `{ git = "...", version = "4.0" }`
+/// for publishing the crate, when it is rewritten.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VersionKey {
+    /// Keep the requirement as is.
+    Keep,
+    /// Remove the requirement, `--drop-version`.
+    Drop,
+    /// Set the requirement to the version of the crate at the new reference, `--sync-version`.
+    Sync,
+}
+
+/// What `--emit` prints instead of modifying the manifests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Emit {
+    /// The `[patch."URL"]` tables that apply the rewrite.
+    Patch,
+}
+
+impl FromStr for Emit {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "patch" => Ok(Self::Patch),
+            _ => bail!("Unknown `--emit` `{}`, expected `patch`.", s),
+        }
+    }
+}
+
+/// The patches printed by `--emit patch`, patched url -> crate -> source keys and values.
+type Patches = BTreeMap<String, BTreeMap<String, Vec<(&'static str, String)>>>;
+
+/// Everything that is required to decide if and how a dependency should be rewritten.
+struct Rules {
+    /// The dependencies to rewrite and the version to use for them.
+    ///
+    /// The first entry matching a dependency wins.
+    targets: Vec<(Rewrite, Version)>,
+    matcher: Matcher,
+    url_scheme: Option<UrlScheme>,
+    /// Also rewrite the cargo config files.
+    cargo_config: bool,
+    /// Only rewrite the `[dev-dependencies]`.
+    dev_only: bool,
+    /// Crates that were renamed upstream, old name -> new name.
+    renames: BTreeMap<String, String>,
+    /// Crates that use a different version (and `git` url) than the matching target.
+    overrides: Overrides,
+    /// The command to run for the modified files.
+    hook: Option<Hook>,
+    /// The extension of the copies of the modified files, see `--backup-ext`.
+    backup_ext: Option<String>,
+    /// Upgrade all registry dependencies to their latest compatible version.
+    crates_io_deps: bool,
+    /// The features the crates that are declared with different features are unified to, by
+    /// manifest and crate, for `--normalize-features`.
+    unified_features: HashMap<PathBuf, BTreeMap<String, FeatureSet>>,
+    /// The fields of `[workspace.package]` that are set, field -> value.
+    workspace_package: BTreeMap<String, String>,
+    /// How the inserted `git`/`branch`/`tag`/`rev`/`version` entries are formatted.
+    formatting: Formatting,
+    /// What to do with registry dependencies that are not published.
+    on_missing: OnMissing,
+    /// What happens to the `version` of the rewritten git dependencies.
+    version_key: VersionKey,
+    /// The versions of the registry crates that were already fetched.
+    crate_versions: RefCell<HashMap<String, Option<Vec<semver::Version>>>>,
+    /// The versions of the registry crates that were fetched in parallel before the manifests
+    /// are processed, see [`prefetch_registry_versions`].
+    prefetched_versions: RefCell<HashMap<String, Option<Vec<semver::Version>>>>,
+    /// The git urls that were already parsed.
+    ///
+    /// Parsing is expensive and large workspaces use the same few urls over and over again.
+    git_urls: RefCell<HashMap<String, Option<GitUrl>>>,
+    /// The `Cargo.lock` of the reference project used by `--rev-from`.
+    rev_from: Option<LockFile>,
+    /// The `Cargo.lock` of the release used by `--consistent-release`.
+    release_lock: Option<LockFile>,
+    /// The branches of the remote repositories that were already fetched, by url.
+    remote_branches: RefCell<HashMap<String, Option<git::RemoteBranches>>>,
+    /// The manifests of the repositories at the tag of `--version-from-tag` or the reference of
+    /// `--sync-version`, by url and reference.
+    tagged_manifests: RefCell<HashMap<String, Option<TaggedManifests>>>,
+    /// Fail for git dependencies that can not be parsed or escape the matching.
+    strict: bool,
+    /// For `diener plan` the files are not written, but their old and new content is
+    /// recorded here.
+    planned: Option<RefCell<BTreeMap<PathBuf, (String, String)>>>,
+    /// For `--emit patch` the rewritten git dependencies are recorded here.
+    patches: Option<RefCell<Patches>>,
+}
+
+impl Rules {
+    /// Parse the given git `url`, returns `None` if it is invalid.
+    fn parse_git_url(&self, url: &str) -> Option<GitUrl> {
+        self.git_urls
+            .borrow_mut()
+            .entry(url.into())
+            .or_insert_with(|| GitUrl::parse(url).ok())
+            .clone()
+    }
+
+    /// Record the new `source` of `package` from the repository at `url` for `--emit patch`.
+    fn record_patch(&self, url: &str, package: &str, source: Vec<(&'static str, String)>) {
+        let mut patches = match &self.patches {
+            Some(patches) => patches.borrow_mut(),
+            None => return,
+        };
+
+        let previous = patches
+            .entry(url.into())
+            .or_default()
+            .insert(package.into(), source.clone());
+        if previous.is_some_and(|p| p != source) {
+            log::warn!(
+                "`{}` is rewritten to different sources, only the last one is patched.",
+                package
+            );
+        }
+    }
+}
+
+/// A directory tree in which the dependencies are rewritten.
+struct Root {
+    path: PathBuf,
+    /// Directories and files that should not be touched, relative to `path`.
+    skip_paths: GlobSet,
+    /// The `Cargo.lock` used by `--freeze`.
+    lock_file: Option<LockFile>,
+    /// The source replacements of the cargo config files that apply to `path`.
+    replacements: Replacements,
+    /// Set for the checkouts of git submodules found by `--recurse-submodules`.
+    submodule: bool,
 }
 
 /// `update` subcommand options.
 #[derive(Debug, StructOpt)]
 pub struct Update {
     /// The path where Diener should search for `Cargo.toml` files.
+    ///
+    /// Can be passed multiple times to update several repositories with the same rules.
+    #[structopt(long)]
+    path: Vec<PathBuf>,
+
+    /// Skip all directories and files matching the given glob, e.g. `examples/**`.
+    ///
+    /// The glob is matched against the path relative to `--path`. Can be passed multiple
+    /// times.
+    #[structopt(long)]
+    skip_path: Vec<String>,
+
+    /// Also alter the manifests that are excluded from the workspace.
+    ///
+    /// By default the directories listed in `workspace.exclude` of the `Cargo.toml` at
+    /// `--path` are skipped.
     #[structopt(long)]
-    path: Option<PathBuf>,
+    include_excluded: bool,
+
+    /// Also update the checkouts of the git submodules below `--path`, recursively.
+    ///
+    /// Every submodule is handled like an additional `--path`: with its own `workspace.exclude`,
+    /// check for uncommitted changes and summary. Submodules that aren't initialized are
+    /// skipped.
+    #[structopt(long, conflicts_with = "open-pr")]
+    recurse_submodules: bool,
 
     /// Only alter Substrate dependencies.
     #[structopt(long, short = "s")]
@@ -51,162 +416,2629 @@ pub struct Update {
     all: bool,
 
     /// The `branch` that the dependencies should use.
-    #[structopt(long, conflicts_with_all = &[ "rev", "tag" ])]
+    #[structopt(
+        long,
+        conflicts_with_all = &[ "rev", "tag", "freeze", "rev-from", "fix-default-branch", "version" ]
+    )]
     branch: Option<String>,
 
     /// The `rev` that the dependencies should use.
-    #[structopt(long, conflicts_with_all = &[ "branch", "tag" ])]
+    ///
+    /// An abbreviated commit hash is resolved to the full hash in the repository.
+    #[structopt(
+        long,
+        conflicts_with_all = &[ "branch", "tag", "freeze", "rev-from", "fix-default-branch", "version" ]
+    )]
     rev: Option<String>,
 
     /// The `tag` that the dependencies should use.
-    #[structopt(long, conflicts_with_all = &[ "rev", "branch" ])]
+    #[structopt(
+        long,
+        conflicts_with_all = &[ "rev", "branch", "freeze", "rev-from", "fix-default-branch", "version" ]
+    )]
     tag: Option<String>,
 
+    /// Pin all dependencies that use a `branch` to the `rev` they are currently locked to.
+    ///
+    /// The revisions are taken from the workspace `Cargo.lock`.
+    #[structopt(
+        long,
+        conflicts_with_all = &[ "rev", "branch", "tag", "rev-from", "fix-default-branch", "version" ]
+    )]
+    freeze: bool,
+
+    /// Use the revisions another project is pinned to.
+    ///
+    /// Takes the `Cargo.lock` of the other project, either a path, an url, `-` for stdin or
+    /// `release:OWNER/REPO@TAG` for a GitHub release, and sets the `rev` of every dependency
+    /// to the commit of the same repository in it.
+    #[structopt(
+        long,
+        conflicts_with_all = &[ "rev", "branch", "tag", "freeze", "fix-default-branch", "version" ]
+    )]
+    rev_from: Option<String>,
+
+    /// Switch dependencies whose `branch` no longer exists upstream to the default branch of
+    /// the repository.
+    ///
+    /// E.g. `branch = "master"` becomes `branch = "main"` after the repository renamed its
+    /// default branch. The branches are looked up with `git ls-remote`, dependencies whose
+    /// branch still exists are not changed.
+    #[structopt(
+        long,
+        conflicts_with_all = &[ "rev", "branch", "tag", "freeze", "rev-from", "version" ]
+    )]
+    fix_default_branch: bool,
+
+    /// Switch the dependencies from git to the given version on `crates.io`.
+    ///
+    /// Either a version requirement like `1.2`, `latest` for the newest stable release or
+    /// `latest:MAJOR` (e.g. `latest:4`) for the newest stable release with the given major
+    /// version. The versions are looked up for every crate on its own.
+    #[structopt(long)]
+    version: Option<RegistryVersion>,
+
+    /// Together with `--tag`, switch the dependencies to `crates.io` with the version their
+    /// manifest has at the tag.
+    ///
+    /// The manifests are fetched from GitHub, for repositories where the tag names don't match
+    /// the crate versions.
+    #[structopt(long, requires = "tag", conflicts_with = "version")]
+    version_from_tag: bool,
+
+    /// Remove the `version` of the rewritten git dependencies.
+    ///
+    /// By default a `version` next to `git`, e.g. for publishing the crate, is kept as is.
+    #[structopt(long, conflicts_with = "sync-version")]
+    drop_version: bool,
+
+    /// Set the `version` of the rewritten git dependencies to the version of the crate at the
+    /// new `branch`, `tag` or `rev`.
+    ///
+    /// The manifests are fetched from GitHub. Dependencies without a `version` don't get one.
+    #[structopt(long)]
+    sync_version: bool,
+
+    /// Switch the dependencies from git to `crates.io`, with the versions of one release.
+    ///
+    /// Takes a `Cargo.lock` like `--rev-from`, e.g. the one of the release of the repository
+    /// with `release:OWNER/REPO@TAG`. Every crate gets the version it has in this `Cargo.lock`,
+    /// so all crates of a repository are on the same release.
+    #[structopt(
+        long,
+        conflicts_with_all = &[
+            "branch", "rev", "tag", "freeze", "rev-from", "fix-default-branch", "version",
+            "profile", "companion",
+        ]
+    )]
+    consistent_release: Option<String>,
+
     /// Rewrite the `git` url to the give one.
+    ///
+    /// The url can contain the placeholders `{org}` and `{repo}`, which are replaced by the
+    /// owner and name of the original url, e.g. `https://github.com/myorg/{repo}`. With
+    /// placeholders, `--git` can also be used with `--all`.
     #[structopt(long)]
     git: Option<String>,
+
+    /// Rewrite the `git` urls of all altered dependencies to use the given scheme.
+    ///
+    /// Cargo treats `https` and `ssh` urls of the same repository as different sources,
+    /// so mixing them leads to duplicated crates. Urls with a custom port keep it, but can
+    /// not be switched to the other scheme.
+    #[structopt(long, possible_values = &[ "https", "ssh" ])]
+    url_scheme: Option<UrlScheme>,
+
+    /// The config file to use.
+    ///
+    /// Defaults to `diener.toml` at the first `--path`, if it exists.
+    #[structopt(long)]
+    config: Option<PathBuf>,
+
+    /// Use the repositories and versions of the given profile from the config file.
+    ///
+    /// A profile is declared as `[profile.NAME]` in the config file and maps the
+    /// repositories (`substrate`, `polkadot`, `cumulus` or `beefy`) to the `branch`, `tag`
+    /// or `rev` (and optionally `git`) they should use.
+    #[structopt(
+        long,
+        conflicts_with_all = &[
+            "substrate", "polkadot", "cumulus", "beefy", "all",
+            "branch", "rev", "tag", "freeze", "rev-from", "fix-default-branch", "version", "git",
+        ]
+    )]
+    profile: Option<String>,
+
+    /// Test a companion pull request, e.g. `paritytech/polkadot#1234`.
+    ///
+    /// Looks up the fork and branch of the given pull request on GitHub and switches all
+    /// dependencies of the pull request's repository to them.
+    #[structopt(
+        long,
+        conflicts_with_all = &[
+            "substrate", "polkadot", "cumulus", "beefy", "all",
+            "branch", "rev", "tag", "freeze", "rev-from", "fix-default-branch", "version", "git",
+            "profile",
+        ]
+    )]
+    companion: Option<String>,
+
+    /// Upgrade all `crates.io` dependencies to their latest semver compatible version.
+    ///
+    /// `foo = "1.2"` becomes `foo = "1.5.3"`, if `1.5.3` is the latest `1.x` release. Exact
+    /// (`=1.2.3`) and complex requirements are not changed. Can be used alone or together
+    /// with the options for the git dependencies.
+    #[structopt(long)]
+    crates_io_deps: bool,
+
+    /// What to do with dependencies that are not published on `crates.io`, e.g. internal
+    /// pallets, when upgrading them with `--crates-io-deps`.
+    ///
+    /// `skip` leaves them untouched silently, `keep` keeps their current version with a
+    /// warning and `fail` aborts.
+    #[structopt(long, default_value = "keep", possible_values = &[ "skip", "keep", "fail" ])]
+    on_missing: OnMissing,
+
+    /// Print the rewrite to stdout instead of modifying the manifests.
+    ///
+    /// `patch` prints a `[patch."URL"]` table for every repository with an entry for every
+    /// rewritten crate, to be pasted into the manifest of choice. Only git dependencies are
+    /// included, as cargo can't patch a registry dependency with another version from the
+    /// same registry.
+    #[structopt(
+        long,
+        possible_values = &[ "patch" ],
+        conflicts_with_all = &[ "watch", "open-pr", "update-lockfiles", "cargo-config" ]
+    )]
+    emit: Option<Emit>,
+
+    /// Unify the features of crates that are declared with different `default-features` or
+    /// `features` in the manifests of a workspace.
+    ///
+    /// The crates are always reported, with this the declarations are rewritten to disable
+    /// the default features if one of them does and to enable all features of all of them.
+    /// Can be used alone or together with the other options.
+    #[structopt(long)]
+    normalize_features: bool,
+
+    /// Set a field of the `[workspace.package]` table, e.g. `rust-version=1.75` or
+    /// `version=1.2.0`.
+    ///
+    /// Only fields that are already declared in a workspace are changed. Fields can also be
+    /// declared in the `[workspace-package]` table of the config file. Can be used alone or
+    /// together with the other options and can be passed multiple times.
+    #[structopt(long)]
+    workspace_package: Vec<String>,
+
+    /// A file with crates that should use a different source than the other dependencies.
+    ///
+    /// The file maps crate names to their source, e.g. `sp-io = { rev = "abc123" }`, and
+    /// applies to the dependencies that are rewritten. Overrides can also be declared in the
+    /// `[override]` table of the config file.
+    #[structopt(long)]
+    overrides: Option<PathBuf>,
+
+    /// Rename a crate that was renamed upstream, e.g.
+    /// `beefy-primitives=sp-consensus-beefy`.
+    ///
+    /// The dependency key (or the `package` of a renamed dependency) of every rewritten
+    /// dependency is changed to the new name. Renames can also be declared in the `[rename]`
+    /// table of the config file. Can be passed multiple times.
+    #[structopt(long)]
+    rename: Vec<String>,
+
+    /// Also rewrite the `[patch]` sections and git `[source]` replacements in the cargo
+    /// config files (`.cargo/config.toml`).
+    #[structopt(long)]
+    cargo_config: bool,
+
+    /// Only rewrite the `[dev-dependencies]`, leaving the normal and build dependencies
+    /// untouched.
+    #[structopt(long, conflicts_with = "cargo-config")]
+    dev_only: bool,
+
+    /// A shell command to run for every modified file, with the path of the file appended.
+    ///
+    /// E.g. `--hook "taplo fmt"` to format the modified manifests.
+    #[structopt(long)]
+    hook: Option<String>,
+
+    /// Run the `--hook` only once at the end, with the paths of all modified files appended.
+    #[structopt(long, requires = "hook")]
+    hook_once: bool,
+
+    /// Exit with a distinct exit code if no dependency was rewritten.
+    ///
+    /// See the crate documentation for all exit codes.
+    #[structopt(long)]
+    detailed_exit_codes: bool,
+
+    /// Fail if no dependency was rewritten.
+    ///
+    /// A run that changes nothing is usually caused by a wrong `--path` or repository
+    /// selection. Exits with the same code as `--detailed-exit-codes`.
+    #[structopt(long)]
+    expect_changes: bool,
+
+    /// Check that the selected versions of the repositories fit together.
+    ///
+    /// Fetches the `Cargo.lock` of every selected repository at the selected version and
+    /// warns if it pins one of the other selected repositories to a different version.
+    #[structopt(long)]
+    check_compat: bool,
+
+    /// Fail for git dependencies that would silently be skipped.
+    ///
+    /// These are dependencies with a git url that can not be parsed and dependencies whose
+    /// repository has the name of a known repository, but doesn't match any accepted url.
+    #[structopt(long)]
+    strict: bool,
+
+    /// Run even if `Cargo.toml` files at `--path` have uncommitted changes.
+    ///
+    /// By default diener refuses to run in that case, as its changes could otherwise not be
+    /// separated from the manual ones.
+    #[structopt(long)]
+    force: bool,
+
+    /// Keep running and re-apply the rewrite to every new or changed `Cargo.toml`.
+    #[structopt(long)]
+    watch: bool,
+
+    /// The interval in seconds in which `--watch` checks for changed files.
+    #[structopt(long, default_value = "2")]
+    watch_interval: u64,
+
+    /// Stop after the given number of seconds.
+    ///
+    /// Like on Ctrl-C, the run stops after the file that is currently processed. With `--watch`,
+    /// watching stops.
+    #[structopt(long)]
+    timeout: Option<u64>,
+
+    /// Restore the modified files when the run is interrupted by Ctrl-C or `--timeout`.
+    #[structopt(long)]
+    revert_on_interrupt: bool,
+
+    /// Keep a copy of every modified file with the given extension appended, e.g. `.bak`
+    /// writes `Cargo.toml.bak` next to `Cargo.toml`.
+    ///
+    /// A missing leading `.` is added. The copy is written right before the file is rewritten,
+    /// an existing copy is overwritten.
+    #[structopt(long)]
+    backup_ext: Option<String>,
+
+    /// Run `cargo update --workspace` for every workspace with modified manifests.
+    ///
+    /// Nested workspaces have their own `Cargo.lock`, so each is updated on its own.
+    #[structopt(long)]
+    update_lockfiles: bool,
+
+    /// Commit the changes to a new branch, push it and open a pull request on GitHub.
+    ///
+    /// Requires a GitHub token and a single `--path` inside a git repository whose remote is
+    /// on GitHub. The pull request targets the branch that is checked out.
+    #[structopt(long, conflicts_with = "watch")]
+    open_pr: bool,
+
+    /// The branch the changes are committed to for `--open-pr`.
+    ///
+    /// Defaults to `diener/update-TIMESTAMP`.
+    #[structopt(long, requires = "open-pr")]
+    pr_branch: Option<String>,
+
+    /// The git remote the branch is pushed to for `--open-pr`.
+    ///
+    /// Defaults to `origin`.
+    #[structopt(long, requires = "open-pr")]
+    pr_remote: Option<String>,
+
+    /// The title of the pull request opened by `--open-pr`.
+    ///
+    /// `{rewritten}` is replaced by the number of rewritten dependencies, `{files}` by the
+    /// number of modified files and `{changes}` by a list of all changes. Defaults to
+    /// `Update {rewritten} dependencies`.
+    #[structopt(long, requires = "open-pr")]
+    pr_title: Option<String>,
+
+    /// The body of the pull request opened by `--open-pr`.
+    ///
+    /// Supports the same placeholders as `--pr-title` and defaults to a list of all changes.
+    #[structopt(long, requires = "open-pr")]
+    pr_body: Option<String>,
+
+    /// Append a record of every run to the given file.
+    ///
+    /// Each record is a JSON object on its own line, with the arguments, the modified files
+    /// and the old and new source of every rewritten dependency.
+    #[structopt(long)]
+    audit_log: Option<PathBuf>,
+
+    /// Write a summary of the changes in the given format after the run.
+    ///
+    /// `markdown` is a table with the crate, the file and the old and new source of every
+    /// rewritten dependency, to be posted as a pull request comment by a CI bot. Unlike
+    /// `--audit-log`, it is meant to be read by humans.
+    #[structopt(long, possible_values = &[ "markdown" ], conflicts_with = "watch")]
+    report: Option<report::Format>,
+
+    /// The file the summary of `--report` is written to, instead of stdout.
+    #[structopt(long, requires = "report")]
+    report_file: Option<PathBuf>,
+
+    #[structopt(flatten)]
+    match_options: MatchOptions,
 }
 
 impl Update {
-    /// Convert the options into the parts `Rewrite`, `Version`, `Option<PathBuf>`.
-    fn into_parts(self) -> Result<(Rewrite, Version, Option<PathBuf>)> {
-        let version = if let Some(branch) = self.branch {
-            Version::Branch(branch)
-        } else if let Some(rev) = self.rev {
-            Version::Rev(rev)
-        } else if let Some(tag) = self.tag {
-            Version::Tag(tag)
+    /// Convert the options into the parts `Rules`, `Vec<Root>`.
+    fn into_parts(self) -> Result<(Rules, Vec<Root>)> {
+        let paths = if self.path.is_empty() {
+            vec![current_dir().with_context(|| "Working directory is invalid.")?]
+        } else {
+            self.path.clone()
+        };
+
+        let mut roots = Vec::new();
+        for path in paths {
+            ensure!(
+                path.is_dir(),
+                "Path '{}' is not a directory.",
+                path.display()
+            );
+
+            let submodules = if self.recurse_submodules {
+                git::submodules(&path)?
+            } else {
+                Vec::new()
+            };
+            if !submodules.is_empty() {
+                log::info!(
+                    "Found {} git submodules in {}",
+                    submodules.len(),
+                    path.display()
+                );
+            }
+
+            let checkouts = submodules.iter().map(|s| (s.clone(), true));
+            for (path, submodule) in std::iter::once((path, false)).chain(checkouts) {
+                let mut excluded = if self.include_excluded {
+                    Vec::new()
+                } else {
+                    workspace_excludes(&path)?
+                };
+                // The submodules below a checkout are handled as roots of their own.
+                excluded.extend(
+                    submodules
+                        .iter()
+                        .filter_map(|s| s.strip_prefix(&path).ok())
+                        .filter(|s| !s.as_os_str().is_empty())
+                        .map(|s| s.to_string_lossy().into_owned()),
+                );
+                roots.push(Root {
+                    skip_paths: skip_paths(&self.skip_path, &excluded)?,
+                    lock_file: None,
+                    replacements: Replacements::load(&path)?,
+                    path,
+                    submodule,
+                });
+            }
+        }
+        let path = &roots[0].path;
+
+        let config = Config::load(self.config.as_deref(), path)?;
+        let targets = if let Some(ref profile) = self.profile {
+            config
+                .profile(profile)?
+                .repos
+                .iter()
+                .map(|(repo, settings)| {
+                    (repo.rewrite(settings.git.clone()), settings.version.clone())
+                })
+                .collect()
+        } else if let Some(ref companion) = self.companion {
+            vec![companion_target(companion)?]
+        } else if (self.crates_io_deps
+            || self.normalize_features
+            || !self.workspace_package.is_empty()
+            || !config.workspace_package.is_empty())
+            && !self.has_version()
+        {
+            Vec::new()
+        } else {
+            vec![self.target()?]
+        };
+
+        let mut renames = config.renames;
+        for rename in &self.rename {
+            let (old, new) = rename
+                .split_once('=')
+                .filter(|(o, n)| !o.is_empty() && !n.is_empty())
+                .ok_or_else(|| anyhow!("Invalid `--rename` `{}`, expected `OLD=NEW`.", rename))?;
+            renames.insert(old.into(), new.into());
+        }
+
+        let mut workspace_package = config.workspace_package;
+        for field in &self.workspace_package {
+            let (field, value) = field
+                .split_once('=')
+                .filter(|(f, v)| !f.is_empty() && !v.is_empty())
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Invalid `--workspace-package` `{}`, expected `FIELD=VALUE`.",
+                        field
+                    )
+                })?;
+            config::check_workspace_package_field(field, value)?;
+            workspace_package.insert(field.into(), value.into());
+        }
+
+        let mut overrides = config.overrides;
+        if let Some(ref file) = self.overrides {
+            overrides.extend(config::read_overrides(file)?);
+        }
+
+        let rules = Rules {
+            targets,
+            matcher: Matcher::new(self.match_options)?
+                .with_classify_rules(&config.classify_rules)
+                .with_url_rules(&config.url_rules),
+            url_scheme: self.url_scheme,
+            cargo_config: self.cargo_config,
+            dev_only: self.dev_only,
+            renames,
+            overrides,
+            hook: self.hook.clone().map(|command| Hook {
+                command,
+                once: self.hook_once,
+            }),
+            backup_ext: self.backup_ext.clone(),
+            crates_io_deps: self.crates_io_deps,
+            unified_features: Default::default(),
+            workspace_package,
+            formatting: config.formatting,
+            on_missing: self.on_missing,
+            version_key: match (self.drop_version, self.sync_version) {
+                (true, _) => VersionKey::Drop,
+                (_, true) => VersionKey::Sync,
+                _ => VersionKey::Keep,
+            },
+            crate_versions: Default::default(),
+            prefetched_versions: Default::default(),
+            git_urls: Default::default(),
+            rev_from: self
+                .rev_from
+                .as_deref()
+                .map(LockFile::load)
+                .transpose()
+                .context("Failed to load the `Cargo.lock` given to `--rev-from`")?,
+            release_lock: self
+                .consistent_release
+                .as_deref()
+                .map(LockFile::load)
+                .transpose()
+                .context("Failed to load the `Cargo.lock` given to `--consistent-release`")?,
+            remote_branches: Default::default(),
+            tagged_manifests: Default::default(),
+            strict: self.strict,
+            planned: None,
+            patches: None,
+        };
+
+        Ok((rules, roots))
+    }
+
+    /// Returns `true` if one of `--branch`, `--rev`, `--tag`, `--freeze`, `--rev-from`,
+    /// `--fix-default-branch`, `--version` or `--consistent-release` was passed.
+    fn has_version(&self) -> bool {
+        self.branch.is_some()
+            || self.rev.is_some()
+            || self.tag.is_some()
+            || self.freeze
+            || self.rev_from.is_some()
+            || self.fix_default_branch
+            || self.version.is_some()
+            || self.consistent_release.is_some()
+    }
+
+    /// Returns the target given by the `--substrate`, `--branch`, ... options.
+    fn target(&self) -> Result<(Rewrite, Version)> {
+        let version = if let Some(ref branch) = self.branch {
+            Version::Branch(branch.clone())
+        } else if let Some(ref rev) = self.rev {
+            Version::Rev(rev.clone())
+        } else if let Some(ref tag) = self.tag {
+            if self.version_from_tag {
+                Version::Registry(RegistryVersion::FromTag(tag.clone()))
+            } else {
+                Version::Tag(tag.clone())
+            }
+        } else if self.freeze {
+            Version::Freeze
+        } else if self.rev_from.is_some() {
+            Version::RevFrom
+        } else if self.fix_default_branch {
+            Version::DefaultBranch
+        } else if let Some(ref version) = self.version {
+            Version::Registry(version.clone())
+        } else if self.consistent_release.is_some() {
+            Version::Registry(RegistryVersion::Locked)
         } else {
-            bail!("You need to pass `--branch`, `--tag` or `--rev`");
+            bail!("You need to pass `--branch`, `--tag`, `--rev`, `--freeze`, `--rev-from`, `--fix-default-branch`, `--version`, `--consistent-release`, `--profile`, `--crates-io-deps`, `--normalize-features` or `--workspace-package`");
         };
 
+        let git = self.git.clone();
         let rewrite = if self.all {
-            if self.git.is_some() {
-                bail!("You need to pass `--substrate`, `--polkadot`, `--cumulus` or `--beefy` for `--git`.");
+            if git.as_deref().is_some_and(|g| !is_git_template(g)) {
+                bail!("You need to pass `--substrate`, `--polkadot`, `--cumulus` or `--beefy` for `--git` without placeholders.");
             } else {
-                Rewrite::All
+                Rewrite::All(git)
             }
         } else if self.substrate {
-            Rewrite::Substrate(self.git)
+            Rewrite::Substrate(git)
         } else if self.beefy {
-            Rewrite::Beefy(self.git)
+            Rewrite::Beefy(git)
         } else if self.polkadot {
-            Rewrite::Polkadot(self.git)
+            Rewrite::Polkadot(git)
         } else if self.cumulus {
-            Rewrite::Cumulus(self.git)
+            Rewrite::Cumulus(git)
         } else {
-            bail!("You must specify one of `--substrate`, `--polkadot`, `--cumulus`, `--beefy` or `--all`.");
+            bail!("You must specify one of `--substrate`, `--polkadot`, `--cumulus`, `--beefy`, `--all` or `--profile`.");
         };
 
-        Ok((rewrite, version, self.path))
+        Ok((rewrite, version))
     }
 
     /// Run this subcommand.
     pub fn run(self) -> Result<()> {
-        let (rewrite, version, path) = self.into_parts()?;
+        self.execute(false).map(drop)
+    }
 
-        let path = path
-            .map(Ok)
-            .unwrap_or_else(|| current_dir().with_context(|| "Working directory is invalid."))?;
+    /// Compute the changes without writing them, for `diener plan`.
+    pub(crate) fn plan(self) -> Result<Vec<plan::File>> {
+        ensure!(!self.watch, "`--watch` can not be used with `diener plan`.");
+        ensure!(
+            !self.open_pr,
+            "`--open-pr` can not be used with `diener plan`."
+        );
         ensure!(
-            path.is_dir(),
-            "Path '{}' is not a directory.",
-            path.display()
+            self.emit.is_none(),
+            "`--emit` can not be used with `diener plan`."
         );
+        self.execute(true)
+    }
+
+    /// Rewrite the dependencies, returns the planned files if `plan` is `true`.
+    fn execute(self, plan: bool) -> Result<Vec<plan::File>> {
+        let fail_unchanged = self.detailed_exit_codes || self.expect_changes;
+        let watch = self.watch.then(|| Duration::from_secs(self.watch_interval));
+        let force = self.force;
+        let check_compat = self.check_compat;
+        let audit_log = self.audit_log.clone();
+        let report = self.report;
+        let report_file = self.report_file.clone();
+        let deadline = self
+            .timeout
+            .map(|t| Instant::now() + Duration::from_secs(t));
+        let revert_on_interrupt = self.revert_on_interrupt;
+        let update_lockfiles = self.update_lockfiles;
+        let normalize_features = self.normalize_features;
+        let emit = self.emit;
+        // Like for a plan, nothing is written when emitting the rewrite.
+        let plan = plan || emit.is_some();
+        let pull_request = self.open_pr.then(|| PullRequest {
+            remote: self.pr_remote.clone().unwrap_or_else(|| "origin".into()),
+            branch: self.pr_branch.clone(),
+            title: self.pr_title.clone(),
+            body: self.pr_body.clone(),
+        });
+        let (mut rules, mut roots) = self.into_parts()?;
+        rules.planned = plan.then(Default::default);
+        rules.patches = (emit == Some(Emit::Patch)).then(Default::default);
+        interrupt::install();
+
+        // Plans don't modify the manifests. The roots are locked in a fixed order, so two runs
+        // with the same roots can't wait on each other.
+        let mut lock_dirs = roots.iter().map(|r| &r.path).collect::<Vec<_>>();
+        lock_dirs.sort();
+        lock_dirs.dedup();
+        let _locks = lock_dirs
+            .into_iter()
+            .filter(|_| !plan)
+            .map(|d| run_lock::acquire(d))
+            .collect::<Result<Vec<_>>>()?;
+
+        if rules.crates_io_deps {
+            for root in &roots {
+                if let Some(source) = root.replacements.crates_io() {
+                    log::warn!(
+                        "`crates.io` is replaced with {} for {}, the upgraded versions need to \
+                         be available there.",
+                        source,
+                        root.path.display()
+                    );
+                }
+            }
+        }
+
+        resolve_short_revs(&mut rules.targets)?;
+
+        if check_compat && compat::check(&rules.targets)? > 0 {
+            log::warn!("The selected versions are probably not compatible.");
+        }
+
+        if let Some(pull_request) = &pull_request {
+            ensure!(
+                roots.len() == 1,
+                "`--open-pr` can only be used with a single `--path`."
+            );
+            ensure!(
+                http::has_github_token(),
+                "`--open-pr` requires a GitHub token, set `GITHUB_TOKEN` or pass `--github-token`."
+            );
+            pull_request.repository(&roots[0].path)?;
+        }
+
+        if !force && !plan {
+            let mut dirty = Vec::new();
+            for root in &roots {
+                dirty.extend(
+                    git::dirty_manifests(&root.path)?
+                        .into_iter()
+                        .map(|m| m.display().to_string()),
+                );
+            }
+            ensure!(
+                dirty.is_empty(),
+                "The following manifests have uncommitted changes:\n  {}\n\
+                 Commit or stash them first, or pass `--force` to run anyway.",
+                dirty.join("\n  ")
+            );
+        }
+
+        if rules
+            .targets
+            .iter()
+            .any(|(_, v)| matches!(v, Version::Freeze))
+        {
+            for root in &mut roots {
+                let lock_file = LockFile::find(&root.path).ok_or_else(|| {
+                    anyhow!(
+                        "Could not find a `Cargo.lock` for '{}' that is required by `--freeze`.",
+                        root.path.display()
+                    )
+                })?;
+                log::info!("Using lock file: {}", lock_file.display());
+                root.lock_file = Some(LockFile::read(&lock_file)?);
+            }
+        }
+
+        let registry_lookups = rules.crates_io_deps
+            || rules.targets.iter().any(|(_, v)| {
+                matches!(
+                    v,
+                    Version::Registry(RegistryVersion::Latest | RegistryVersion::LatestMajor(_))
+                )
+            });
+        if registry_lookups && watch.is_none() {
+            let switched = prefetch_registry_versions(&roots, &rules)?;
+            check_release_consistency(&switched, &rules);
+        }
+
+        if let Some(interval) = watch {
+            return watch_manifests(&roots, &rules, interval, deadline, audit_log.as_deref())
+                .map(|_| Vec::new());
+        }
+
+        let mut workspaces = Vec::new();
+        for root in &roots {
+            workspaces.extend(workspaces::find(manifests(&root.path, &root.skip_paths))?);
+        }
+
+        let all_manifests = roots
+            .iter()
+            .flat_map(|r| manifests(&r.path, &r.skip_paths))
+            .collect::<Vec<_>>();
+        for drift in features::find_drift(&all_manifests, &workspaces, |k| {
+            dependency_kind(k, rules.dev_only)
+        })? {
+            drift.report();
+            if normalize_features {
+                let unified = drift.unified();
+                for manifest in drift.declarations.values().flatten() {
+                    rules
+                        .unified_features
+                        .entry(manifest.clone())
+                        .or_default()
+                        .insert(drift.package.clone(), unified.clone());
+                }
+            }
+        }
+        // Workspace path -> (rewritten dependencies, modified files).
+        let mut summaries = BTreeMap::<PathBuf, (usize, usize)>::new();
 
-        let is_hidden = |entry: &DirEntry| {
-            entry
-                .file_name()
-                .to_str()
-                .map(|s| s.starts_with('.'))
-                .unwrap_or(false)
+        let mut rewritten = 0;
+        let mut modified = Vec::new();
+        let mut originals = Vec::new();
+        let partial_failure = |e: anyhow::Error, rewritten| {
+            if rewritten > 0 {
+                e.context(PartialFailure)
+            } else {
+                e
+            }
         };
+        let result = (|| -> Result<()> {
+            for root in &roots {
+                let before = (rewritten, modified.len());
+                // Process every workspace as a unit, nested ones after their parent.
+                let mut files = files(root, &rules).collect::<Vec<_>>();
+                files.sort_by_cached_key(|f| {
+                    workspaces::containing(&workspaces, f).map(|w| w.path.clone())
+                });
+
+                for file in files {
+                    interrupt::check(deadline)?;
+
+                    // In plan mode nothing is written, so there is nothing to restore.
+                    let original = if revert_on_interrupt && !plan {
+                        Some(fs::read_to_string(&file)?)
+                    } else {
+                        None
+                    };
+                    let (changes, changed) = handle_file(&file, root, &rules)
+                        .map_err(|e| partial_failure(e, rewritten))?;
+                    rewritten += changes.len();
+
+                    if let Some(workspace) = workspaces::containing(&workspaces, &file) {
+                        let summary = summaries.entry(workspace.path.clone()).or_default();
+                        summary.0 += changes.len();
+                        summary.1 += usize::from(changed);
+                    }
+
+                    if changed {
+                        originals.extend(original.map(|o| (file.clone(), o)));
+                        modified.push(audit::File {
+                            path: file,
+                            changes,
+                        });
+                    }
+                }
+
+                if root.submodule {
+                    log::info!(
+                        "Submodule {}: rewrote {} dependencies in {} files.",
+                        root.path.display(),
+                        rewritten - before.0,
+                        modified.len() - before.1
+                    );
+                }
+            }
+
+            if let Some(hook) = rules
+                .hook
+                .as_ref()
+                .filter(|h| h.once && !modified.is_empty() && !plan)
+            {
+                let files = modified.iter().map(|f| f.path.clone()).collect::<Vec<_>>();
+                hook.run(&files)
+                    .map_err(|e| partial_failure(e, rewritten))?;
+            }
+
+            for workspace in &workspaces {
+                let (dependencies, files) =
+                    summaries.get(&workspace.path).copied().unwrap_or_default();
+                if workspaces.len() > 1 {
+                    log::info!(
+                        "Workspace {}: rewrote {} dependencies in {} files.",
+                        workspace.describe(),
+                        dependencies,
+                        files
+                    );
+                }
+                if update_lockfiles && files > 0 && !plan {
+                    workspace
+                        .update_lockfile()
+                        .map_err(|e| partial_failure(e, rewritten))?;
+                }
+            }
+
+            Ok(())
+        })();
+
+        if result.as_ref().is_err_and(|e| e.is::<Interrupted>()) {
+            if plan {
+                output!("Stopped before the plan was written.");
+            } else {
+                report_interrupt(&modified, &originals)?;
+            }
+            if revert_on_interrupt {
+                modified.clear();
+            }
+        }
+
+        if let Some(audit_log) = audit_log.filter(|_| !plan) {
+            audit::append(&audit_log, &modified, result.as_ref().err())?;
+        }
+        result?;
+
+        log::info!("Rewrote {} dependencies.", rewritten);
+        if report == Some(report::Format::Markdown) {
+            let roots = roots.iter().map(|r| r.path.clone()).collect::<Vec<_>>();
+            let markdown = report::markdown(&modified, &roots);
+            match &report_file {
+                Some(file) => fs::write(file, markdown)
+                    .with_context(|| anyhow!("Failed to write the report to {}", file.display()))?,
+                None => print!("{}", markdown),
+            }
+        }
+        if rewritten == 0 && fail_unchanged {
+            return Err(NothingMatched.into());
+        }
+
+        if let Some(pull_request) = pull_request {
+            if modified.is_empty() {
+                log::info!("Nothing changed, not opening a pull request.");
+            } else {
+                let url = pull_request
+                    .open(&roots[0].path, &modified, rewritten)
+                    .map_err(|e| partial_failure(e, rewritten))?;
+                output!("Opened pull request: {}", url);
+            }
+        }
+
+        if let Some(patches) = rules.patches {
+            print!("{}", patch_tables(&patches.into_inner()));
+            return Ok(Vec::new());
+        }
 
-        WalkDir::new(path)
-            .follow_links(true)
+        let mut planned = rules.planned.map(RefCell::into_inner).unwrap_or_default();
+        Ok(modified
             .into_iter()
-            .filter_entry(|e| !is_hidden(e))
-            .filter_map(|e| e.ok())
-            .filter(|e| {
-                e.file_type().is_file() && e.file_name().to_string_lossy().ends_with("Cargo.toml")
+            .filter_map(|file| {
+                let (old, new) = planned.remove(&file.path)?;
+                Some(plan::File::new(file.path, &old, new, file.changes))
             })
-            .try_for_each(|toml| handle_toml_file(toml.into_path(), &rewrite, &version))
+            .collect())
     }
 }
 
-/// Handle a given dependency.
-///
-/// This directly modifies the given `dep` in the requested way.
-fn handle_dependency(name: &str, dep: &mut InlineTable, rewrite: &Rewrite, version: &Version) {
-    let git = if let Some(git) = dep
-        .get("git")
-        .and_then(|v| v.as_str())
-        .and_then(|d| GitUrl::parse(d).ok())
-    {
-        git
-    } else {
-        return;
-    };
+/// Returns the `[patch."URL"]` tables for the recorded `patches`.
+fn patch_tables(patches: &Patches) -> String {
+    let mut patch = Table::new();
+    patch.set_implicit(true);
+    for (url, crates) in patches {
+        let mut table = Table::new();
+        for (package, source) in crates {
+            let source = source
+                .iter()
+                .map(|(key, value)| (*key, Value::from(value.as_str())))
+                .collect::<toml_edit::InlineTable>();
+            table.insert(package, toml_edit::value(source));
+        }
+        patch.insert(url, Item::Table(table));
+    }
 
-    let new_git = match rewrite {
-        Rewrite::All => &None,
-        Rewrite::Substrate(new_git) if git.name == "substrate" => new_git,
-        Rewrite::Polkadot(new_git) if git.name == "polkadot" => new_git,
-        Rewrite::Cumulus(new_git) if git.name == "cumulus" => new_git,
-        Rewrite::Beefy(new_git) if git.name == "grandpa-bridge-gadget" => new_git,
-        _ => return,
-    };
+    let mut doc = Document::new();
+    doc.insert("patch", Item::Table(patch));
+    doc.to_string()
+}
 
-    if let Some(new_git) = new_git {
-        *dep.get_or_insert("git", "") = Value::from(new_git.as_str()).decorated(" ", "");
+/// Print the files that were modified before the run was interrupted.
+///
+/// The `originals` are the contents of the modified files before the run, these are restored
+/// for `--revert-on-interrupt`.
+fn report_interrupt(modified: &[audit::File], originals: &[(PathBuf, String)]) -> Result<()> {
+    if modified.is_empty() {
+        output!("Stopped before any file was modified.");
+        return Ok(());
     }
 
-    dep.remove("tag");
-    dep.remove("branch");
-    dep.remove("rev");
+    if originals.is_empty() {
+        output!("Stopped, the following files were already modified:");
+        modified
+            .iter()
+            .for_each(|f| output!("  {}", f.path.display()));
+        return Ok(());
+    }
 
-    match version {
-        Version::Tag(tag) => {
-            *dep.get_or_insert("tag", "") = Value::from(tag.as_str()).decorated(" ", " ");
-        }
-        Version::Branch(branch) => {
-            *dep.get_or_insert("branch", "") = Value::from(branch.as_str()).decorated(" ", " ");
-        }
-        Version::Rev(rev) => {
-            *dep.get_or_insert("rev", "") = Value::from(rev.as_str()).decorated(" ", " ");
-        }
+    for (path, original) in originals {
+        atomic_write::write(path, original)
+            .with_context(|| anyhow!("Failed to restore {}", path.display()))?;
+        output!("Restored {}", path.display());
     }
-    log::debug!("  updated: {:?} <= {}", version, name);
+    output!("Stopped, all modified files were restored.");
+    Ok(())
 }
 
-/// Handle a given `Cargo.toml`.
+/// Apply the `rules` to all manifests in the `roots` and then re-apply them to every manifest
+/// that is added or changed afterwards.
 ///
-/// This means scanning all dependencies and rewrite the requested onces.
-fn handle_toml_file(path: PathBuf, rewrite: &Rewrite, version: &Version) -> Result<()> {
-    log::info!("Processing: {}", path.display());
+/// This runs until it is interrupted with Ctrl-C or the `deadline` given with `--timeout` has
+/// passed.
+fn watch_manifests(
+    roots: &[Root],
+    rules: &Rules,
+    interval: Duration,
+    deadline: Option<Instant>,
+    audit_log: Option<&Path>,
+) -> Result<()> {
+    let mtime = |manifest: &Path| fs::metadata(manifest).and_then(|m| m.modified()).ok();
+    let mut known = HashMap::<PathBuf, Option<SystemTime>>::new();
 
-    let mut toml_doc = Document::from_str(&fs::read_to_string(&path)?)?;
+    for root in roots {
+        log::info!("Watching {} for changes.", root.path.display());
+    }
+    loop {
+        let mut processed = Vec::new();
+        let mut modified = Vec::new();
+        let mut audited = Vec::new();
 
-    // Iterate over all tables in the document
-    toml_doc
-        .clone()
-        .iter()
-        // filter out everything that is not a dependency table
-        .filter(|(k, _)| k.contains("dependencies"))
-        .filter_map(|(k, v)| v.as_table().map(|t| (k, t)))
-        .for_each(|(k, t)| {
-            t.iter()
-                // Filter everything that is not an inline table (`{ foo = bar }`)
-                .filter_map(|v| v.1.as_inline_table().map(|_| v.0))
-                .for_each(|dn| {
-                    // Get the actual inline table from the document that we modify
-                    let table = toml_doc[k][dn]
-                        .as_inline_table_mut()
-                        .expect("We filter by `is_inline_table`; qed");
-                    handle_dependency(dn, table, rewrite, version);
-                })
-        });
+        for (root, manifest) in roots
+            .iter()
+            .flat_map(|root| files(root, rules).map(move |m| (root, m)))
+        {
+            if interrupt::check(deadline).is_err() {
+                log::info!("Stopped watching.");
+                return Ok(());
+            }
 
-    fs::write(&path, toml_doc.to_string())?;
-    Ok(())
+            let current = mtime(&manifest);
+            if known.get(&manifest) == Some(&current) {
+                continue;
+            }
+
+            match handle_file(&manifest, root, rules) {
+                Ok((changes, true)) => {
+                    modified.push(manifest.clone());
+                    audited.push(audit::File {
+                        path: manifest.clone(),
+                        changes,
+                    });
+                }
+                Ok(_) => {}
+                Err(e) => log::error!("Failed to process {}: {:?}", manifest.display(), e),
+            }
+            processed.push(manifest);
+        }
+
+        if let Some(hook) = rules
+            .hook
+            .as_ref()
+            .filter(|h| h.once && !modified.is_empty())
+        {
+            if let Err(e) = hook.run(&modified) {
+                log::error!("{:?}", e);
+            }
+        }
+
+        if let Some(audit_log) = audit_log.filter(|_| !audited.is_empty()) {
+            if let Err(e) = audit::append(audit_log, &audited, None) {
+                log::error!("{:?}", e);
+            }
+        }
+
+        // Record the time after our own writes and the hook, to not process the files again.
+        for manifest in processed {
+            let current = mtime(&manifest);
+            known.insert(manifest, current);
+        }
+
+        let remaining = deadline.map_or(interval, |d| d.saturating_duration_since(Instant::now()));
+        thread::sleep(interval.min(remaining));
+        if interrupt::check(deadline).is_err() {
+            log::info!("Stopped watching.");
+            return Ok(());
+        }
+    }
+}
+
+/// Build the glob set for the given `--skip-path` globs and `excluded` directories.
+fn skip_paths(globs: &[String], excluded: &[String]) -> Result<GlobSet> {
+    let mut set = GlobSetBuilder::new();
+    for glob in globs {
+        set.add(Glob::new(glob).with_context(|| anyhow!("Invalid `--skip-path` `{}`", glob))?);
+    }
+    for dir in excluded {
+        let dir = globset::escape(dir.trim_start_matches("./").trim_end_matches('/'));
+        set.add(Glob::new(&dir).with_context(|| anyhow!("Invalid `workspace.exclude` `{}`", dir))?);
+    }
+    set.build().context("Failed to build `--skip-path` globs")
+}
+
+/// Returns the `workspace.exclude` entries of the `Cargo.toml` in the given directory.
+fn workspace_excludes(dir: &Path) -> Result<Vec<String>> {
+    let manifest = dir.join("Cargo.toml");
+    if !manifest.is_file() {
+        return Ok(Vec::new());
+    }
+
+    let doc = Document::from_str(&fs::read_to_string(&manifest)?)
+        .with_context(|| anyhow!("Failed to parse {}", manifest.display()))?;
+    let excluded = doc
+        .get("workspace")
+        .and_then(|w| w.get("exclude"))
+        .and_then(|e| e.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.as_str())
+        .map(String::from)
+        .collect::<Vec<_>>();
+
+    if !excluded.is_empty() {
+        log::info!(
+            "Skipping excluded workspace members: {}",
+            excluded.join(", ")
+        );
+    }
+    Ok(excluded)
+}
+
+/// Returns the target for the given companion pull request, e.g. `paritytech/polkadot#1234`.
+fn companion_target(companion: &str) -> Result<(Rewrite, Version)> {
+    #[derive(Deserialize)]
+    struct PullRequest {
+        head: Head,
+    }
+
+    #[derive(Deserialize)]
+    struct Head {
+        #[serde(rename = "ref")]
+        branch: String,
+        repo: Option<HeadRepo>,
+    }
+
+    #[derive(Deserialize)]
+    struct HeadRepo {
+        html_url: String,
+    }
+
+    let (repository, number) = companion
+        .split_once('#')
+        .filter(|(r, n)| r.contains('/') && n.parse::<u64>().is_ok())
+        .ok_or_else(|| {
+            anyhow!(
+                "Invalid `--companion` `{}`, expected `OWNER/REPO#NUMBER`.",
+                companion
+            )
+        })?;
+    let repo = repository
+        .rsplit('/')
+        .next()
+        .and_then(Repo::from_name)
+        .ok_or_else(|| anyhow!("`{}` is not one of the known repositories.", repository))?;
+
+    let pr = http::github_api::<PullRequest>(&format!("repos/{}/pulls/{}", repository, number))?
+        .ok_or_else(|| anyhow!("Pull request `{}` not found.", companion))?;
+    let fork = pr.head.repo.ok_or_else(|| {
+        anyhow!(
+            "The repository of pull request `{}` was deleted.",
+            companion
+        )
+    })?;
+
+    log::info!(
+        "Using branch `{}` of {} for companion {}",
+        pr.head.branch,
+        fork.html_url,
+        companion
+    );
+
+    Ok((
+        repo.rewrite(Some(fork.html_url)),
+        Version::Branch(pr.head.branch),
+    ))
+}
+
+/// The banner cargo puts at the top of the manifests it normalizes for publishing.
+const GENERATED_BANNER: &str = "# THIS FILE IS AUTOMATICALLY GENERATED BY CARGO";
+
+/// Returns the dependency tables of the manifest `doc` whose name is accepted by `kind`.
+///
+/// Besides the top level tables like `[dependencies]`, this includes the platform specific
+/// tables like `[target.'cfg(unix)'.dependencies]`.
+pub(crate) fn dependency_tables(doc: &Document, kind: impl Fn(&str) -> bool) -> Vec<&Table> {
+    let mut tables = Vec::new();
+    for (key, item) in doc.iter() {
+        if key == "target" {
+            let targets = item
+                .as_table()
+                .into_iter()
+                .flat_map(|t| t.iter())
+                .filter_map(|(_, t)| t.as_table());
+            for target in targets {
+                tables.extend(
+                    target
+                        .iter()
+                        .filter(|(k, _)| kind(k))
+                        .filter_map(|(_, t)| t.as_table()),
+                );
+            }
+        } else if kind(key) {
+            tables.extend(item.as_table());
+        }
+    }
+    tables
+}
+
+/// Like [`dependency_tables`], but returns the tables mutably.
+pub(crate) fn dependency_tables_mut(
+    doc: &mut Document,
+    kind: impl Fn(&str) -> bool,
+) -> Vec<&mut Table> {
+    let mut tables = Vec::new();
+    for (key, item) in doc.iter_mut() {
+        if key == "target" {
+            let targets = item
+                .as_table_mut()
+                .into_iter()
+                .flat_map(|t| t.iter_mut())
+                .filter_map(|(_, t)| t.as_table_mut());
+            for target in targets {
+                tables.extend(
+                    target
+                        .iter_mut()
+                        .filter(|(k, _)| kind(k.get()))
+                        .filter_map(|(_, t)| t.as_table_mut()),
+                );
+            }
+        } else if kind(key.get()) {
+            tables.extend(item.as_table_mut());
+        }
+    }
+    tables
+}
+
+/// Returns all `Cargo.toml` files in the given directory tree.
+///
+/// Hidden directories and everything matching `skip_paths` (relative to `path`) are skipped.
+/// Vendored crates and manifests generated by cargo are skipped as well, as changing them
+/// breaks the checksums cargo verifies for them.
+pub(crate) fn manifests<'a>(
+    path: &'a Path,
+    skip_paths: &'a GlobSet,
+) -> impl Iterator<Item = PathBuf> + 'a {
+    let is_hidden = |entry: &DirEntry| {
+        entry
+            .file_name()
+            .to_str()
+            .map(|s| s.starts_with('.'))
+            .unwrap_or(false)
+    };
+
+    WalkDir::new(path)
+        .follow_links(true)
+        .into_iter()
+        .filter_entry(move |e| {
+            let relative = e.path().strip_prefix(path).unwrap_or(e.path());
+            !is_hidden(e) && !skip_paths.is_match(relative) && !is_vendored(e)
+        })
+        .filter_map(|e| e.ok())
+        .filter(|e| {
+            e.file_type().is_file() && e.file_name().to_string_lossy().ends_with("Cargo.toml")
+        })
+        .map(|e| e.into_path())
+        .filter(|p| !is_generated(p))
+}
+
+/// Returns `true` if the given entry is the directory of a vendored crate.
+///
+/// `cargo vendor` puts a `.cargo-checksum.json` into the directory of every crate.
+fn is_vendored(entry: &DirEntry) -> bool {
+    let vendored =
+        entry.file_type().is_dir() && entry.path().join(".cargo-checksum.json").is_file();
+    if vendored {
+        log::debug!("Skipping vendored crate: {}", entry.path().display());
+    }
+    vendored
+}
+
+/// Returns `true` if the manifest at `path` was generated by cargo.
+///
+/// Only the beginning of the file is read, as the banner is the first thing in it.
+fn is_generated(path: &Path) -> bool {
+    let mut start = [0; 256];
+    let read = fs::File::open(path)
+        .and_then(|mut f| f.read(&mut start))
+        .unwrap_or(0);
+    let generated = String::from_utf8_lossy(&start[..read]).contains(GENERATED_BANNER);
+    if generated {
+        log::debug!("Skipping generated manifest: {}", path.display());
+    }
+    generated
+}
+
+/// Returns all files in `root` that should be processed with the given `rules`.
+fn files<'a>(root: &'a Root, rules: &'a Rules) -> impl Iterator<Item = PathBuf> + 'a {
+    let cargo_configs = rules
+        .cargo_config
+        .then(|| cargo_configs(&root.path, &root.skip_paths))
+        .into_iter()
+        .flatten();
+    manifests(&root.path, &root.skip_paths).chain(cargo_configs)
+}
+
+/// Returns all cargo config files (`.cargo/config.toml` or `.cargo/config`) in the given
+/// directory tree.
+///
+/// Everything matching `skip_paths` (relative to `path`) is skipped.
+fn cargo_configs<'a>(
+    path: &'a Path,
+    skip_paths: &'a GlobSet,
+) -> impl Iterator<Item = PathBuf> + 'a {
+    WalkDir::new(path)
+        .follow_links(true)
+        .into_iter()
+        .filter_entry(move |e| {
+            let relative = e.path().strip_prefix(path).unwrap_or(e.path());
+            let name = e.file_name().to_string_lossy();
+            (name == ".cargo" || !name.starts_with('.') || e.depth() == 0)
+                && !skip_paths.is_match(relative)
+        })
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file() && is_cargo_config(e.path()))
+        .map(|e| e.into_path())
+}
+
+/// Returns `true` if the given `path` points to a cargo config file.
+fn is_cargo_config(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|n| n == "config.toml" || n == "config")
+        && path
+            .parent()
+            .and_then(|p| p.file_name())
+            .is_some_and(|p| p == ".cargo")
+}
+
+/// Handle a given dependency.
+///
+/// This directly modifies the given `dep` in the requested way. Returns the change, if the
+/// dependency was rewritten.
+///
+/// `root` provides the `Cargo.lock` used by `--freeze` and the cargo source replacements.
+///
+/// `dep` is either an inline table (`foo = { git = "..." }`), a dotted-key table
+/// (`foo.git = "..."`) or a standard table (`[source.foo]`).
+fn handle_dependency(name: &str, dep: &mut Item, root: &Root, rules: &Rules) -> Option<Change> {
+    let style = rules.formatting.style(dep);
+    let dep = dep.as_table_like_mut()?;
+
+    // The url may carry an embedded reference (`?branch=master`), which is moved to its
+    // own key when rewriting.
+    let (url, embedded) = match dep.get("git").and_then(|v| v.as_str()) {
+        Some(git) => {
+            let (url, embedded) = split_reference(git);
+            (url.to_string(), embedded)
+        }
+        None => return None,
+    };
+    let git = rules.parse_git_url(&url)?;
+
+    let old_reference = ["branch", "tag", "rev"]
+        .into_iter()
+        .find_map(|k| {
+            dep.get(k)
+                .and_then(|v| v.as_str())
+                .map(|v| (k, v.to_string()))
+        })
+        .or_else(|| embedded.clone());
+    let old_source =
+        report::git_source(&url, old_reference.as_ref().map(|(k, v)| (*k, v.as_str())));
+
+    // With source replacement, the dependency may only be recognized by the source cargo
+    // actually uses.
+    let replacement = root.replacements.git(&git, old_reference.as_ref());
+    let replaced_git = match replacement {
+        Some(source_replacement::Source::Git(url, _)) => GitUrl::parse(url).ok(),
+        _ => None,
+    };
+    let package = dep.get("package").and_then(|p| p.as_str()).unwrap_or(name);
+    let (new_git, version) = find_target(&git, replaced_git.as_ref(), package, rules)?;
+
+    if let Version::Registry(registry) = version {
+        let package = package.to_string();
+        let change = switch_to_registry(
+            name, &package, &git, dep, &style, old_source, registry, rules,
+        );
+        if let Some(change) = &change {
+            rules.record_patch(&url, &package, vec![("version", change.new.clone())]);
+        }
+        return change;
+    }
+
+    let new_git = new_git.as_ref().map(|g| expand_git_template(g, &git));
+    let new_git = match (&new_git, rules.url_scheme) {
+        (Some(new_git), Some(scheme)) => match GitUrl::parse(new_git) {
+            Ok(g) => Some(
+                with_scheme(&g, scheme)
+                    .map_err(|e| log::error!("  `{}`: {}", name, e))
+                    .ok()?,
+            ),
+            Err(_) => Some(new_git.clone()),
+        },
+        (None, Some(scheme)) => Some(
+            with_scheme(&git, scheme)
+                .map_err(|e| log::error!("  `{}`: {}", name, e))
+                .ok()?,
+        ),
+        (Some(new_git), None) => Some(new_git.clone()),
+        // Drop the embedded reference, it is replaced by the new one.
+        (None, None) => embedded.is_some().then(|| url.clone()),
+    };
+
+    let resolved;
+    let (key, value) = match version {
+        Version::Tag(tag) => ("tag", tag),
+        Version::Branch(branch) => ("branch", branch),
+        Version::Rev(rev) => ("rev", rev),
+        Version::Freeze => {
+            let branch = dep
+                .get("branch")
+                .and_then(|b| b.as_str())
+                .map(String::from)
+                .or_else(|| {
+                    embedded
+                        .clone()
+                        .filter(|(k, _)| *k == "branch")
+                        .map(|(_, b)| b)
+                })?;
+            resolved = match root
+                .lock_file
+                .as_ref()
+                .and_then(|l| locked_rev(l, package, &git, &branch))
+            {
+                Some(rev) => rev,
+                None => {
+                    log::warn!(
+                        "  `{}` with branch `{}` not found in `Cargo.lock`, not freezing it.",
+                        name,
+                        branch
+                    );
+                    return None;
+                }
+            };
+            ("rev", &resolved)
+        }
+        Version::RevFrom => {
+            resolved = match rules
+                .rev_from
+                .as_ref()
+                .and_then(|l| pinned_rev(l, package, &git, &rules.matcher))
+            {
+                Some(rev) => rev,
+                None => {
+                    log::warn!(
+                        "  `{}` not found in the `Cargo.lock` given to `--rev-from`, not changing it.",
+                        name
+                    );
+                    return None;
+                }
+            };
+            ("rev", &resolved)
+        }
+        // Handled above, as the dependency isn't a git dependency afterwards.
+        Version::Registry(_) => return None,
+        Version::DefaultBranch => {
+            let branch = match &old_reference {
+                Some(("branch", branch)) => branch,
+                _ => return None,
+            };
+            resolved = default_branch(name, new_git.as_deref().unwrap_or(&url), branch, rules)?;
+            ("branch", &resolved)
+        }
+    };
+
+    let new_source = report::git_source(new_git.as_deref().unwrap_or(&url), Some((key, value)));
+    rules.record_patch(
+        &url,
+        package,
+        vec![
+            ("git", new_git.clone().unwrap_or_else(|| url.clone())),
+            (key, value.clone()),
+        ],
+    );
+
+    // With `--cargo-config` the replaced sources are rewritten as well.
+    if let Some(replacement) = replacement.filter(|_| !rules.cargo_config) {
+        let new_reference = (key, value.clone());
+        let replaced = GitUrl::parse(new_git.as_deref().unwrap_or(&url))
+            .is_ok_and(|new| root.replacements.git(&new, Some(&new_reference)).is_some());
+        if !replaced {
+            log::warn!(
+                "  `{}`: cargo replaces {} with {}, but {} isn't replaced. Update the `[source]` \
+                 section of the cargo config or pass `--cargo-config`.",
+                name,
+                old_source,
+                replacement,
+                new_source
+            );
+        }
+    }
+
+    // Copied, as `package` borrows the dependency.
+    let package = package.to_string();
+    let new_url = new_git.as_deref().unwrap_or(&url);
+    match rules.version_key {
+        _ if !dep.contains_key("version") => {}
+        VersionKey::Keep => {}
+        VersionKey::Drop => {
+            log::debug!("  dropped the version of {}", name);
+            dep.remove("version");
+        }
+        VersionKey::Sync => {
+            let version = GitUrl::parse(new_url)
+                .map_err(|e| anyhow!("Invalid git url {}: {}", new_url, e))
+                .and_then(|git| tagged_version(&git, value, &package, rules))
+                .and_then(|v| {
+                    v.ok_or_else(|| {
+                        anyhow!("{} has no crate `{}` at `{}`.", new_url, package, value)
+                    })
+                });
+            match version {
+                Ok(version) => {
+                    log::debug!("  synced the version of {} to {}", name, version);
+                    style.insert(dep, "version", version.as_str());
+                }
+                Err(e) => log::warn!("  Not syncing the version of `{}`: {:#}", name, e),
+            }
+        }
+    }
+
+    if let Some(new_git) = new_git {
+        style.insert(dep, "git", new_git.as_str());
+    }
+
+    // The reference that is kept is rewritten in place.
+    ["tag", "branch", "rev"]
+        .iter()
+        .filter(|k| **k != key)
+        .for_each(|k| {
+            dep.remove(k);
+        });
+
+    style.insert(dep, key, value.as_str());
+    log::debug!("  updated: {:?} <= {}", version, name);
+    Some(Change {
+        name: name.into(),
+        old: old_source,
+        new: new_source,
+    })
+}
+
+/// A git dependency that is switched to the latest version on `crates.io` by `--version`.
+struct Switched<'a> {
+    /// The url of the repository the dependency currently uses.
+    repository: String,
+    package: String,
+    version: &'a RegistryVersion,
+}
+
+/// Scan the manifests for the crates whose versions are looked up on `crates.io` and fetch their
+/// versions with up to `--jobs` requests at the same time.
+///
+/// Returns the git dependencies that are switched to the latest version on `crates.io`.
+fn prefetch_registry_versions<'a>(roots: &[Root], rules: &'a Rules) -> Result<Vec<Switched<'a>>> {
+    let manifests = roots
+        .iter()
+        .flat_map(|root| manifests(&root.path, &root.skip_paths))
+        .collect::<Vec<_>>();
+    let docs = jobs::map(&manifests, |path| -> Result<Option<Document>> {
+        let content = fs::read_to_string(path)?;
+        if !content.contains("dependencies") {
+            return Ok(None);
+        }
+        Document::from_str(&content)
+            .map(Some)
+            .with_context(|| anyhow!("Failed to parse {}", path.display()))
+    });
+
+    let mut switched = Vec::new();
+    let mut packages = BTreeSet::new();
+    for doc in docs {
+        let doc = match doc? {
+            Some(doc) => doc,
+            None => continue,
+        };
+
+        for deps in dependency_tables(&doc, |k| k.contains("dependencies")) {
+            for (name, dep) in deps.iter() {
+                if let Some(version) = dep.as_str() {
+                    if rules.crates_io_deps && !version.is_empty() {
+                        packages.insert(name.to_string());
+                    }
+                    continue;
+                }
+                let dep = match dep.as_table_like() {
+                    Some(dep) => dep,
+                    None => continue,
+                };
+                let package = dep.get("package").and_then(|p| p.as_str()).unwrap_or(name);
+
+                let url = match dep.get("git").and_then(|v| v.as_str()) {
+                    Some(git) => split_reference(git).0,
+                    None => {
+                        let is_registry = ["path", "registry", "workspace"]
+                            .iter()
+                            .all(|k| !dep.contains_key(k));
+                        if rules.crates_io_deps && is_registry && dep.contains_key("version") {
+                            packages.insert(package.to_string());
+                        }
+                        continue;
+                    }
+                };
+                let version = match rules
+                    .parse_git_url(url)
+                    .and_then(|git| find_target(&git, None, package, rules))
+                {
+                    Some((_, Version::Registry(version)))
+                        if matches!(
+                            version,
+                            RegistryVersion::Latest | RegistryVersion::LatestMajor(_)
+                        ) =>
+                    {
+                        version
+                    }
+                    _ => continue,
+                };
+
+                packages.insert(package.to_string());
+                switched.push(Switched {
+                    repository: url.trim_end_matches('/').trim_end_matches(".git").into(),
+                    package: package.into(),
+                    version,
+                });
+            }
+        }
+    }
+
+    let packages = packages
+        .into_iter()
+        .filter(|p| !rules.crate_versions.borrow().contains_key(p))
+        .collect::<Vec<_>>();
+    let versions = jobs::map(&packages, |p| crates_io::get_versions(p));
+    // Failures are reported when the dependency is rewritten, which fetches it again.
+    rules.prefetched_versions.borrow_mut().extend(
+        packages
+            .into_iter()
+            .zip(versions)
+            .filter_map(|(p, v)| Some((p, v.ok()?))),
+    );
+
+    Ok(switched)
+}
+
+/// Fetch the versions of `package` from `crates.io`, unless they were already prefetched.
+fn fetch_versions(package: &str, rules: &Rules) -> Result<Option<Vec<semver::Version>>> {
+    match rules.prefetched_versions.borrow_mut().remove(package) {
+        Some(versions) => Ok(versions),
+        None => crates_io::get_versions(package),
+    }
+}
+
+/// Warn if the crates of one repository would end up on different versions on `crates.io`.
+///
+/// Crates of one release train that are on different versions don't build together, which
+/// happens when the latest versions are looked up while a release is published.
+fn check_release_consistency(switched: &[Switched], rules: &Rules) {
+    // Repository -> version -> crates.
+    let mut resolved = BTreeMap::<&str, BTreeMap<String, BTreeSet<&str>>>::new();
+    for dep in switched {
+        // Crates that can not be resolved are reported when they are rewritten.
+        let selected = registry_versions(&dep.package, rules)
+            .ok()
+            .flatten()
+            .and_then(|versions| dep.version.select(&versions));
+        if let Some(selected) = selected {
+            resolved
+                .entry(&dep.repository)
+                .or_default()
+                .entry(selected)
+                .or_default()
+                .insert(&dep.package);
+        }
+    }
+
+    for (repository, versions) in resolved.iter().filter(|(_, v)| v.len() > 1) {
+        log::warn!(
+            "The crates of {} would end up on different versions:\n  {}\n\
+             Pass `--consistent-release` to take the versions of all crates from one release.",
+            repository,
+            versions
+                .iter()
+                .map(|(version, crates)| format!(
+                    "{}: {}",
+                    version,
+                    crates.iter().copied().collect::<Vec<_>>().join(", ")
+                ))
+                .collect::<Vec<_>>()
+                .join("\n  ")
+        );
+    }
+}
+
+/// Returns the new `git` url and version for a dependency on `package` from the repository at
+/// `git`, or at `replaced_git` if cargo replaces the source.
+///
+/// Returns `None` if the dependency isn't rewritten.
+fn find_target<'a>(
+    git: &GitUrl,
+    replaced_git: Option<&GitUrl>,
+    package: &str,
+    rules: &'a Rules,
+) -> Option<(&'a Option<String>, &'a Version)> {
+    let (new_git, version) = rules.targets.iter().find_map(|(r, v)| {
+        r.matches(git, &rules.matcher)
+            .or_else(|| replaced_git.and_then(|g| r.matches(g, &rules.matcher)))
+            .map(|g| (g, v))
+    })?;
+
+    match rules.overrides.get(package) {
+        Some(o) if o.git.is_some() => Some((&o.git, &o.version)),
+        Some(o) => Some((new_git, &o.version)),
+        None => Some((new_git, version)),
+    }
+}
+
+/// Switch the git dependency `name` to the given `version` of its `package` on `crates.io`.
+///
+/// Returns the change, if the dependency was switched.
+#[allow(clippy::too_many_arguments)]
+fn switch_to_registry(
+    name: &str,
+    package: &str,
+    git: &GitUrl,
+    dep: &mut dyn TableLike,
+    style: &Style,
+    old_source: String,
+    version: &RegistryVersion,
+    rules: &Rules,
+) -> Option<Change> {
+    let new = match version {
+        RegistryVersion::Requirement(requirement) => Ok(requirement.clone()),
+        RegistryVersion::Locked => rules
+            .release_lock
+            .as_ref()
+            .ok_or_else(|| anyhow!("`--consistent-release` requires a `Cargo.lock`."))
+            .and_then(|lock_file| locked_version(lock_file, package)),
+        RegistryVersion::FromTag(tag) => tagged_version(git, tag, package, rules).and_then(|v| {
+            v.ok_or_else(|| anyhow!("{} has no crate `{}` at `{}`.", git, package, tag))
+        }),
+        _ => registry_versions(package, rules).and_then(|versions| {
+            let versions =
+                versions.ok_or_else(|| anyhow!("`{}` is not published on crates.io.", package))?;
+            version.select(&versions).ok_or_else(|| {
+                anyhow!(
+                    "`{}` has no release matching `--version {}`.",
+                    package,
+                    version
+                )
+            })
+        }),
+    };
+    let new = match new {
+        Ok(new) => new,
+        Err(e) => {
+            log::warn!("  {:#}", e);
+            return None;
+        }
+    };
+
+    for key in ["git", "tag", "branch", "rev", "version"] {
+        dep.remove(key);
+    }
+    style.insert(dep, "version", new.as_str());
+    log::debug!("  switched to crates.io: {} => {}", name, new);
+    Some(Change {
+        name: name.into(),
+        old: old_source,
+        new,
+    })
+}
+
+/// Returns all versions of `package` that are published on `crates.io`.
+///
+/// Returns `None` if the crate isn't published.
+fn registry_versions(package: &str, rules: &Rules) -> Result<Option<Vec<semver::Version>>> {
+    let mut crate_versions = rules.crate_versions.borrow_mut();
+    if let Some(versions) = crate_versions.get(package) {
+        return Ok(versions.clone());
+    }
+
+    let versions = fetch_versions(package, rules)?;
+    crate_versions.insert(package.into(), versions.clone());
+    Ok(versions)
+}
+
+/// Returns the version of `package` in the `Cargo.lock` of `--consistent-release`.
+///
+/// Only the registry packages and the members of the workspace of the `Cargo.lock` are taken
+/// into account.
+fn locked_version(lock_file: &LockFile, package: &str) -> Result<String> {
+    let versions = lock_file
+        .packages
+        .iter()
+        .filter(|p| p.name == package)
+        .filter(|p| {
+            p.source
+                .as_deref()
+                .is_none_or(|s| s.starts_with("registry+"))
+        })
+        .map(|p| p.version.as_str())
+        .collect::<BTreeSet<_>>();
+    match versions.len() {
+        0 => bail!("`{}` is not part of the release `Cargo.lock`.", package),
+        1 => Ok(versions.into_iter().next().unwrap_or_default().into()),
+        _ => bail!(
+            "`{}` has multiple versions in the release `Cargo.lock`: {}",
+            package,
+            versions.into_iter().collect::<Vec<_>>().join(", ")
+        ),
+    }
+}
+
+/// Returns the version of `package` in its manifest at `tag` of the repository `git`.
+///
+/// `tag` may be any git reference. Returns `None` if the repository has no such crate.
+fn tagged_version(git: &GitUrl, tag: &str, package: &str, rules: &Rules) -> Result<Option<String>> {
+    let mut tagged_manifests = rules.tagged_manifests.borrow_mut();
+    // The listing is only fetched once per repository and reference, also if it failed.
+    let key = format!("{}@{}", git, tag);
+    let manifests = tagged_manifests.entry(key).or_insert_with(|| {
+        TaggedManifests::fetch(git, tag)
+            .map_err(|e| log::warn!("{:#}", e))
+            .ok()
+    });
+    let manifests = match manifests {
+        Some(manifests) => manifests,
+        None => bail!("The manifests of {} at `{}` are not available.", git, tag),
+    };
+
+    manifests.version(package)
+}
+
+/// Checks the git url of the dependency `name` in the file at `path` for `--strict`.
+///
+/// Fails if the url can not be parsed or if the repository has the name of a known
+/// repository, but isn't matched by any of the accepted urls.
+fn check_git_url(path: &Path, name: &str, dep: &Item, matcher: &Matcher) -> Result<()> {
+    let git = match dep
+        .as_table_like()
+        .and_then(|d| d.get("git"))
+        .and_then(|g| g.as_str())
+    {
+        Some(git) => git,
+        None => return Ok(()),
+    };
+
+    let url = GitUrl::parse(split_reference(git).0).map_err(|e| {
+        anyhow!(
+            "{}: `{}` has an invalid git url `{}`: {}",
+            path.display(),
+            name,
+            git,
+            e
+        )
+    })?;
+
+    match Repo::from_name(&url.name) {
+        Some(repo) if matcher.classify(&url).is_none() => bail!(
+            "{}: `{}` uses `{}`, which is not accepted as {}. Pass `--expected-url` to accept it.",
+            path.display(),
+            name,
+            git,
+            repo.name()
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Replace the abbreviated commit hashes given with `--rev` by the full hashes.
+///
+/// Cargo handles abbreviated hashes differently across versions, the full hash makes the
+/// `Cargo.lock` deterministic. Fails if a hash is ambiguous or doesn't exist.
+fn resolve_short_revs(targets: &mut [(Rewrite, Version)]) -> Result<()> {
+    for (rewrite, version) in targets {
+        let rev = match version {
+            Version::Rev(rev) if git::is_short_rev(rev) => rev,
+            _ => continue,
+        };
+
+        let mut resolved = Vec::new();
+        let mut errors = Vec::new();
+        for (repo, git) in rewrite.repositories() {
+            let url = git.unwrap_or_else(|| repo.url());
+            match git::resolve_rev(&url, rev) {
+                Ok(full) => resolved.push(full),
+                Err(e) => errors.push(e),
+            }
+        }
+        resolved.sort();
+        resolved.dedup();
+
+        match resolved.len() {
+            // Keep the error itself, e.g. to exit with the code for network errors.
+            0 if errors.len() == 1 => {
+                return Err(errors.remove(0))
+                    .with_context(|| anyhow!("Failed to resolve `--rev {}`", rev));
+            }
+            0 => bail!(
+                "Failed to resolve `--rev {}`:\n  {}",
+                rev,
+                errors
+                    .iter()
+                    .map(|e| format!("{:#}", e))
+                    .collect::<Vec<_>>()
+                    .join("\n  ")
+            ),
+            1 => {
+                log::info!("Resolved `{}` to `{}`.", rev, resolved[0]);
+                *rev = resolved.remove(0);
+            }
+            _ => bail!(
+                "`--rev {}` is ambiguous, it matches:\n  {}",
+                rev,
+                resolved.join("\n  ")
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the default branch of the repository at `url`, if `branch` doesn't exist there.
+///
+/// Returns `None` if `branch` still exists or the branches of the repository can not be
+/// fetched.
+fn default_branch(name: &str, url: &str, branch: &str, rules: &Rules) -> Option<String> {
+    let remote = rules
+        .remote_branches
+        .borrow_mut()
+        .entry(url.into())
+        .or_insert_with(|| {
+            git::remote_branches(url)
+                .map_err(|e| log::warn!("Failed to fetch the branches of {}: {:#}", url, e))
+                .ok()
+        })
+        .clone()?;
+
+    if remote.branches.iter().any(|b| b == branch) {
+        return None;
+    }
+
+    match remote.default {
+        Some(default) if default != branch => Some(default),
+        _ => {
+            log::warn!(
+                "  `{}`: branch `{}` doesn't exist in {} and it has no default branch.",
+                name,
+                branch,
+                url
+            );
+            None
+        }
+    }
+}
+
+/// Returns the commit the given `package` from `git` at `branch` is locked to.
+fn locked_rev(lock_file: &LockFile, package: &str, git: &GitUrl, branch: &str) -> Option<String> {
+    lock_file
+        .packages
+        .iter()
+        .filter(|p| p.name == package)
+        .filter_map(|p| p.git_source())
+        .find(|s| {
+            s.reference
+                .as_ref()
+                .is_some_and(|(k, v)| k == "branch" && v == branch)
+                && GitUrl::parse(&s.url).is_ok_and(|url| same_repository(&url, git))
+        })
+        .map(|s| s.commit)
+}
+
+/// Returns the commit the repository of `git` is pinned to in the given `lock_file`.
+///
+/// Prefers the commit of the given `package`. Other packages from the same repository are
+/// used for crates the other project doesn't depend on. The repository is also matched if
+/// the `Cargo.lock` uses another url for it, e.g. the official one instead of a fork.
+fn pinned_rev(
+    lock_file: &LockFile,
+    package: &str,
+    git: &GitUrl,
+    matcher: &Matcher,
+) -> Option<String> {
+    let repo = matcher.classify(git);
+    let sources = lock_file
+        .packages
+        .iter()
+        .filter_map(|p| p.git_source().map(|s| (p, s)))
+        .filter(|(_, s)| {
+            GitUrl::parse(&s.url).is_ok_and(|url| {
+                same_repository(&url, git) || (repo.is_some() && matcher.classify(&url) == repo)
+            })
+        })
+        .collect::<Vec<_>>();
+
+    sources
+        .iter()
+        .find(|(p, _)| p.name == package)
+        .or_else(|| sources.first())
+        .map(|(_, s)| s.commit.clone())
+}
+
+/// Handle the given file, either a `Cargo.toml` or a cargo config file.
+///
+/// Returns the changed dependencies and if the file was modified. A per-file hook is run for
+/// modified files.
+fn handle_file(path: &Path, root: &Root, rules: &Rules) -> Result<(Vec<Change>, bool)> {
+    let (changes, modified) = if is_cargo_config(path) {
+        handle_cargo_config(path, root, rules)?
+    } else {
+        handle_toml_file(path, root, rules)?
+    };
+    // With `--emit` only the emitted rewrite is printed.
+    if rules.patches.is_none() {
+        changes.iter().for_each(|c| report::change(path, c));
+    }
+
+    if let Some(hook) = rules
+        .hook
+        .as_ref()
+        .filter(|h| !h.once && modified && rules.planned.is_none())
+    {
+        hook.run(&[path.into()])?;
+    }
+
+    Ok((changes, modified))
+}
+
+/// Handle a given `Cargo.toml`.
+///
+/// This means scanning all dependencies and rewrite the requested onces.
+///
+/// Returns the changed dependencies and if the file was modified.
+fn handle_toml_file(path: &Path, root: &Root, rules: &Rules) -> Result<(Vec<Change>, bool)> {
+    log::info!(manifest:% = path.display(); "Processing: {}", path.display());
+
+    let content = fs::read_to_string(path)?;
+    // Manifests without dependency tables don't need to be parsed at all.
+    let workspace_package = !rules.workspace_package.is_empty() && content.contains("workspace");
+    if !content.contains("dependencies") && !workspace_package {
+        return Ok((Vec::new(), false));
+    }
+
+    let mut toml_doc = Document::from_str(&content)?;
+    let mut changes = Vec::new();
+    if workspace_package {
+        changes.extend(set_workspace_package(
+            path,
+            &mut toml_doc,
+            &rules.workspace_package,
+        ));
+    }
+    let mut renamed = Vec::new();
+
+    let unified_features = rules.unified_features.get(path);
+    let kind = |k: &str| dependency_kind(k, rules.dev_only);
+    for deps in dependency_tables_mut(&mut toml_doc, kind) {
+        let mut rewritten = Vec::new();
+
+        for (name, dep) in deps.iter_mut() {
+            if rules.strict {
+                check_git_url(path, name.get(), dep, &rules.matcher)?;
+            }
+
+            // Only inline tables (`{ foo = bar }`) and dotted-key tables (`foo.bar = baz`)
+            // can be git dependencies.
+            let is_table = dep.is_inline_table() || dep.as_table().is_some_and(|t| t.is_dotted());
+            let change = if is_table {
+                handle_dependency(name.get(), dep, root, rules)
+            } else {
+                None
+            };
+
+            if let Some(change) = change {
+                changes.push(change);
+                rewritten.push(name.get().to_string());
+            } else if rules.crates_io_deps {
+                changes.extend(upgrade_registry_dependency(name.get(), dep, rules)?);
+            }
+
+            let package = dep
+                .get("package")
+                .and_then(|p| p.as_str())
+                .unwrap_or(name.get())
+                .to_string();
+            if let Some(unified) = unified_features.and_then(|u| u.get(&package)) {
+                let old = FeatureSet::of(dep);
+                if unified.apply(dep) {
+                    changes.push(Change {
+                        name: name.get().into(),
+                        old: old.map(|o| o.to_string()).unwrap_or_default(),
+                        new: unified.to_string(),
+                    });
+                }
+            }
+        }
+
+        renamed.extend(
+            rewritten
+                .iter()
+                .filter_map(|name| rename_dependency(deps, name, &rules.renames)),
+        );
+    }
+
+    // Nothing was touched, so there is no need to serialize the document again.
+    if changes.is_empty() {
+        return Ok((changes, false));
+    }
+
+    if let Some(features) = toml_doc.get_mut("features").and_then(|f| f.as_table_mut()) {
+        renamed
+            .iter()
+            .for_each(|(old, new)| rename_in_features(features, old, new));
+    }
+
+    let modified = write_if_changed(path, &content, toml_doc.to_string(), rules)?;
+    Ok((changes, modified))
+}
+
+/// Set the `[workspace.package]` `fields` given by `--workspace-package` in the manifest `doc`.
+///
+/// Fields the workspace doesn't declare are not added, as the members would need to inherit them
+/// first.
+fn set_workspace_package(
+    path: &Path,
+    doc: &mut Document,
+    fields: &BTreeMap<String, String>,
+) -> Vec<Change> {
+    let package = match doc
+        .get_mut("workspace")
+        .and_then(|w| w.get_mut("package"))
+        .and_then(|p| p.as_table_like_mut())
+    {
+        Some(package) => package,
+        None => {
+            if doc.contains_key("workspace") {
+                log::warn!(
+                    "{} has no `[workspace.package]`, its fields are not set.",
+                    path.display()
+                );
+            }
+            return Vec::new();
+        }
+    };
+
+    let mut changes = Vec::new();
+    for (field, value) in fields {
+        let old = match package.get_mut(field).and_then(|f| f.as_value_mut()) {
+            Some(old) if old.as_str() == Some(value.as_str()) => continue,
+            Some(old) if old.is_str() => old,
+            _ => {
+                log::warn!(
+                    "{} has no string `workspace.package.{}`, it is not set.",
+                    path.display(),
+                    field
+                );
+                continue;
+            }
+        };
+
+        // Keep the formatting (e.g. the comments) around the value.
+        let decor = old.decor().clone();
+        let previous = std::mem::replace(old, Value::from(value.as_str()));
+        *old.decor_mut() = decor;
+        changes.push(Change {
+            name: format!("workspace.package.{}", field),
+            old: previous.as_str().unwrap_or_default().into(),
+            new: value.clone(),
+        });
+    }
+
+    changes
+}
+
+/// Returns `true` if the dependency table `kind` (e.g. `dev-dependencies`) should be processed.
+fn dependency_kind(kind: &str, dev_only: bool) -> bool {
+    if dev_only {
+        kind == "dev-dependencies"
+    } else {
+        kind.contains("dependencies")
+    }
+}
+
+/// Upgrade the registry dependency `name` to the latest version that is compatible with its
+/// current version requirement.
+///
+/// `dep` is either a plain version (`foo = "1.2"`) or a table with a `version` and without
+/// any other source. Returns the change, if the dependency was upgraded.
+fn upgrade_registry_dependency(
+    name: &str,
+    dep: &mut Item,
+    rules: &Rules,
+) -> Result<Option<Change>> {
+    let (version, package) = match dep {
+        Item::Value(Value::String(version)) => (version, name.to_string()),
+        _ => {
+            let dep = match dep.as_table_like_mut() {
+                Some(dep) => dep,
+                None => return Ok(None),
+            };
+            if ["git", "path", "registry", "workspace"]
+                .iter()
+                .any(|k| dep.contains_key(k))
+            {
+                return Ok(None);
+            }
+
+            let package = dep
+                .get("package")
+                .and_then(|p| p.as_str())
+                .unwrap_or(name)
+                .to_string();
+            match dep.get_mut("version").and_then(|v| v.as_value_mut()) {
+                Some(Value::String(version)) => (version, package),
+                _ => return Ok(None),
+            }
+        }
+    };
+
+    let old = version.value().trim().to_string();
+    let (prefix, req) = match semver::VersionReq::parse(&old) {
+        Ok(req) if req.comparators.len() == 1 => match req.comparators[0].op {
+            semver::Op::Caret if old.starts_with('^') => ("^", req),
+            semver::Op::Caret => ("", req),
+            semver::Op::Tilde => ("~", req),
+            _ => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+
+    let mut crate_versions = rules.crate_versions.borrow_mut();
+    let versions = match crate_versions.entry(package) {
+        Entry::Occupied(versions) => versions.into_mut(),
+        Entry::Vacant(entry) => {
+            let versions = fetch_versions(entry.key(), rules)?;
+            if versions.is_none() {
+                match rules.on_missing {
+                    OnMissing::Skip => {
+                        log::debug!("  `{}` is not published on crates.io.", entry.key())
+                    }
+                    OnMissing::Keep => {
+                        log::warn!("  `{}` is not published on crates.io.", entry.key())
+                    }
+                    OnMissing::Fail => bail!(
+                        "`{}` is not published on crates.io. Pass `--on-missing skip` or \
+                         `--on-missing keep` to leave it untouched.",
+                        entry.key()
+                    ),
+                }
+            }
+            entry.insert(versions)
+        }
+    };
+    let latest = versions
+        .iter()
+        .flatten()
+        .filter(|v| req.matches(v))
+        .max()
+        .cloned();
+    let latest = match latest {
+        Some(latest) => latest,
+        None => return Ok(None),
+    };
+
+    let comparator = &req.comparators[0];
+    let minimum = semver::Version {
+        major: comparator.major,
+        minor: comparator.minor.unwrap_or(0),
+        patch: comparator.patch.unwrap_or(0),
+        pre: comparator.pre.clone(),
+        build: Default::default(),
+    };
+    if latest <= minimum {
+        return Ok(None);
+    }
+
+    let new = format!("{}{}", prefix, latest);
+
+    let decor = version.decor().clone();
+    *version = toml_edit::Formatted::new(new.clone());
+    *version.decor_mut() = decor;
+    log::debug!("  upgraded: {} => {}", name, latest);
+    Ok(Some(Change {
+        name: name.into(),
+        old,
+        new,
+    }))
+}
+
+/// Apply the `renames` to the dependency `name` in the given dependency `table`.
+///
+/// Renamed dependencies (`foo = { package = "bar" }`) get their `package` changed, all other
+/// dependencies their key. Returns `(old, new)` if the key was changed.
+fn rename_dependency(
+    table: &mut Table,
+    name: &str,
+    renames: &BTreeMap<String, String>,
+) -> Option<(String, String)> {
+    let dep = table.get_mut(name)?.as_table_like_mut()?;
+
+    if let Some(package) = dep.get_mut("package").and_then(|p| p.as_value_mut()) {
+        let new = renames.get(package.as_str()?)?;
+        log::debug!("  renamed package: {} => {}", package, new);
+
+        let decor = package.decor().clone();
+        *package = Value::from(new.as_str());
+        *package.decor_mut() = decor;
+        return None;
+    }
+
+    let new = renames.get(name)?;
+    if table.contains_key(new) {
+        log::warn!(
+            "  not renaming `{}` to `{}`, the dependency already exists.",
+            name,
+            new
+        );
+        return None;
+    }
+    log::debug!("  renamed: {} => {}", name, new);
+
+    // Keep the position of the dependency.
+    let order = table
+        .iter()
+        .map(|(k, _)| if k == name { new.clone() } else { k.into() })
+        .collect::<Vec<_>>();
+    let position = |key: &Key| order.iter().position(|k| k == key.get());
+
+    let (key, item) = table.remove_entry(name)?;
+    table.insert_formatted(
+        &Key::new(new.as_str()).with_decor(key.decor().clone()),
+        item,
+    );
+    table.sort_values_by(|a, _, b, _| position(a).cmp(&position(b)));
+
+    Some((name.into(), new.clone()))
+}
+
+/// Rename all references to the dependency `old` in the `[features]` table to `new`.
+///
+/// This covers `old`, `dep:old`, `old/feature` and `old?/feature`.
+fn rename_in_features(features: &mut Table, old: &str, new: &str) {
+    features
+        .iter_mut()
+        .filter_map(|(_, f)| f.as_array_mut())
+        .flat_map(|f| f.iter_mut())
+        .for_each(|feature| {
+            let renamed = match feature.as_str() {
+                Some(f) if f == old => new.to_string(),
+                Some(f) if f.strip_prefix("dep:") == Some(old) => format!("dep:{}", new),
+                Some(f) => match f.split_once('/') {
+                    Some((dep, rest)) if dep.trim_end_matches('?') == old => {
+                        format!("{}{}/{}", new, &dep[old.len()..], rest)
+                    }
+                    _ => return,
+                },
+                None => return,
+            };
+
+            let decor = feature.decor().clone();
+            *feature = Value::from(renamed);
+            *feature.decor_mut() = decor;
+        });
+}
+
+/// Handle a given cargo config file (`.cargo/config.toml`).
+///
+/// This rewrites the dependencies in all `[patch]` sections and the git `[source]`
+/// replacements.
+///
+/// Returns the changed entries and if the file was modified.
+fn handle_cargo_config(path: &Path, root: &Root, rules: &Rules) -> Result<(Vec<Change>, bool)> {
+    log::info!(manifest:% = path.display(); "Processing: {}", path.display());
+
+    let content = fs::read_to_string(path)?;
+    let mut doc = Document::from_str(&content)?;
+    let mut changes = Vec::new();
+
+    if let Some(patch) = doc.get_mut("patch").and_then(|p| p.as_table_like_mut()) {
+        for (name, dep) in patch
+            .iter_mut()
+            .filter_map(|(_, deps)| deps.as_table_like_mut())
+            .flat_map(|deps| deps.iter_mut())
+        {
+            if rules.strict {
+                check_git_url(path, name.get(), dep, &rules.matcher)?;
+            }
+            changes.extend(handle_dependency(name.get(), dep, root, rules));
+        }
+    }
+
+    if let Some(sources) = doc.get_mut("source").and_then(|s| s.as_table_like_mut()) {
+        for (name, source) in sources.iter_mut() {
+            if rules.strict {
+                check_git_url(path, name.get(), source, &rules.matcher)?;
+            }
+            changes.extend(handle_dependency(name.get(), source, root, rules));
+        }
+    }
+
+    let modified = write_if_changed(path, &content, doc.to_string(), rules)?;
+    Ok((changes, modified))
+}
+
+/// Write `new` to `path`, if it differs from the `old` content.
+///
+/// Files that didn't change are not touched, to not trigger unneeded rebuilds. Returns `true`
+/// if the file was written.
+///
+/// For `diener plan` the new content is only recorded.
+fn write_if_changed(path: &Path, old: &str, new: String, rules: &Rules) -> Result<bool> {
+    if new == old {
+        return Ok(false);
+    }
+
+    if let Some(planned) = &rules.planned {
+        planned.borrow_mut().insert(path.into(), (old.into(), new));
+        return Ok(true);
+    }
+
+    if let Some(ext) = &rules.backup_ext {
+        let backup = backup_path(path, ext);
+        fs::write(&backup, old)
+            .with_context(|| anyhow!("Failed to write the backup {}", backup.display()))?;
+    }
+
+    atomic_write::write(path, new)
+        .with_context(|| anyhow!("Failed to write {}", path.display()))?;
+    Ok(true)
+}
+
+/// Returns the path of the backup of `path` for `--backup-ext`, the extension `ext` is appended
+/// to the file name.
+fn backup_path(path: &Path, ext: &str) -> PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+    if !ext.starts_with('.') {
+        backup.push(".");
+    }
+    backup.push(ext);
+    backup.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dependency_tables_include_target_tables() {
+        let doc = Document::from_str(
+            r#"
+[package]
+name = "foo"
+
+[dependencies]
+a = "1"
+
+[dev-dependencies]
+b = "1"
+
+[target.'cfg(unix)'.dependencies]
+c = "1"
+
+[target.'cfg(unix)'.dev-dependencies]
+d = "1"
+"#,
+        )
+        .unwrap();
+
+        let names = |kind: fn(&str) -> bool| {
+            dependency_tables(&doc, kind)
+                .into_iter()
+                .flat_map(|t| t.iter().map(|(k, _)| k.to_string()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(|k| k.contains("dependencies")), ["a", "b", "c", "d"]);
+        assert_eq!(names(|k| k == "dev-dependencies"), ["b", "d"]);
+    }
+
+    #[test]
+    fn registry_version_selection() {
+        let versions = ["3.0.0", "4.0.0", "4.2.1", "5.0.0-rc.1", "4.10.0"]
+            .map(|v| semver::Version::parse(v).unwrap());
+
+        let select = |version: &str| {
+            RegistryVersion::from_str(version)
+                .unwrap()
+                .select(&versions)
+        };
+        assert_eq!(select("latest").as_deref(), Some("4.10.0"));
+        assert_eq!(select("latest:3").as_deref(), Some("3.0.0"));
+        assert_eq!(select("latest:5"), None);
+        assert_eq!(select("5.0.0-rc.1").as_deref(), Some("5.0.0-rc.1"));
+
+        assert!(RegistryVersion::from_str("latest:x").is_err());
+        assert!(RegistryVersion::from_str("not a version").is_err());
+    }
+
+    #[test]
+    fn locked_versions_are_taken_from_the_release() {
+        let lock_file = LockFile::from_str(
+            r#"
+[[package]]
+name = "sp-core"
+version = "21.0.0"
+
+[[package]]
+name = "sp-io"
+version = "23.0.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "sp-runtime"
+version = "24.0.0"
+source = "git+https://github.com/paritytech/polkadot-sdk?branch=master#abc"
+
+[[package]]
+name = "sp-std"
+version = "8.0.0"
+
+[[package]]
+name = "sp-std"
+version = "9.0.0"
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(locked_version(&lock_file, "sp-core").unwrap(), "21.0.0");
+        assert_eq!(locked_version(&lock_file, "sp-io").unwrap(), "23.0.0");
+        assert!(locked_version(&lock_file, "sp-runtime").is_err());
+        assert!(locked_version(&lock_file, "sp-std").is_err());
+    }
+
+    #[test]
+    fn workspace_package_fields_are_set() {
+        let mut doc = Document::from_str(
+            "[workspace.package]\nversion = \"1.0.0\" # Bumped on release.\nrust-version = \"1.75\"\n",
+        )
+        .unwrap();
+        let fields = BTreeMap::from([
+            ("version".to_string(), "1.1.0".to_string()),
+            ("rust-version".to_string(), "1.75".to_string()),
+            ("edition".to_string(), "2021".to_string()),
+        ]);
+
+        let changes = set_workspace_package(Path::new("Cargo.toml"), &mut doc, &fields);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].name, "workspace.package.version");
+        assert_eq!(changes[0].old, "1.0.0");
+        assert_eq!(
+            doc.to_string(),
+            "[workspace.package]\nversion = \"1.1.0\" # Bumped on release.\nrust-version = \"1.75\"\n"
+        );
+    }
+
+    #[test]
+    fn version_of_git_dependencies_is_kept_or_dropped() {
+        let rewrite = |flags: &[&str]| {
+            let mut args = vec!["update", "--substrate", "--branch", "master"];
+            args.extend(flags);
+            let (rules, roots) = Update::from_iter_safe(args).unwrap().into_parts().unwrap();
+            let mut doc = Document::from_str(
+                "sp-io = { git = \"https://github.com/paritytech/substrate\", tag = \"v1.0\", version = \"4.0\" }\n",
+            )
+            .unwrap();
+            handle_dependency("sp-io", &mut doc["sp-io"], &roots[0], &rules).unwrap();
+            doc.to_string()
+        };
+
+        assert_eq!(
+            rewrite(&[]),
+            "sp-io = { git = \"https://github.com/paritytech/substrate\", version = \"4.0\", branch = \"master\" }\n"
+        );
+        assert_eq!(
+            rewrite(&["--drop-version"]),
+            "sp-io = { git = \"https://github.com/paritytech/substrate\", branch = \"master\" }\n"
+        );
+    }
+
+    #[test]
+    fn forks_are_only_rewritten_with_match_name_only() {
+        let matcher = |flags: &[&str]| {
+            let mut args = vec!["diener"];
+            args.extend(flags);
+            Matcher::new(MatchOptions::from_iter_safe(args).unwrap()).unwrap()
+        };
+        let official = GitUrl::parse("https://github.com/paritytech/substrate").unwrap();
+        let fork = GitUrl::parse("https://github.com/myorg/substrate").unwrap();
+        let other = GitUrl::parse("https://github.com/myorg/frontier").unwrap();
+
+        let default = matcher(&[]);
+        assert!(Rewrite::All(None).matches(&official, &default).is_some());
+        assert!(Rewrite::Substrate(None)
+            .matches(&official, &default)
+            .is_some());
+        assert!(Rewrite::All(None).matches(&fork, &default).is_none());
+        assert!(Rewrite::Substrate(None).matches(&fork, &default).is_none());
+        assert!(Rewrite::All(None).matches(&other, &default).is_none());
+
+        let name_only = matcher(&["--match-name-only"]);
+        assert!(Rewrite::Substrate(None)
+            .matches(&fork, &name_only)
+            .is_some());
+        assert!(Rewrite::Polkadot(None).matches(&fork, &name_only).is_none());
+        // `--all` keeps the legacy behavior of rewriting every git dependency.
+        assert!(Rewrite::All(None).matches(&fork, &name_only).is_some());
+        assert!(Rewrite::All(None).matches(&other, &name_only).is_some());
+    }
+
+    #[test]
+    fn dotted_dependencies_keep_their_keys() {
+        let rewrite = |flags: &[&str]| {
+            let mut args = vec!["update", "--substrate"];
+            args.extend(flags);
+            let (rules, roots) = Update::from_iter_safe(args).unwrap().into_parts().unwrap();
+            let mut doc = Document::from_str(
+                "[dependencies]\n\
+                 sp-core.git = \"https://github.com/paritytech/substrate\"\n\
+                 sp-core.branch = \"polkadot-v0.9.40\"\n\
+                 sp-core.default-features = false\n",
+            )
+            .unwrap();
+            let dep = &mut doc["dependencies"]["sp-core"];
+            handle_dependency("sp-core", dep, &roots[0], &rules).unwrap();
+            doc.to_string()
+        };
+
+        assert_eq!(
+            rewrite(&["--branch", "master"]),
+            "[dependencies]\n\
+             sp-core.git = \"https://github.com/paritytech/substrate\"\n\
+             sp-core.branch = \"master\"\n\
+             sp-core.default-features = false\n"
+        );
+        assert_eq!(
+            rewrite(&["--rev", "abc"]),
+            "[dependencies]\n\
+             sp-core.git = \"https://github.com/paritytech/substrate\"\n\
+             sp-core.default-features = false\n\
+             sp-core.rev = \"abc\"\n"
+        );
+    }
+
+    #[test]
+    fn backup_path_appends_the_extension() {
+        let path = Path::new("runtime/Cargo.toml");
+        assert_eq!(
+            backup_path(path, ".bak"),
+            Path::new("runtime/Cargo.toml.bak")
+        );
+        assert_eq!(
+            backup_path(path, "orig"),
+            Path::new("runtime/Cargo.toml.orig")
+        );
+    }
+
+    #[test]
+    fn patch_tables_are_grouped_by_repository() {
+        let mut patches = Patches::new();
+        let substrate = patches
+            .entry("https://github.com/paritytech/substrate".into())
+            .or_default();
+        for package in ["sp-io", "sp-core"] {
+            substrate.insert(
+                package.into(),
+                vec![
+                    ("git", "https://github.com/paritytech/polkadot-sdk".into()),
+                    ("branch", "master".into()),
+                ],
+            );
+        }
+
+        assert_eq!(
+            patch_tables(&patches),
+            r#"[patch."https://github.com/paritytech/substrate"]
+sp-core = { git = "https://github.com/paritytech/polkadot-sdk", branch = "master" }
+sp-io = { git = "https://github.com/paritytech/polkadot-sdk", branch = "master" }
+"#
+        );
+    }
 }
@@ -0,0 +1,476 @@
+use crate::{
+    atomic_write,
+    repos::{split_reference, MatchOptions, Matcher},
+    run_lock,
+    update::{dependency_tables, dependency_tables_mut, Rewrite},
+    workspacify::{manifest_iter, read_toml},
+};
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use git_url_parse::GitUrl;
+use std::{
+    collections::{BTreeMap, HashSet},
+    env::current_dir,
+    path::PathBuf,
+};
+use structopt::StructOpt;
+use toml_edit::{value, Document, InlineTable, Item, Table, Value};
+
+/// The keys of a dependency that describe where it comes from.
+///
+/// These are moved into `[workspace.dependencies]`.
+const SOURCE_KEYS: &[&str] = &[
+    "package", "git", "branch", "tag", "rev", "version", "registry",
+];
+
+/// `workspaceify` subcommand options.
+#[derive(Debug, StructOpt)]
+pub struct Workspaceify {
+    /// The path to the workspace root directory.
+    ///
+    /// This is the directory where your workspace `Cargo.toml` is located.
+    /// Uses the working directory if none is supplied.
+    #[structopt(long)]
+    path: Option<PathBuf>,
+
+    /// Only hoist Substrate dependencies.
+    #[structopt(long, short = "s")]
+    substrate: bool,
+
+    /// Only hoist Polkadot dependencies.
+    #[structopt(long, short = "p")]
+    polkadot: bool,
+
+    /// Only hoist Cumulus dependencies.
+    #[structopt(long, short = "c")]
+    cumulus: bool,
+
+    /// Only hoist BEEFY dependencies.
+    #[structopt(long, short = "b")]
+    beefy: bool,
+
+    /// Hoist polkadot, substrate, cumulus + beefy dependencies.
+    #[structopt(long, short = "a")]
+    all: bool,
+
+    /// Also hoist dependencies that are fetched from a registry (`foo = "1.0"`).
+    #[structopt(long)]
+    crates_io: bool,
+
+    #[structopt(flatten)]
+    match_options: MatchOptions,
+}
+
+/// A dependency that is a candidate for being moved into `[workspace.dependencies]`.
+struct Candidate {
+    /// The source keys of the dependency, see [`SOURCE_KEYS`].
+    source: Vec<(&'static str, Value)>,
+    /// Is any member disabling the default features of this dependency?
+    no_default_features: bool,
+    /// The manifests that declare this dependency with a different source.
+    conflicts: Vec<PathBuf>,
+}
+
+impl Workspaceify {
+    /// Run this subcommand.
+    pub fn run(self) -> Result<()> {
+        let rewrite = if self.all {
+            Some(Rewrite::All(None))
+        } else if self.substrate {
+            Some(Rewrite::Substrate(None))
+        } else if self.polkadot {
+            Some(Rewrite::Polkadot(None))
+        } else if self.cumulus {
+            Some(Rewrite::Cumulus(None))
+        } else if self.beefy {
+            Some(Rewrite::Beefy(None))
+        } else if self.crates_io {
+            None
+        } else {
+            bail!("You must specify one of `--substrate`, `--polkadot`, `--cumulus`, `--beefy`, `--all` or `--crates-io`.");
+        };
+
+        let workspace = self
+            .path
+            .map(Ok)
+            .unwrap_or_else(|| current_dir().with_context(|| "Working directory is invalid."))?;
+        ensure!(
+            workspace.is_dir(),
+            "Path '{}' is not a directory.",
+            workspace.display()
+        );
+        let _lock = run_lock::acquire(&workspace)?;
+
+        let root_manifest = workspace.join("Cargo.toml");
+        let mut root = read_toml(&root_manifest, false)?;
+        ensure!(
+            root.get("workspace").is_some_and(|w| w.is_table()),
+            "`{}` is not a workspace manifest.",
+            root_manifest.display()
+        );
+
+        let members = manifest_iter(&workspace)
+            .filter(|m| *m != root_manifest)
+            .map(|m| read_toml(&m, false).map(|doc| (m, doc)))
+            .collect::<Result<Vec<_>>>()?;
+
+        let selector = Selector {
+            rewrite,
+            matcher: Matcher::new(self.match_options)?,
+            crates_io: self.crates_io,
+        };
+        let mut candidates = BTreeMap::<String, Candidate>::new();
+
+        // Dependencies that are already part of the workspace win.
+        if let Some(deps) = root
+            .get("workspace")
+            .and_then(|w| w.get("dependencies"))
+            .and_then(|d| d.as_table_like())
+        {
+            for (name, dep) in deps.iter() {
+                if let Some(source) = dependency_source(dep) {
+                    let no_default_features = disables_default_features(dep);
+                    candidates.insert(
+                        name.into(),
+                        Candidate {
+                            source,
+                            no_default_features,
+                            conflicts: Vec::new(),
+                        },
+                    );
+                }
+            }
+        }
+        let existing = candidates.keys().cloned().collect::<HashSet<_>>();
+
+        for (path, doc) in
+            std::iter::once((&root_manifest, &root)).chain(members.iter().map(|(p, d)| (p, d)))
+        {
+            for (name, dep) in dependencies(doc).filter(|(_, dep)| selector.selects(dep)) {
+                let source = dependency_source(dep).expect("`selects` checks the source; qed");
+                let no_default_features = disables_default_features(dep);
+
+                match candidates.get_mut(name) {
+                    Some(candidate) => {
+                        if same_source(&candidate.source, &source) {
+                            candidate.no_default_features |= no_default_features;
+                        } else {
+                            candidate.conflicts.push(path.clone());
+                        }
+                    }
+                    None => {
+                        candidates.insert(
+                            name.into(),
+                            Candidate {
+                                source,
+                                no_default_features,
+                                conflicts: Vec::new(),
+                            },
+                        );
+                    }
+                }
+            }
+        }
+
+        candidates.retain(|name, candidate| {
+            if candidate.conflicts.is_empty() {
+                true
+            } else {
+                log::warn!(
+                    "Not hoisting `{}`, it is declared with different sources in: {:?}",
+                    name,
+                    candidate.conflicts
+                );
+                false
+            }
+        });
+
+        for (path, mut doc) in members {
+            if rewrite_member(&mut doc, &selector, &candidates) > 0 {
+                atomic_write::write(&path, doc.to_string())
+                    .with_context(|| anyhow!("Failed to write manifest to {}", path.display()))?;
+            }
+        }
+
+        let mut root_changed = rewrite_member(&mut root, &selector, &candidates) > 0;
+        let workspace_deps = root["workspace"]
+            .as_table_mut()
+            .expect("Checked above that `workspace` is a table; qed")
+            .entry("dependencies")
+            .or_insert_with(|| {
+                let mut table = Table::new();
+                table.decor_mut().set_prefix("\n");
+                Item::Table(table)
+            })
+            .as_table_like_mut()
+            .ok_or_else(|| anyhow!("`workspace.dependencies` is not a table"))?;
+
+        for (name, candidate) in candidates.iter().filter(|c| !existing.contains(c.0)) {
+            log::info!("Hoisting `{}` into the workspace.", name);
+
+            let mut dep = InlineTable::new();
+            candidate.source.iter().for_each(|(key, value)| {
+                dep.insert(*key, value.clone());
+            });
+            if candidate.no_default_features {
+                dep.insert("default-features", false.into());
+            }
+            dep.fmt();
+            workspace_deps.insert(name, value(dep));
+            root_changed = true;
+        }
+
+        if root_changed {
+            atomic_write::write(&root_manifest, root.to_string()).with_context(|| {
+                anyhow!("Failed to write manifest to {}", root_manifest.display())
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Decides which dependencies should be hoisted.
+struct Selector {
+    rewrite: Option<Rewrite>,
+    matcher: Matcher,
+    crates_io: bool,
+}
+
+impl Selector {
+    fn selects(&self, dep: &Item) -> bool {
+        if dependency_source(dep).is_none() {
+            return false;
+        }
+
+        match dep
+            .as_table_like()
+            .and_then(|d| d.get("git"))
+            .and_then(|g| g.as_str())
+        {
+            Some(git) => GitUrl::parse(split_reference(git).0).is_ok_and(|git| {
+                self.rewrite
+                    .as_ref()
+                    .is_some_and(|r| r.matches(&git, &self.matcher).is_some())
+            }),
+            None => self.crates_io,
+        }
+    }
+}
+
+/// Returns all dependencies of the given manifest, including the platform specific ones.
+fn dependencies(doc: &Document) -> impl Iterator<Item = (&str, &Item)> {
+    dependency_tables(doc, |k| k.contains("dependencies"))
+        .into_iter()
+        .flat_map(|t| t.iter())
+}
+
+/// Returns the source keys of the given dependency.
+///
+/// Returns `None` for dependencies that can not be hoisted, e.g. `path` dependencies or
+/// dependencies that already use `workspace = true`.
+fn dependency_source(dep: &Item) -> Option<Vec<(&'static str, Value)>> {
+    if let Some(version) = dep.as_str() {
+        return Some(vec![("version", version.into())]);
+    }
+
+    let dep = dep.as_table_like()?;
+    if dep.contains_key("path") || dep.contains_key("workspace") {
+        return None;
+    }
+
+    let source = SOURCE_KEYS
+        .iter()
+        .filter_map(|k| {
+            dep.get(k)
+                .and_then(|v| v.as_value())
+                .map(|v| (*k, v.clone()))
+        })
+        .collect::<Vec<_>>();
+
+    if source.iter().any(|(k, _)| *k == "git" || *k == "version") {
+        Some(source)
+    } else {
+        None
+    }
+}
+
+/// Returns `true` if the given dependency sets `default-features = false`.
+fn disables_default_features(dep: &Item) -> bool {
+    dep.as_table_like()
+        .and_then(|d| d.get("default-features"))
+        .and_then(|d| d.as_bool())
+        == Some(false)
+}
+
+fn same_source(a: &[(&'static str, Value)], b: &[(&'static str, Value)]) -> bool {
+    let normalize = |s: &[(&'static str, Value)]| {
+        s.iter()
+            .map(|(k, v)| (*k, v.to_string().trim().to_string()))
+            .collect::<BTreeMap<_, _>>()
+    };
+
+    normalize(a) == normalize(b)
+}
+
+/// Rewrites all hoisted dependencies of the given member to `workspace = true`.
+///
+/// Returns the number of rewritten dependencies.
+fn rewrite_member(
+    doc: &mut Document,
+    selector: &Selector,
+    candidates: &BTreeMap<String, Candidate>,
+) -> usize {
+    dependency_tables_mut(doc, |k| k.contains("dependencies"))
+        .into_iter()
+        .flat_map(|t| t.iter_mut())
+        .filter(|(_, dep)| selector.selects(dep))
+        .map(|(mut name, dep)| {
+            let candidate = match candidates.get(name.get()) {
+                Some(candidate) => candidate,
+                None => return false,
+            };
+
+            let mut new_dep = InlineTable::new();
+            new_dep.insert("workspace", true.into());
+
+            if let Some(old) = dep.as_table_like() {
+                let default_features = old.get("default-features").and_then(|d| d.as_bool());
+                // Members can only re-enable default features that the workspace disabled.
+                if candidate.no_default_features && default_features != Some(false) {
+                    new_dep.insert("default-features", true.into());
+                }
+
+                ["features", "optional"].iter().for_each(|k| {
+                    if let Some(v) = old.get(k).and_then(|v| v.as_value()) {
+                        new_dep.insert(*k, v.clone());
+                    }
+                });
+            }
+            new_dep.fmt();
+            new_dep.set_dotted(false);
+
+            // `sp-io.git = ".."` has no space between the key and the dot.
+            if dep.as_table_like().is_some_and(|d| d.is_dotted()) {
+                name.decor_mut().set_suffix(" ");
+            }
+
+            let decor = dep.as_value().map(|v| v.decor().clone());
+            let mut new_dep = Value::InlineTable(new_dep);
+            if let Some(decor) = decor {
+                *new_dep.decor_mut() = decor;
+            }
+
+            log::info!("  `{}` => `workspace = true`", name.get());
+            *dep = Item::Value(new_dep);
+            true
+        })
+        .filter(|rewritten| *rewritten)
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Runs `workspaceify` with `args` on a workspace with the given `(path, manifest)` files
+    /// and returns the manifests afterwards.
+    fn workspaceify(name: &str, args: &[&str], files: &[(&str, &str)]) -> Vec<String> {
+        let dir = std::env::temp_dir().join(format!(
+            "diener-workspaceify-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        for (path, manifest) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, manifest).unwrap();
+        }
+
+        Workspaceify::from_iter_safe(
+            ["workspaceify", "--path", dir.to_str().unwrap()]
+                .iter()
+                .chain(args),
+        )
+        .unwrap()
+        .run()
+        .unwrap();
+
+        let manifests = files
+            .iter()
+            .map(|(path, _)| fs::read_to_string(dir.join(path)).unwrap())
+            .collect();
+        fs::remove_dir_all(&dir).unwrap();
+        manifests
+    }
+
+    const ROOT: &str = "[workspace]\nmembers = [\"a\", \"b\"]\n";
+
+    #[test]
+    fn dependencies_are_hoisted_into_the_workspace() {
+        let manifests = workspaceify(
+            "hoist",
+            &["--all"],
+            &[
+                ("Cargo.toml", ROOT),
+                (
+                    "a/Cargo.toml",
+                    "[dependencies]\n\
+                     sp-io = { git = \"https://github.com/paritytech/substrate\", branch = \"master\", default-features = false, features = [\"std\"] }\n\
+                     serde = \"1\"\n",
+                ),
+                (
+                    "b/Cargo.toml",
+                    "[dependencies]\n\
+                     sp-io.git = \"https://github.com/paritytech/substrate\"\n\
+                     sp-io.branch = \"master\"\n\
+                     \n\
+                     [target.'cfg(unix)'.dependencies]\n\
+                     sp-core = { git = \"https://github.com/paritytech/substrate\", branch = \"master\", optional = true }\n",
+                ),
+            ],
+        );
+
+        assert_eq!(
+            manifests[0],
+            "[workspace]\nmembers = [\"a\", \"b\"]\n\n\
+             [workspace.dependencies]\n\
+             sp-core = { git = \"https://github.com/paritytech/substrate\", branch = \"master\" }\n\
+             sp-io = { git = \"https://github.com/paritytech/substrate\", branch = \"master\", default-features = false }\n"
+        );
+        assert_eq!(
+            manifests[1],
+            "[dependencies]\n\
+             sp-io = { workspace = true, features = [\"std\"] }\n\
+             serde = \"1\"\n"
+        );
+        assert_eq!(
+            manifests[2],
+            "[dependencies]\n\
+             sp-io = { workspace = true, default-features = true }\n\
+             \n\
+             [target.'cfg(unix)'.dependencies]\n\
+             sp-core = { workspace = true, optional = true }\n"
+        );
+    }
+
+    #[test]
+    fn dependencies_with_conflicting_sources_are_not_hoisted() {
+        let a = "[dependencies]\n\
+                 sp-io = { git = \"https://github.com/paritytech/substrate\", branch = \"master\" }\n";
+        let b = "[dependencies]\n\
+                 sp-io = { git = \"https://github.com/paritytech/substrate\", rev = \"abc\" }\n";
+        let manifests = workspaceify(
+            "conflict",
+            &["--all"],
+            &[
+                ("Cargo.toml", ROOT),
+                ("a/Cargo.toml", a),
+                ("b/Cargo.toml", b),
+            ],
+        );
+
+        assert_eq!(manifests, [ROOT, a, b]);
+    }
+}
@@ -0,0 +1,97 @@
+use anyhow::{anyhow, ensure, Context, Result};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+};
+use toml_edit::Document;
+
+/// A cargo workspace, i.e. a `Cargo.toml` with a `[workspace]` table.
+#[derive(Debug, Clone)]
+pub(crate) struct Workspace {
+    /// The directory of the workspace root manifest.
+    pub path: PathBuf,
+    /// Is the root manifest a virtual manifest, i.e. without a `[package]`?
+    pub is_virtual: bool,
+}
+
+impl Workspace {
+    /// Returns a description of the workspace for the log output.
+    pub(crate) fn describe(&self) -> String {
+        if self.is_virtual {
+            format!("{} (virtual)", self.path.display())
+        } else {
+            self.path.display().to_string()
+        }
+    }
+
+    /// Update the `Cargo.lock` of the workspace to the rewritten dependencies.
+    pub(crate) fn update_lockfile(&self) -> Result<()> {
+        log::info!("Updating the `Cargo.lock` of {}", self.describe());
+
+        let status = Command::new("cargo")
+            .args(["update", "--workspace", "--manifest-path"])
+            .arg(self.path.join("Cargo.toml"))
+            .status()
+            .with_context(|| "Failed to run `cargo`, is it installed?")?;
+        ensure!(
+            status.success(),
+            "`cargo update` failed for {} with {}",
+            self.path.display(),
+            status
+        );
+
+        Ok(())
+    }
+}
+
+/// Returns the workspaces declared by the given `manifests`.
+///
+/// Workspaces that are nested in another one are reported, as they are processed with the
+/// same rules as the outer workspace.
+pub(crate) fn find(manifests: impl Iterator<Item = PathBuf>) -> Result<Vec<Workspace>> {
+    let mut workspaces = Vec::new();
+    for manifest in manifests {
+        let content = fs::read_to_string(&manifest)?;
+        // Parsing is expensive, most manifests are only members.
+        if !content.contains("workspace") {
+            continue;
+        }
+
+        let doc = Document::from_str(&content)
+            .with_context(|| anyhow!("Failed to parse {}", manifest.display()))?;
+        if !doc.contains_table("workspace") {
+            continue;
+        }
+
+        let path = manifest.parent().unwrap_or(Path::new("")).to_path_buf();
+        workspaces.push(Workspace {
+            is_virtual: !doc.contains_key("package"),
+            path,
+        });
+    }
+
+    for workspace in &workspaces {
+        if let Some(outer) = containing(
+            &workspaces,
+            workspace.path.parent().unwrap_or(Path::new("")),
+        ) {
+            log::info!(
+                "Found workspace {} nested in {}",
+                workspace.describe(),
+                outer.path.display()
+            );
+        }
+    }
+
+    Ok(workspaces)
+}
+
+/// Returns the innermost of the `workspaces` that contains `path`.
+pub(crate) fn containing<'a>(workspaces: &'a [Workspace], path: &Path) -> Option<&'a Workspace> {
+    workspaces
+        .iter()
+        .filter(|w| path.starts_with(&w.path))
+        .max_by_key(|w| w.path.components().count())
+}
@@ -1,8 +1,9 @@
+use crate::{atomic_write, run_lock};
 use anyhow::{anyhow, bail, ensure, Context, Result};
 use std::{
     collections::HashMap,
     env::current_dir,
-    fs::{self, OpenOptions},
+    fs::OpenOptions,
     io::Read,
     path::{Path, PathBuf},
     str::FromStr,
@@ -34,6 +35,7 @@ impl Workspacify {
             "Path '{}' is not a directory.",
             workspace.display()
         );
+        let _lock = run_lock::acquire(&workspace)?;
 
         // Create a mapping of package_name -> manifest
         let mut packages = HashMap::<String, PathBuf>::new();
@@ -72,7 +74,7 @@ impl Workspacify {
     }
 }
 
-fn manifest_iter(workspace: &Path) -> impl Iterator<Item = PathBuf> {
+pub(crate) fn manifest_iter(workspace: &Path) -> impl Iterator<Item = PathBuf> {
     WalkDir::new(workspace)
         .follow_links(false)
         .into_iter()
@@ -128,7 +130,7 @@ fn update_workspace_members(workspace: &Path, packages: &HashMap<String, PathBuf
         .ok_or_else(|| anyhow!("`workspace` is not a table"))?
         .insert("members", value(members));
 
-    fs::write(&manifest, toml.to_string()).context("Failed to write workspace manifest")
+    atomic_write::write(&manifest, toml.to_string()).context("Failed to write workspace manifest")
 }
 
 fn rewrite_manifest(path: &Path, packages: &HashMap<String, PathBuf>) -> Result<()> {
@@ -141,7 +143,7 @@ fn rewrite_manifest(path: &Path, packages: &HashMap<String, PathBuf>) -> Result<
         .filter_map(|dep| dep.1.as_inline_table_mut().map(|v| (dep.0, v)))
         .try_for_each(|dep| handle_dep((dep.0, dep.1, path), packages))?;
 
-    fs::write(path, toml.to_string())
+    atomic_write::write(path, toml.to_string())
         .with_context(|| anyhow!("Failed to write manifest to {}", path.display()))
 }
 
@@ -183,7 +185,7 @@ fn handle_dep(
     Ok(())
 }
 
-fn read_toml(path: &Path, create: bool) -> Result<Document> {
+pub(crate) fn read_toml(path: &Path, create: bool) -> Result<Document> {
     let mut content = String::new();
     OpenOptions::new()
         .read(true)